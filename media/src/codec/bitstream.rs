@@ -1,7 +1,19 @@
+/// 读取比特流失败的原因.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitStreamError {
+    /// 数据不足, 读取越过了结尾
+    Eof,
+    /// 指数哥伦布码的前导零超过 31 个, 值超出 u32
+    InvalidExpGolomb,
+    /// 语法元素超出标准允许的范围
+    OutOfRange(&'static str),
+}
+
 /// 比特流，处理一次，向前流动，不能回退。
+#[derive(Clone)]
 pub struct BitStream{
     bs: bytes::Bytes,
-    cursor: usize,    // 要读取的要读取的字节所在索引 
+    cursor: usize,    // 要读取的要读取的字节所在索引
     bits_left: u8, // 当前读取的字节剩余位数。
 }
 
@@ -14,50 +26,103 @@ impl BitStream {
         }
     }
 
-    pub fn read_byte(&self) -> u8{
-        *self.bs.get(self.cursor).unwrap()
+    /// 从 NAL 单元创建, 跳过 `header_len` 字节的 NAL 头并去掉防竞争字节.
+    pub fn from_nalu(nalu: &[u8], header_len: usize) -> Self {
+        Self::new(bytes::Bytes::from(super::rbsp::nal_to_rbsp(nalu.get(header_len..).unwrap_or_default())))
+    }
+
+    /// 当前字节, 不移动位置.
+    pub fn read_byte(&self) -> Result<u8, BitStreamError> {
+        self.bs.get(self.cursor).copied().ok_or(BitStreamError::Eof)
+    }
+
+    /// 剩余的比特数.
+    pub fn bits_remaining(&self) -> usize {
+        (self.bs.len().saturating_sub(self.cursor) * 8).saturating_sub(8 - self.bits_left as usize)
     }
 
-    pub fn read_u1(&mut self) -> u8{
+    pub fn is_byte_aligned(&self) -> bool {
+        self.bits_left == 8
+    }
+
+    /// 跳到下一个字节的开头, 已对齐时不动.
+    pub fn byte_align(&mut self) {
+        if !self.is_byte_aligned() {
+            self.cursor += 1;
+            self.bits_left = 8;
+        }
+    }
+
+    /// rbsp_trailing_bits 之前是否还有数据 (H.264 7.2): 最后一个为 1 的比特是 rbsp_stop_one_bit.
+    pub fn more_rbsp_data(&self) -> bool {
+        let Some(last) = self.bs.iter().rposition(|&byte| byte != 0) else {
+            return false;
+        };
+        let stop_bit = last * 8 + 7 - self.bs[last].trailing_zeros() as usize;
+        let position = self.cursor * 8 + (8 - self.bits_left as usize);
+        position < stop_bit
+    }
+
+    pub fn read_u1(&mut self) -> Result<u8, BitStreamError> {
+        let byte = self.read_byte()?;
         self.bits_left -= 1;
-        let res = (self.read_byte() >> self.bits_left) & 0x01;
+        let res = (byte >> self.bits_left) & 0x01;
 
-        // println!("byte: {:#x}", self.read_byte());
         if self.bits_left == 0 {
             self.cursor += 1;
             self.bits_left = 8;
         }
-        res
-    } 
+        Ok(res)
+    }
 
-    pub fn read_u(&mut self, n: u8) -> usize{
+    /// 读取 n (最多 64) 位, 数据不足时不移动位置.
+    pub fn read_u(&mut self, n: u8) -> Result<usize, BitStreamError> {
+        debug_assert!(n <= 64);
+        if self.bits_remaining() < n as usize {
+            return Err(BitStreamError::Eof);
+        }
         let mut res: usize = 0;
         for _ in 0..n{
             res <<= 1;
-            let cur_bit = self.read_u1() as usize;
+            let cur_bit = self.read_u1()? as usize;
             res |= cur_bit;
         }
-        res
+        Ok(res)
     }
 
-    pub fn read_ue(&mut self) -> u32 {
+    /// 读取 n 位但不移动位置.
+    pub fn peek_u(&self, n: u8) -> Result<usize, BitStreamError> {
+        self.clone().read_u(n)
+    }
+
+    /// 跳过 n 位.
+    pub fn skip(&mut self, n: usize) -> Result<(), BitStreamError> {
+        if self.bits_remaining() < n {
+            return Err(BitStreamError::Eof);
+        }
+        let position = self.cursor * 8 + (8 - self.bits_left as usize) + n;
+        self.cursor = position / 8;
+        self.bits_left = 8 - (position % 8) as u8;
+        Ok(())
+    }
+
+    pub fn read_ue(&mut self) -> Result<u32, BitStreamError> {
         let mut zeros: u8 = 0;
-        while self.read_u1() == 0 {
+        while self.read_u1()? == 0 {
             zeros += 1;
+            if zeros > 31 {
+                return Err(BitStreamError::InvalidExpGolomb);
+            }
         }
 
-        debug_assert!(zeros <= 32);
-        let res: u32 = (1 << zeros | self.read_u(zeros) as u32) - 1;
-        res
+        let res = ((1u64 << zeros) - 1 + self.read_u(zeros)? as u64) as u32;
+        Ok(res)
     }
 
     /// se = (-1)^(k + 1)*Ceil(K / 2)
-    pub fn read_se(&mut self) -> i32 {
-        let ue: u32 = self.read_ue();
-        println!("ue: {}", ue);
-        let negative = if ue & 0x1 == 1 {1} else {-1};
-        let res = (ue as i64 + 1 >> 1) as i32 * negative;
-        res    
+    pub fn read_se(&mut self) -> Result<i32, BitStreamError> {
+        let ue = self.read_ue()? as i64;
+        let res = if ue & 0x1 == 1 { (ue + 1) >> 1 } else { -(ue >> 1) };
+        Ok(res as i32)
     }
 }
-
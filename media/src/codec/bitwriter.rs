@@ -0,0 +1,91 @@
+/// 比特流写入, [`super::bitstream::BitStream`] 的逆过程, 高位在前.
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    data: Vec<u8>,
+    bits_used: u8, // 最后一个字节已经写入的位数, 0 表示已对齐
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_u1(&mut self, bit: u8) {
+        if self.bits_used == 0 {
+            self.data.push(0);
+        }
+        if bit & 0x01 == 1 {
+            *self.data.last_mut().unwrap() |= 0x80 >> self.bits_used;
+        }
+        self.bits_used = (self.bits_used + 1) % 8;
+    }
+
+    /// 写入 `value` 的低 n (最多 64) 位.
+    pub fn write_u(&mut self, n: u8, value: u64) {
+        debug_assert!(n <= 64);
+        for i in (0..n).rev() {
+            self.write_u1((value >> i) as u8 & 0x01);
+        }
+    }
+
+    pub fn write_ue(&mut self, value: u32) {
+        let code = value as u64 + 1;
+        let len = 64 - code.leading_zeros() as u8;
+        self.write_u(len - 1, 0);
+        self.write_u(len, code);
+    }
+
+    /// 正数映射为奇数, 负数映射为偶数.
+    pub fn write_se(&mut self, value: i32) {
+        let ue = if value > 0 { value as i64 * 2 - 1 } else { -(value as i64) * 2 };
+        self.write_ue(ue as u32);
+    }
+
+    pub fn is_byte_aligned(&self) -> bool {
+        self.bits_used == 0
+    }
+
+    /// rbsp_trailing_bits: rbsp_stop_one_bit 后补零到字节对齐.
+    pub fn write_rbsp_trailing_bits(&mut self) {
+        self.write_u1(1);
+        while !self.is_byte_aligned() {
+            self.write_u1(0);
+        }
+    }
+
+    /// 已写入的字节, 未对齐时最后一个字节的低位补零.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::bitstream::BitStream;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut writer = BitWriter::new();
+        writer.write_u(8, 0x10);
+        writer.write_u1(1);
+        writer.write_ue(5);
+        writer.write_se(-6);
+        writer.write_se(7);
+        writer.write_ue(u32::MAX - 1);
+        writer.write_u(32, 0xdead_beef);
+        writer.write_rbsp_trailing_bits();
+        let bytes = writer.into_bytes();
+        assert_eq!(&bytes[..2], &[0x10, 0b1001_1000]);
+
+        let mut bs = BitStream::new(bytes::Bytes::from(bytes));
+        assert_eq!(bs.read_u(8), Ok(0x10));
+        assert_eq!(bs.read_u1(), Ok(1));
+        assert_eq!(bs.read_ue(), Ok(5));
+        assert_eq!(bs.read_se(), Ok(-6));
+        assert_eq!(bs.read_se(), Ok(7));
+        assert_eq!(bs.read_ue(), Ok(u32::MAX - 1));
+        assert_eq!(bs.read_u(32), Ok(0xdead_beef));
+        assert!(!bs.more_rbsp_data());
+    }
+}
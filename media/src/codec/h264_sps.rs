@@ -1,6 +1,6 @@
-use bitstream::bitstream::BitStream;
+use super::{bitstream::{BitStream, BitStreamError}, bitwriter::BitWriter, rbsp};
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Sps {
     pub profile_idc: u8, //u(8)
     flag: u8,
@@ -13,6 +13,7 @@ pub struct Sps {
     qpprime_y_zero_transform_bypass_flag: u8, // u(1)
     seq_scaling_matrix_present_flag: u8, // u(1)
     seq_scaling_list_present_flag: Vec<u8>, // u(1)
+    seq_scaling_lists: Vec<Vec<i32>>, // 每个 scaling_list() 中的 delta_scale, se(v)
     log2_max_frame_num_minus4: u32, // ue(v)
     pic_order_cnt_type: u32,        // ue(v)
     log2_max_pic_order_cnt_lsb_minus4: u32, // ue(v)
@@ -34,30 +35,199 @@ pub struct Sps {
     frame_crop_top_offset: u32,    // ue(v)
     frame_crop_bottom_offset: u32, // ue(v)
     vui_parameters_present_flag: u8, // u(1)
+    pub vui: Option<Vui>,
 }
 
-impl From<&mut BitStream> for Sps {
-    fn from(bs: &mut BitStream) -> Self{
-        let mut sps = Sps::default();
+/// 视频可用性信息 (H.264 附录 E.1.1).
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Vui {
+    pub aspect_ratio_idc: Option<u8>, // u(8), 255 时为 sar_width:sar_height
+    pub sar_width: u16,               // u(16)
+    pub sar_height: u16,              // u(16)
+    pub overscan_appropriate_flag: Option<u8>, // u(1)
+    pub video_signal_type_present_flag: u8, // u(1)
+    pub video_format: u8,             // u(3), 默认 5 (未指定)
+    pub video_full_range_flag: u8,    // u(1)
+    pub colour_description: Option<ColourDescription>,
+    pub chroma_sample_loc_type: Option<(u32, u32)>, // ue(v), 顶场和底场
+    pub timing_info: Option<TimingInfo>,
+    pub nal_hrd_parameters: Option<HrdParameters>,
+    pub vcl_hrd_parameters: Option<HrdParameters>,
+    pub low_delay_hrd_flag: u8,       // u(1)
+    pub pic_struct_present_flag: u8,  // u(1)
+    pub bitstream_restriction: Option<BitstreamRestriction>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct ColourDescription {
+    pub colour_primaries: u8,         // u(8)
+    pub transfer_characteristics: u8, // u(8)
+    pub matrix_coefficients: u8,      // u(8)
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct TimingInfo {
+    pub num_units_in_tick: u32,  // u(32)
+    pub time_scale: u32,         // u(32)
+    pub fixed_frame_rate_flag: u8, // u(1)
+}
+
+/// E.1.2, 只保留各 CPB 的码率和缓冲区大小.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct HrdParameters {
+    pub bit_rate_scale: u8,           // u(4)
+    pub cpb_size_scale: u8,           // u(4)
+    pub bit_rate_value_minus1: Vec<u32>, // ue(v)
+    pub cpb_size_value_minus1: Vec<u32>, // ue(v)
+    pub cbr_flag: Vec<u8>,            // u(1)
+    pub initial_cpb_removal_delay_length_minus1: u8, // u(5)
+    pub cpb_removal_delay_length_minus1: u8,         // u(5)
+    pub dpb_output_delay_length_minus1: u8,          // u(5)
+    pub time_offset_length: u8,                      // u(5)
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct BitstreamRestriction {
+    pub motion_vectors_over_pic_boundaries_flag: u8, // u(1)
+    pub max_bytes_per_pic_denom: u32,      // ue(v)
+    pub max_bits_per_mb_denom: u32,        // ue(v)
+    pub log2_max_mv_length_horizontal: u32, // ue(v)
+    pub log2_max_mv_length_vertical: u32,  // ue(v)
+    pub max_num_reorder_frames: u32,       // ue(v)
+    pub max_dec_frame_buffering: u32,      // ue(v)
+}
+
+impl TryFrom<&mut BitStream> for HrdParameters {
+    type Error = BitStreamError;
+
+    fn try_from(bs: &mut BitStream) -> Result<Self, Self::Error> {
+        let mut hrd = HrdParameters::default();
+        let cpb_cnt = bs.read_ue()? + 1;
+        hrd.bit_rate_scale = bs.read_u(4)? as u8;
+        hrd.cpb_size_scale = bs.read_u(4)? as u8;
+        for _ in 0..cpb_cnt {
+            hrd.bit_rate_value_minus1.push(bs.read_ue()?);
+            hrd.cpb_size_value_minus1.push(bs.read_ue()?);
+            hrd.cbr_flag.push(bs.read_u1()?);
+        }
+        hrd.initial_cpb_removal_delay_length_minus1 = bs.read_u(5)? as u8;
+        hrd.cpb_removal_delay_length_minus1 = bs.read_u(5)? as u8;
+        hrd.dpb_output_delay_length_minus1 = bs.read_u(5)? as u8;
+        hrd.time_offset_length = bs.read_u(5)? as u8;
+        Ok(hrd)
+    }
+}
+
+impl TryFrom<&mut BitStream> for Vui {
+    type Error = BitStreamError;
+
+    fn try_from(bs: &mut BitStream) -> Result<Self, Self::Error> {
+        let mut vui = Vui { video_format: 5, ..Default::default() };
+
+        if bs.read_u1()? == 1 {
+            let aspect_ratio_idc = bs.read_u(8)? as u8;
+            if aspect_ratio_idc == 255 {
+                vui.sar_width = bs.read_u(16)? as u16;
+                vui.sar_height = bs.read_u(16)? as u16;
+            }
+            vui.aspect_ratio_idc = Some(aspect_ratio_idc);
+        }
+        if bs.read_u1()? == 1 {
+            vui.overscan_appropriate_flag = Some(bs.read_u1()?);
+        }
+        vui.video_signal_type_present_flag = bs.read_u1()?;
+        if vui.video_signal_type_present_flag == 1 {
+            vui.video_format = bs.read_u(3)? as u8;
+            vui.video_full_range_flag = bs.read_u1()?;
+            if bs.read_u1()? == 1 {
+                vui.colour_description = Some(ColourDescription {
+                    colour_primaries: bs.read_u(8)? as u8,
+                    transfer_characteristics: bs.read_u(8)? as u8,
+                    matrix_coefficients: bs.read_u(8)? as u8,
+                });
+            }
+        }
+        if bs.read_u1()? == 1 {
+            vui.chroma_sample_loc_type = Some((bs.read_ue()?, bs.read_ue()?));
+        }
+        if bs.read_u1()? == 1 {
+            vui.timing_info = Some(TimingInfo {
+                num_units_in_tick: bs.read_u(32)? as u32,
+                time_scale: bs.read_u(32)? as u32,
+                fixed_frame_rate_flag: bs.read_u1()?,
+            });
+        }
+        if bs.read_u1()? == 1 {
+            vui.nal_hrd_parameters = Some(HrdParameters::try_from(&mut *bs)?);
+        }
+        if bs.read_u1()? == 1 {
+            vui.vcl_hrd_parameters = Some(HrdParameters::try_from(&mut *bs)?);
+        }
+        if vui.nal_hrd_parameters.is_some() || vui.vcl_hrd_parameters.is_some() {
+            vui.low_delay_hrd_flag = bs.read_u1()?;
+        }
+        vui.pic_struct_present_flag = bs.read_u1()?;
+        if bs.read_u1()? == 1 {
+            vui.bitstream_restriction = Some(BitstreamRestriction {
+                motion_vectors_over_pic_boundaries_flag: bs.read_u1()?,
+                max_bytes_per_pic_denom: bs.read_ue()?,
+                max_bits_per_mb_denom: bs.read_ue()?,
+                log2_max_mv_length_horizontal: bs.read_ue()?,
+                log2_max_mv_length_vertical: bs.read_ue()?,
+                max_num_reorder_frames: bs.read_ue()?,
+                max_dec_frame_buffering: bs.read_ue()?,
+            });
+        }
+        Ok(vui)
+    }
+}
+
+/// 读取 scaling_list() 中的 delta_scale (7.3.2.1.1.1), 不展开为矩阵.
+fn read_scaling_list(bs: &mut BitStream, size: usize) -> Result<Vec<i32>, BitStreamError> {
+    let mut deltas = Vec::new();
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta_scale = bs.read_se()?;
+            if !(-128..=127).contains(&delta_scale) {
+                return Err(BitStreamError::OutOfRange("delta_scale"));
+            }
+            deltas.push(delta_scale);
+            next_scale = (last_scale + delta_scale + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(deltas)
+}
 
-        sps.profile_idc = bs.read_u(8) as u8;
-        println!("profile_idc: {}", sps.profile_idc);
-        sps.flag = bs.read_u(8) as u8;
-        sps.level_idc = bs.read_u(8) as u8;
-        println!("level_idc: {}", sps.level_idc);
-        sps.seq_parameter_set_id = bs.read_ue();
+impl TryFrom<&mut BitStream> for Sps {
+    type Error = BitStreamError;
+
+    fn try_from(bs: &mut BitStream) -> Result<Self, Self::Error> {
+        let mut sps = Sps {
+            profile_idc: bs.read_u(8)? as u8,
+            flag: bs.read_u(8)? as u8,
+            level_idc: bs.read_u(8)? as u8,
+            seq_parameter_set_id: bs.read_ue()?,
+            // 没有 chroma_format_idc 时为 4:2:0
+            chroma_format_idc: 1,
+            ..Default::default()
+        };
 
         match sps.profile_idc {
             100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 => {
-                sps.chroma_format_idc = bs.read_ue();
+                sps.chroma_format_idc = bs.read_ue()?;
                 if sps.chroma_format_idc == 3 {
-                    sps.separate_colour_plane_flag = bs.read_u1();
+                    sps.separate_colour_plane_flag = bs.read_u1()?;
                 }
-                sps.bit_depth_luma_minus8 = bs.read_ue();
-                sps.bit_depth_chroma_minus8 = bs.read_ue();
+                sps.bit_depth_luma_minus8 = bs.read_ue()?;
+                sps.bit_depth_chroma_minus8 = bs.read_ue()?;
 
-                sps.qpprime_y_zero_transform_bypass_flag = bs.read_u1();
-                sps.seq_scaling_matrix_present_flag = bs.read_u1();
+                sps.qpprime_y_zero_transform_bypass_flag = bs.read_u1()?;
+                sps.seq_scaling_matrix_present_flag = bs.read_u1()?;
 
                 if sps.seq_scaling_matrix_present_flag > 0 {
                     let matrix_dim: usize = if sps.chroma_format_idc != 2 {
@@ -66,78 +236,361 @@ impl From<&mut BitStream> for Sps {
                         12
                     };
 
-                    for _ in 0..matrix_dim {
-                        sps
-                            .seq_scaling_list_present_flag
-                            .push(bs.read_u1());
+                    for i in 0..matrix_dim {
+                        let present = bs.read_u1()?;
+                        let deltas = if present == 1 { read_scaling_list(bs, if i < 6 { 16 } else { 64 })? } else { Vec::new() };
+                        sps.seq_scaling_list_present_flag.push(present);
+                        sps.seq_scaling_lists.push(deltas);
                     }
                 }
             }
             _ => {}
         }
 
-        sps.log2_max_frame_num_minus4 = bs.read_ue();
-        sps.pic_order_cnt_type = bs.read_ue();
+        sps.log2_max_frame_num_minus4 = bs.read_ue()?;
+        sps.pic_order_cnt_type = bs.read_ue()?;
 
         match sps.pic_order_cnt_type {
             0 => {
                 sps.log2_max_pic_order_cnt_lsb_minus4 =
-                    bs.read_ue();
+                    bs.read_ue()?;
             }
             1 => {
-                sps.delta_pic_order_always_zero_flag = bs.read_u1();
-                sps.offset_for_non_ref_pic = bs.read_se();
-                sps.offset_for_top_to_bottom_field = bs.read_se();
+                sps.delta_pic_order_always_zero_flag = bs.read_u1()?;
+                sps.offset_for_non_ref_pic = bs.read_se()?;
+                sps.offset_for_top_to_bottom_field = bs.read_se()?;
                 sps.num_ref_frames_in_pic_order_cnt_cycle =
-                    bs.read_ue();
+                    bs.read_ue()?;
 
-                for i in 0..sps.num_ref_frames_in_pic_order_cnt_cycle as usize {
-                    sps.offset_for_ref_frame[i] = bs.read_se();
+                for _ in 0..sps.num_ref_frames_in_pic_order_cnt_cycle {
+                    sps.offset_for_ref_frame.push(bs.read_se()?);
                 }
             }
             _ => {}
         }
 
-        sps.max_num_ref_frames = bs.read_ue();
-        sps.gaps_in_frame_num_value_allowed_flag = bs.read_u1();
+        sps.max_num_ref_frames = bs.read_ue()?;
+        sps.gaps_in_frame_num_value_allowed_flag = bs.read_u1()?;
 
-        sps.pic_width_in_mbs_minus1 = bs.read_ue();
-        sps.pic_height_in_map_units_minus1 = bs.read_ue();
+        sps.pic_width_in_mbs_minus1 = bs.read_ue()?;
+        sps.pic_height_in_map_units_minus1 = bs.read_ue()?;
 
-        sps.frame_mbs_only_flag = bs.read_u1();
+        sps.frame_mbs_only_flag = bs.read_u1()?;
 
         if sps.frame_mbs_only_flag == 0 {
-            sps.mb_adaptive_frame_field_flag = bs.read_u1();
+            sps.mb_adaptive_frame_field_flag = bs.read_u1()?;
         }
-        sps.direct_8x8_inference_flag = bs.read_u1();
-        sps.frame_cropping_flag = bs.read_u1();
+        sps.direct_8x8_inference_flag = bs.read_u1()?;
+        sps.frame_cropping_flag = bs.read_u1()?;
 
         if sps.frame_cropping_flag > 0 {
-            sps.frame_crop_left_offset = bs.read_ue();
-            sps.frame_crop_right_offset = bs.read_ue();
-            sps.frame_crop_top_offset = bs.read_ue();
-            sps.frame_crop_bottom_offset = bs.read_ue();
+            sps.frame_crop_left_offset = bs.read_ue()?;
+            sps.frame_crop_right_offset = bs.read_ue()?;
+            sps.frame_crop_top_offset = bs.read_ue()?;
+            sps.frame_crop_bottom_offset = bs.read_ue()?;
         }
 
-        sps.vui_parameters_present_flag = bs.read_u1();
+        sps.vui_parameters_present_flag = bs.read_u1()?;
+        if sps.vui_parameters_present_flag == 1 {
+            sps.vui = Some(Vui::try_from(&mut *bs)?);
+        }
+
+        Ok(sps)
+    }
+}
 
-        sps
+impl HrdParameters {
+    fn write(&self, w: &mut BitWriter) {
+        w.write_ue(self.bit_rate_value_minus1.len().saturating_sub(1) as u32);
+        w.write_u(4, self.bit_rate_scale as u64);
+        w.write_u(4, self.cpb_size_scale as u64);
+        for i in 0..self.bit_rate_value_minus1.len() {
+            w.write_ue(self.bit_rate_value_minus1[i]);
+            w.write_ue(self.cpb_size_value_minus1[i]);
+            w.write_u1(self.cbr_flag[i]);
+        }
+        w.write_u(5, self.initial_cpb_removal_delay_length_minus1 as u64);
+        w.write_u(5, self.cpb_removal_delay_length_minus1 as u64);
+        w.write_u(5, self.dpb_output_delay_length_minus1 as u64);
+        w.write_u(5, self.time_offset_length as u64);
+    }
+}
+
+impl Vui {
+    fn write(&self, w: &mut BitWriter) {
+        w.write_u1(self.aspect_ratio_idc.is_some() as u8);
+        if let Some(aspect_ratio_idc) = self.aspect_ratio_idc {
+            w.write_u(8, aspect_ratio_idc as u64);
+            if aspect_ratio_idc == 255 {
+                w.write_u(16, self.sar_width as u64);
+                w.write_u(16, self.sar_height as u64);
+            }
+        }
+        w.write_u1(self.overscan_appropriate_flag.is_some() as u8);
+        if let Some(flag) = self.overscan_appropriate_flag {
+            w.write_u1(flag);
+        }
+        // 修改了视频格式或色彩描述时也要输出
+        let video_signal_type = self.video_signal_type_present_flag == 1
+            || self.video_format != 5
+            || self.video_full_range_flag != 0
+            || self.colour_description.is_some();
+        w.write_u1(video_signal_type as u8);
+        if video_signal_type {
+            w.write_u(3, self.video_format as u64);
+            w.write_u1(self.video_full_range_flag);
+            w.write_u1(self.colour_description.is_some() as u8);
+            if let Some(colour) = self.colour_description {
+                w.write_u(8, colour.colour_primaries as u64);
+                w.write_u(8, colour.transfer_characteristics as u64);
+                w.write_u(8, colour.matrix_coefficients as u64);
+            }
+        }
+        w.write_u1(self.chroma_sample_loc_type.is_some() as u8);
+        if let Some((top, bottom)) = self.chroma_sample_loc_type {
+            w.write_ue(top);
+            w.write_ue(bottom);
+        }
+        w.write_u1(self.timing_info.is_some() as u8);
+        if let Some(timing) = self.timing_info {
+            w.write_u(32, timing.num_units_in_tick as u64);
+            w.write_u(32, timing.time_scale as u64);
+            w.write_u1(timing.fixed_frame_rate_flag);
+        }
+        for hrd in [&self.nal_hrd_parameters, &self.vcl_hrd_parameters] {
+            w.write_u1(hrd.is_some() as u8);
+            if let Some(hrd) = hrd {
+                hrd.write(w);
+            }
+        }
+        if self.nal_hrd_parameters.is_some() || self.vcl_hrd_parameters.is_some() {
+            w.write_u1(self.low_delay_hrd_flag);
+        }
+        w.write_u1(self.pic_struct_present_flag);
+        w.write_u1(self.bitstream_restriction.is_some() as u8);
+        if let Some(restriction) = self.bitstream_restriction {
+            w.write_u1(restriction.motion_vectors_over_pic_boundaries_flag);
+            w.write_ue(restriction.max_bytes_per_pic_denom);
+            w.write_ue(restriction.max_bits_per_mb_denom);
+            w.write_ue(restriction.log2_max_mv_length_horizontal);
+            w.write_ue(restriction.log2_max_mv_length_vertical);
+            w.write_ue(restriction.max_num_reorder_frames);
+            w.write_ue(restriction.max_dec_frame_buffering);
+        }
     }
 }
 
 impl Sps {
-    //! 
+    /// 从完整的 SPS NAL 单元 (含 1 字节头, 可能带防竞争字节) 解析.
+    pub fn parse(nalu: &[u8]) -> Result<Self, BitStreamError> {
+        Sps::try_from(&mut BitStream::from_nalu(nalu, 1))
+    }
+
+    /// 序列化为 RBSP, 含 rbsp_trailing_bits, 与 `try_from` 对称.
+    pub fn to_rbsp(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_u(8, self.profile_idc as u64);
+        w.write_u(8, self.flag as u64);
+        w.write_u(8, self.level_idc as u64);
+        w.write_ue(self.seq_parameter_set_id);
+        if matches!(self.profile_idc, 100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128) {
+            w.write_ue(self.chroma_format_idc);
+            if self.chroma_format_idc == 3 {
+                w.write_u1(self.separate_colour_plane_flag);
+            }
+            w.write_ue(self.bit_depth_luma_minus8);
+            w.write_ue(self.bit_depth_chroma_minus8);
+            w.write_u1(self.qpprime_y_zero_transform_bypass_flag);
+            w.write_u1(self.seq_scaling_matrix_present_flag);
+            if self.seq_scaling_matrix_present_flag > 0 {
+                for (present, deltas) in self.seq_scaling_list_present_flag.iter().zip(&self.seq_scaling_lists) {
+                    w.write_u1(*present);
+                    for delta in deltas {
+                        w.write_se(*delta);
+                    }
+                }
+            }
+        }
+
+        w.write_ue(self.log2_max_frame_num_minus4);
+        w.write_ue(self.pic_order_cnt_type);
+        match self.pic_order_cnt_type {
+            0 => w.write_ue(self.log2_max_pic_order_cnt_lsb_minus4),
+            1 => {
+                w.write_u1(self.delta_pic_order_always_zero_flag);
+                w.write_se(self.offset_for_non_ref_pic);
+                w.write_se(self.offset_for_top_to_bottom_field);
+                w.write_ue(self.offset_for_ref_frame.len() as u32);
+                for offset in &self.offset_for_ref_frame {
+                    w.write_se(*offset);
+                }
+            }
+            _ => {}
+        }
+
+        w.write_ue(self.max_num_ref_frames);
+        w.write_u1(self.gaps_in_frame_num_value_allowed_flag);
+        w.write_ue(self.pic_width_in_mbs_minus1);
+        w.write_ue(self.pic_height_in_map_units_minus1);
+        w.write_u1(self.frame_mbs_only_flag);
+        if self.frame_mbs_only_flag == 0 {
+            w.write_u1(self.mb_adaptive_frame_field_flag);
+        }
+        w.write_u1(self.direct_8x8_inference_flag);
+        w.write_u1(self.frame_cropping_flag);
+        if self.frame_cropping_flag > 0 {
+            w.write_ue(self.frame_crop_left_offset);
+            w.write_ue(self.frame_crop_right_offset);
+            w.write_ue(self.frame_crop_top_offset);
+            w.write_ue(self.frame_crop_bottom_offset);
+        }
+        w.write_u1(self.vui.is_some() as u8);
+        if let Some(vui) = &self.vui {
+            vui.write(&mut w);
+        }
+        w.write_rbsp_trailing_bits();
+        w.into_bytes()
+    }
+
+    /// 加上 NAL 头和防竞争字节.
+    pub fn to_nalu(&self, nal_header: u8) -> Vec<u8> {
+        let mut nalu = vec![nal_header];
+        nalu.extend(rbsp::rbsp_to_nal(&self.to_rbsp()));
+        nalu
+    }
+
+    /// 裁剪后的宽高, 裁剪单位取决于色度格式和场编码 (7.4.2.1.1).
     pub fn parse_width_height(&self) -> (u32, u32) {
-        let width = (self.pic_width_in_mbs_minus1 + 1) * 16
-            - self.frame_crop_left_offset * 2
-            - self.frame_crop_right_offset * 2;
-        let height = ((2 - self.frame_mbs_only_flag as u32)
-            * (self.pic_height_in_map_units_minus1 + 1)
-            * 16)
-            - (self.frame_crop_top_offset * 2)
-            - (self.frame_crop_bottom_offset * 2);
-
-        // log::trace!("parsed sps data: {:?}", self);
-        (width, height)
-    }  
+        let (sub_width_c, sub_height_c) = match (self.chroma_format_idc, self.separate_colour_plane_flag) {
+            (1, _) => (2, 2),
+            (2, _) => (2, 1),
+            _ => (1, 1), // 单色或独立编码的 4:4:4 没有色度子采样
+        };
+        let crop_unit_x = if self.chroma_format_idc == 0 { 1 } else { sub_width_c };
+        let crop_unit_y = (2 - self.frame_mbs_only_flag as u64) * if self.chroma_format_idc == 0 { 1 } else { sub_height_c };
+
+        // 损坏的 SPS 中的值可能溢出, 用 u64 计算
+        let width = (self.pic_width_in_mbs_minus1 as u64 + 1) * 16;
+        let height = (2 - self.frame_mbs_only_flag as u64) * (self.pic_height_in_map_units_minus1 as u64 + 1) * 16;
+        let crop_x = (self.frame_crop_left_offset as u64 + self.frame_crop_right_offset as u64) * crop_unit_x;
+        let crop_y = (self.frame_crop_top_offset as u64 + self.frame_crop_bottom_offset as u64) * crop_unit_y;
+        let clamp = |value: u64| value.min(u32::MAX as u64) as u32;
+        (clamp(width.saturating_sub(crop_x)), clamp(height.saturating_sub(crop_y)))
+    }
+
+    pub fn chroma_format_idc(&self) -> u32 {
+        self.chroma_format_idc
+    }
+
+    pub fn bit_depth(&self) -> (u32, u32) {
+        (self.bit_depth_luma_minus8.saturating_add(8), self.bit_depth_chroma_minus8.saturating_add(8))
+    }
+
+    /// VUI timing_info 给出的帧率; 一帧为两个 tick (E.2.1).
+    pub fn frame_rate(&self) -> Option<f64> {
+        let timing = self.vui.as_ref()?.timing_info?;
+        if timing.num_units_in_tick == 0 || timing.time_scale == 0 {
+            return None;
+        }
+        Some(timing.time_scale as f64 / (2.0 * timing.num_units_in_tick as f64))
+    }
+}
+
+/// 按挂载点修正 SPS, 为空的项保持不变.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpsRewrite {
+    pub level_idc: Option<u8>,
+    /// 写入 VUI timing_info, 并标记为固定帧率
+    pub frame_rate: Option<u32>,
+    /// 减小播放器的重排序缓存, 降低延迟
+    pub max_num_reorder_frames: Option<u32>,
+}
+
+impl SpsRewrite {
+    pub fn is_empty(&self) -> bool {
+        *self == SpsRewrite::default()
+    }
+
+    pub fn apply(&self, sps: &mut Sps) {
+        if let Some(level_idc) = self.level_idc {
+            sps.level_idc = level_idc;
+        }
+        // time_scale 为帧率的两倍, 超出 u32 时不改写
+        if let Some(time_scale) = self.frame_rate.filter(|fps| *fps > 0).and_then(|fps| fps.checked_mul(2)) {
+            let vui = sps.vui.get_or_insert_with(|| Vui { video_format: 5, ..Default::default() });
+            vui.timing_info = Some(TimingInfo { num_units_in_tick: 1, time_scale, fixed_frame_rate_flag: 1 });
+        }
+        if let Some(reorder) = self.max_num_reorder_frames {
+            let max_num_ref_frames = sps.max_num_ref_frames;
+            let vui = sps.vui.get_or_insert_with(|| Vui { video_format: 5, ..Default::default() });
+            // 没有 bitstream_restriction 时各项取 E.2.1 的推断值
+            let restriction = vui.bitstream_restriction.get_or_insert(BitstreamRestriction {
+                motion_vectors_over_pic_boundaries_flag: 1,
+                max_bytes_per_pic_denom: 2,
+                max_bits_per_mb_denom: 1,
+                log2_max_mv_length_horizontal: 16,
+                log2_max_mv_length_vertical: 16,
+                max_num_reorder_frames: reorder,
+                max_dec_frame_buffering: max_num_ref_frames,
+            });
+            restriction.max_num_reorder_frames = reorder;
+            restriction.max_dec_frame_buffering = restriction.max_dec_frame_buffering.max(reorder).max(max_num_ref_frames);
+        }
+    }
+
+    /// 改写一个 SPS NAL 单元, 保留原来的 NAL 头.
+    pub fn rewrite(&self, nalu: &[u8]) -> Result<Vec<u8>, BitStreamError> {
+        if self.is_empty() {
+            return Ok(nalu.to_vec());
+        }
+        let mut sps = Sps::parse(nalu)?;
+        self.apply(&mut sps);
+        Ok(sps.to_nalu(nalu[0]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_data::H264_SPS;
+
+    #[test]
+    fn test_parse_vui() {
+        let nalu = H264_SPS;
+        let sps = Sps::parse(&nalu).unwrap();
+        assert_eq!((sps.profile_idc, sps.level_idc), (100, 40));
+        assert_eq!(sps.parse_width_height(), (1920, 1080));
+        let vui = sps.vui.as_ref().unwrap();
+        assert_eq!(vui.timing_info, Some(TimingInfo { num_units_in_tick: 1, time_scale: 50, fixed_frame_rate_flag: 0 }));
+        assert_eq!(sps.frame_rate(), Some(25.0));
+    }
+
+    #[test]
+    fn test_serialize_and_rewrite() {
+        let nalu = H264_SPS;
+        let sps = Sps::parse(&nalu).unwrap();
+        assert_eq!(sps.to_nalu(0x67), nalu);
+        assert_eq!(SpsRewrite::default().rewrite(&nalu).unwrap(), nalu);
+
+        let rewrite = SpsRewrite { level_idc: Some(41), frame_rate: Some(30), max_num_reorder_frames: Some(0) };
+        let rewritten = Sps::parse(&rewrite.rewrite(&nalu).unwrap()).unwrap();
+        assert_eq!(rewritten.level_idc, 41);
+        assert_eq!(rewritten.frame_rate(), Some(30.0));
+        assert_eq!(rewritten.parse_width_height(), (1920, 1080));
+        let restriction = rewritten.vui.as_ref().unwrap().bitstream_restriction.unwrap();
+        assert_eq!(restriction.max_num_reorder_frames, 0);
+        assert!(restriction.max_dec_frame_buffering >= rewritten.max_num_ref_frames);
+
+        // 没有 VUI 的 SPS: 补上 timing_info, 其余字段不变
+        let mut plain = sps.clone();
+        plain.vui = None;
+        let rewritten = Sps::parse(&SpsRewrite { frame_rate: Some(25), ..Default::default() }.rewrite(&plain.to_nalu(0x67)).unwrap()).unwrap();
+        assert_eq!(rewritten.frame_rate(), Some(25.0));
+        assert_eq!(rewritten.vui.as_ref().unwrap().video_format, 5);
+        assert_eq!(Sps { vui: None, vui_parameters_present_flag: 0, ..rewritten }, Sps { vui_parameters_present_flag: 0, ..plain });
+
+        // time_scale 溢出时不改写 timing_info
+        let rewritten = Sps::parse(&SpsRewrite { frame_rate: Some(u32::MAX), ..Default::default() }.rewrite(&nalu).unwrap()).unwrap();
+        assert_eq!(rewritten.frame_rate(), Some(25.0));
+    }
 }
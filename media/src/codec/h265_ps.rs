@@ -0,0 +1,505 @@
+use super::bitstream::{BitStream, BitStreamError};
+
+const NAL_HEADER_SIZE: usize = 2;
+/// num_short_term_ref_pic_sets 的上限 (7.4.3.2.1).
+const MAX_SHORT_TERM_REF_PIC_SETS: u32 = 64;
+
+/// profile_tier_level() 中 general 部分 (H.265 7.3.3), 子层的信息只跳过.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ProfileTierLevel {
+    pub general_profile_space: u8,  // u(2)
+    pub general_tier_flag: u8,      // u(1)
+    pub general_profile_idc: u8,    // u(5)
+    pub general_profile_compatibility_flags: u32, // u(32)
+    pub general_constraint_indicator_flags: u64,  // u(48), 含 progressive/interlaced 等 4 个标志
+    pub general_level_idc: u8,      // u(8)
+}
+
+impl ProfileTierLevel {
+    fn parse(bs: &mut BitStream, max_sub_layers_minus1: u8) -> Result<Self, BitStreamError> {
+        let mut ptl = ProfileTierLevel {
+            general_profile_space: bs.read_u(2)? as u8,
+            general_tier_flag: bs.read_u1()?,
+            general_profile_idc: bs.read_u(5)? as u8,
+            general_profile_compatibility_flags: bs.read_u(32)? as u32,
+            general_constraint_indicator_flags: bs.read_u(48)? as u64,
+            ..Default::default()
+        };
+        ptl.general_level_idc = bs.read_u(8)? as u8;
+
+        let sub_layers = max_sub_layers_minus1 as usize;
+        let mut present = Vec::with_capacity(sub_layers);
+        for _ in 0..sub_layers {
+            present.push((bs.read_u1()?, bs.read_u1()?));
+        }
+        if sub_layers > 0 {
+            for _ in sub_layers..8 {
+                bs.skip(2)?; // reserved_zero_2bits
+            }
+        }
+        for (profile_present, level_present) in present {
+            if profile_present == 1 {
+                bs.skip(88)?;
+            }
+            if level_present == 1 {
+                bs.skip(8)?;
+            }
+        }
+        Ok(ptl)
+    }
+}
+
+/// 时序信息, 帧率为 time_scale / num_units_in_tick.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct TimingInfo {
+    pub num_units_in_tick: u32, // u(32)
+    pub time_scale: u32,        // u(32)
+    pub num_ticks_poc_diff_one_minus1: Option<u32>, // ue(v), poc_proportional_to_timing_flag 为 1 时存在
+}
+
+impl TimingInfo {
+    fn parse(bs: &mut BitStream) -> Result<Self, BitStreamError> {
+        let num_units_in_tick = bs.read_u(32)? as u32;
+        let time_scale = bs.read_u(32)? as u32;
+        let num_ticks_poc_diff_one_minus1 = if bs.read_u1()? == 1 { Some(bs.read_ue()?) } else { None };
+        Ok(Self { num_units_in_tick, time_scale, num_ticks_poc_diff_one_minus1 })
+    }
+
+    pub fn frame_rate(&self) -> Option<f64> {
+        if self.num_units_in_tick == 0 || self.time_scale == 0 {
+            return None;
+        }
+        Some(self.time_scale as f64 / self.num_units_in_tick as f64)
+    }
+}
+
+/// 视频参数集 (7.3.2.1), 解析到 vps_timing_info 为止.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Vps {
+    pub vps_video_parameter_set_id: u8, // u(4)
+    pub vps_max_layers_minus1: u8,      // u(6)
+    pub vps_max_sub_layers_minus1: u8,  // u(3)
+    pub vps_temporal_id_nesting_flag: u8, // u(1)
+    pub profile_tier_level: ProfileTierLevel,
+    pub vps_max_dec_pic_buffering_minus1: Vec<u32>, // ue(v)
+    pub vps_max_num_reorder_pics: Vec<u32>,         // ue(v)
+    pub vps_max_latency_increase_plus1: Vec<u32>,   // ue(v)
+    pub vps_max_layer_id: u8,           // u(6)
+    pub vps_num_layer_sets_minus1: u32, // ue(v)
+    pub timing_info: Option<TimingInfo>,
+}
+
+impl Vps {
+    pub fn parse(nalu: &[u8]) -> Result<Self, BitStreamError> {
+        let bs = &mut BitStream::from_nalu(nalu, NAL_HEADER_SIZE);
+        let mut vps = Vps { vps_video_parameter_set_id: bs.read_u(4)? as u8, ..Default::default() };
+        bs.skip(2)?; // vps_base_layer_internal_flag, vps_base_layer_available_flag
+        vps.vps_max_layers_minus1 = bs.read_u(6)? as u8;
+        vps.vps_max_sub_layers_minus1 = bs.read_u(3)? as u8;
+        vps.vps_temporal_id_nesting_flag = bs.read_u1()?;
+        bs.skip(16)?; // vps_reserved_0xffff_16bits
+        vps.profile_tier_level = ProfileTierLevel::parse(bs, vps.vps_max_sub_layers_minus1)?;
+
+        let first = if bs.read_u1()? == 1 { 0 } else { vps.vps_max_sub_layers_minus1 };
+        for _ in first..=vps.vps_max_sub_layers_minus1 {
+            vps.vps_max_dec_pic_buffering_minus1.push(bs.read_ue()?);
+            vps.vps_max_num_reorder_pics.push(bs.read_ue()?);
+            vps.vps_max_latency_increase_plus1.push(bs.read_ue()?);
+        }
+        vps.vps_max_layer_id = bs.read_u(6)? as u8;
+        vps.vps_num_layer_sets_minus1 = bs.read_ue()?;
+        for _ in 0..vps.vps_num_layer_sets_minus1 {
+            bs.read_u(vps.vps_max_layer_id + 1)?; // layer_id_included_flag
+        }
+        if bs.read_u1()? == 1 {
+            vps.timing_info = Some(TimingInfo::parse(bs)?);
+        }
+        Ok(vps)
+    }
+}
+
+/// E.2.1 中 hrd_parameters 之前的部分, 足够得到帧率和显示信息.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Vui {
+    pub aspect_ratio_idc: Option<u8>, // u(8), 255 时为 sar_width:sar_height
+    pub sar_width: u16,               // u(16)
+    pub sar_height: u16,              // u(16)
+    pub overscan_appropriate_flag: Option<u8>, // u(1)
+    pub video_format: u8,             // u(3), 默认 5 (未指定)
+    pub video_full_range_flag: u8,    // u(1)
+    pub colour_description: Option<(u8, u8, u8)>, // colour_primaries, transfer_characteristics, matrix_coeffs
+    pub chroma_sample_loc_type_top_field: u32,    // ue(v)
+    pub chroma_sample_loc_type_bottom_field: u32, // ue(v)
+    pub neutral_chroma_indication_flag: u8, // u(1)
+    pub field_seq_flag: u8,                 // u(1)
+    pub frame_field_info_present_flag: u8,  // u(1)
+    pub default_display_window: Option<[u32; 4]>, // ue(v) 左右上下偏移
+    pub timing_info: Option<TimingInfo>,
+    pub hrd_parameters_present_flag: u8,    // u(1)
+}
+
+impl Vui {
+    fn parse(bs: &mut BitStream) -> Result<Self, BitStreamError> {
+        let mut vui = Vui { video_format: 5, ..Default::default() };
+        if bs.read_u1()? == 1 {
+            let aspect_ratio_idc = bs.read_u(8)? as u8;
+            if aspect_ratio_idc == 255 {
+                vui.sar_width = bs.read_u(16)? as u16;
+                vui.sar_height = bs.read_u(16)? as u16;
+            }
+            vui.aspect_ratio_idc = Some(aspect_ratio_idc);
+        }
+        if bs.read_u1()? == 1 {
+            vui.overscan_appropriate_flag = Some(bs.read_u1()?);
+        }
+        if bs.read_u1()? == 1 {
+            vui.video_format = bs.read_u(3)? as u8;
+            vui.video_full_range_flag = bs.read_u1()?;
+            if bs.read_u1()? == 1 {
+                vui.colour_description = Some((bs.read_u(8)? as u8, bs.read_u(8)? as u8, bs.read_u(8)? as u8));
+            }
+        }
+        if bs.read_u1()? == 1 {
+            vui.chroma_sample_loc_type_top_field = bs.read_ue()?;
+            vui.chroma_sample_loc_type_bottom_field = bs.read_ue()?;
+        }
+        vui.neutral_chroma_indication_flag = bs.read_u1()?;
+        vui.field_seq_flag = bs.read_u1()?;
+        vui.frame_field_info_present_flag = bs.read_u1()?;
+        if bs.read_u1()? == 1 {
+            vui.default_display_window = Some([bs.read_ue()?, bs.read_ue()?, bs.read_ue()?, bs.read_ue()?]);
+        }
+        if bs.read_u1()? == 1 {
+            vui.timing_info = Some(TimingInfo::parse(bs)?);
+            vui.hrd_parameters_present_flag = bs.read_u1()?;
+        }
+        Ok(vui)
+    }
+}
+
+/// 序列参数集 (7.3.2.2), 解析到 VUI 的时序信息为止.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Sps {
+    pub sps_video_parameter_set_id: u8, // u(4)
+    pub sps_max_sub_layers_minus1: u8,  // u(3)
+    pub sps_temporal_id_nesting_flag: u8, // u(1)
+    pub profile_tier_level: ProfileTierLevel,
+    pub sps_seq_parameter_set_id: u32,  // ue(v)
+    pub chroma_format_idc: u32,         // ue(v)
+    pub separate_colour_plane_flag: u8, // u(1)
+    pub pic_width_in_luma_samples: u32, // ue(v)
+    pub pic_height_in_luma_samples: u32, // ue(v)
+    pub conformance_window: Option<[u32; 4]>, // ue(v) 左右上下偏移, 以色度采样为单位
+    pub bit_depth_luma_minus8: u32,     // ue(v)
+    pub bit_depth_chroma_minus8: u32,   // ue(v)
+    pub log2_max_pic_order_cnt_lsb_minus4: u32, // ue(v)
+    pub sps_max_dec_pic_buffering_minus1: Vec<u32>, // ue(v)
+    pub sps_max_num_reorder_pics: Vec<u32>,         // ue(v)
+    pub sps_max_latency_increase_plus1: Vec<u32>,   // ue(v)
+    pub log2_min_luma_coding_block_size_minus3: u32,      // ue(v)
+    pub log2_diff_max_min_luma_coding_block_size: u32,    // ue(v)
+    pub log2_min_luma_transform_block_size_minus2: u32,   // ue(v)
+    pub log2_diff_max_min_luma_transform_block_size: u32, // ue(v)
+    pub max_transform_hierarchy_depth_inter: u32, // ue(v)
+    pub max_transform_hierarchy_depth_intra: u32, // ue(v)
+    pub scaling_list_enabled_flag: u8,  // u(1)
+    pub amp_enabled_flag: u8,           // u(1)
+    pub sample_adaptive_offset_enabled_flag: u8, // u(1)
+    pub pcm_enabled_flag: u8,           // u(1)
+    pub num_short_term_ref_pic_sets: u32, // ue(v)
+    pub long_term_ref_pics_present_flag: u8, // u(1)
+    pub sps_temporal_mvp_enabled_flag: u8,   // u(1)
+    pub strong_intra_smoothing_enabled_flag: u8, // u(1)
+    pub vui: Option<Vui>,
+}
+
+impl Sps {
+    pub fn parse(nalu: &[u8]) -> Result<Self, BitStreamError> {
+        let bs = &mut BitStream::from_nalu(nalu, NAL_HEADER_SIZE);
+        let mut sps = Sps { sps_video_parameter_set_id: bs.read_u(4)? as u8, ..Default::default() };
+        sps.sps_max_sub_layers_minus1 = bs.read_u(3)? as u8;
+        sps.sps_temporal_id_nesting_flag = bs.read_u1()?;
+        sps.profile_tier_level = ProfileTierLevel::parse(bs, sps.sps_max_sub_layers_minus1)?;
+        sps.sps_seq_parameter_set_id = bs.read_ue()?;
+        sps.chroma_format_idc = bs.read_ue()?;
+        if sps.chroma_format_idc == 3 {
+            sps.separate_colour_plane_flag = bs.read_u1()?;
+        }
+        sps.pic_width_in_luma_samples = bs.read_ue()?;
+        sps.pic_height_in_luma_samples = bs.read_ue()?;
+        if bs.read_u1()? == 1 {
+            sps.conformance_window = Some([bs.read_ue()?, bs.read_ue()?, bs.read_ue()?, bs.read_ue()?]);
+        }
+        sps.bit_depth_luma_minus8 = bs.read_ue()?;
+        sps.bit_depth_chroma_minus8 = bs.read_ue()?;
+        sps.log2_max_pic_order_cnt_lsb_minus4 = bs.read_ue()?;
+
+        let first = if bs.read_u1()? == 1 { 0 } else { sps.sps_max_sub_layers_minus1 };
+        for _ in first..=sps.sps_max_sub_layers_minus1 {
+            sps.sps_max_dec_pic_buffering_minus1.push(bs.read_ue()?);
+            sps.sps_max_num_reorder_pics.push(bs.read_ue()?);
+            sps.sps_max_latency_increase_plus1.push(bs.read_ue()?);
+        }
+        sps.log2_min_luma_coding_block_size_minus3 = bs.read_ue()?;
+        sps.log2_diff_max_min_luma_coding_block_size = bs.read_ue()?;
+        sps.log2_min_luma_transform_block_size_minus2 = bs.read_ue()?;
+        sps.log2_diff_max_min_luma_transform_block_size = bs.read_ue()?;
+        sps.max_transform_hierarchy_depth_inter = bs.read_ue()?;
+        sps.max_transform_hierarchy_depth_intra = bs.read_ue()?;
+
+        sps.scaling_list_enabled_flag = bs.read_u1()?;
+        if sps.scaling_list_enabled_flag == 1 && bs.read_u1()? == 1 {
+            skip_scaling_list_data(bs)?;
+        }
+        sps.amp_enabled_flag = bs.read_u1()?;
+        sps.sample_adaptive_offset_enabled_flag = bs.read_u1()?;
+        sps.pcm_enabled_flag = bs.read_u1()?;
+        if sps.pcm_enabled_flag == 1 {
+            bs.read_u(8)?; // pcm_sample_bit_depth_luma_minus1, pcm_sample_bit_depth_chroma_minus1
+            bs.read_ue()?;
+            bs.read_ue()?;
+            bs.read_u1()?;
+        }
+
+        sps.num_short_term_ref_pic_sets = bs.read_ue()?;
+        if sps.num_short_term_ref_pic_sets > MAX_SHORT_TERM_REF_PIC_SETS {
+            return Err(BitStreamError::OutOfRange("num_short_term_ref_pic_sets"));
+        }
+        let mut num_delta_pocs = Vec::with_capacity(sps.num_short_term_ref_pic_sets as usize);
+        for idx in 0..sps.num_short_term_ref_pic_sets as usize {
+            let count = skip_st_ref_pic_set(bs, idx, &num_delta_pocs)?;
+            num_delta_pocs.push(count);
+        }
+        sps.long_term_ref_pics_present_flag = bs.read_u1()?;
+        if sps.long_term_ref_pics_present_flag == 1 {
+            if sps.log2_max_pic_order_cnt_lsb_minus4 > 12 {
+                return Err(BitStreamError::OutOfRange("log2_max_pic_order_cnt_lsb_minus4"));
+            }
+            let poc_lsb_bits = sps.log2_max_pic_order_cnt_lsb_minus4 as u8 + 4;
+            for _ in 0..bs.read_ue()? {
+                bs.read_u(poc_lsb_bits + 1)?; // lt_ref_pic_poc_lsb_sps, used_by_curr_pic_lt_sps_flag
+            }
+        }
+        sps.sps_temporal_mvp_enabled_flag = bs.read_u1()?;
+        sps.strong_intra_smoothing_enabled_flag = bs.read_u1()?;
+        if bs.read_u1()? == 1 {
+            sps.vui = Some(Vui::parse(bs)?);
+        }
+        Ok(sps)
+    }
+
+    /// 按一致性窗口裁剪后的宽高.
+    pub fn width_height(&self) -> (u32, u32) {
+        let (sub_width_c, sub_height_c) = match (self.chroma_format_idc, self.separate_colour_plane_flag) {
+            (1, _) => (2, 2),
+            (2, _) => (2, 1),
+            _ => (1, 1),
+        };
+        let [left, right, top, bottom] = self.conformance_window.unwrap_or_default().map(u64::from);
+        let crop = |size: u32, offset: u64| (size as u64).saturating_sub(offset).min(u32::MAX as u64) as u32;
+        (
+            crop(self.pic_width_in_luma_samples, sub_width_c * (left + right)),
+            crop(self.pic_height_in_luma_samples, sub_height_c * (top + bottom)),
+        )
+    }
+
+    pub fn bit_depth(&self) -> (u32, u32) {
+        (self.bit_depth_luma_minus8.saturating_add(8), self.bit_depth_chroma_minus8.saturating_add(8))
+    }
+
+    /// VUI 时序信息给出的帧率.
+    pub fn frame_rate(&self) -> Option<f64> {
+        self.vui.as_ref()?.timing_info?.frame_rate()
+    }
+}
+
+/// 跳过 scaling_list_data() (7.3.4).
+fn skip_scaling_list_data(bs: &mut BitStream) -> Result<(), BitStreamError> {
+    for size_id in 0..4 {
+        let step = if size_id == 3 { 3 } else { 1 };
+        for _ in (0..6).step_by(step) {
+            if bs.read_u1()? == 0 {
+                bs.read_ue()?; // scaling_list_pred_matrix_id_delta
+                continue;
+            }
+            let coef_num = 64.min(1 << (4 + (size_id << 1)));
+            if size_id > 1 {
+                bs.read_se()?; // scaling_list_dc_coef_minus8
+            }
+            for _ in 0..coef_num {
+                bs.read_se()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 跳过 SPS 中的 st_ref_pic_set(idx) (7.3.7), 返回其 NumDeltaPocs.
+fn skip_st_ref_pic_set(bs: &mut BitStream, idx: usize, num_delta_pocs: &[u32]) -> Result<u32, BitStreamError> {
+    if idx != 0 && bs.read_u1()? == 1 {
+        bs.read_u1()?; // delta_rps_sign
+        bs.read_ue()?; // abs_delta_rps_minus1
+        let mut count = 0;
+        for _ in 0..=num_delta_pocs[idx - 1] {
+            let used_by_curr_pic_flag = bs.read_u1()?;
+            let use_delta_flag = if used_by_curr_pic_flag == 1 { 1 } else { bs.read_u1()? };
+            count += use_delta_flag as u32;
+        }
+        return Ok(count);
+    }
+    let num_negative_pics = bs.read_ue()?;
+    let num_positive_pics = bs.read_ue()?;
+    let num_delta_pocs = num_negative_pics.saturating_add(num_positive_pics);
+    for _ in 0..num_delta_pocs {
+        bs.read_ue()?; // delta_poc_sX_minus1
+        bs.read_u1()?; // used_by_curr_pic_sX_flag
+    }
+    Ok(num_delta_pocs)
+}
+
+/// 图像参数集 (7.3.2.3.1), 解析到去块滤波参数为止.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Pps {
+    pub pps_pic_parameter_set_id: u32,  // ue(v)
+    pub pps_seq_parameter_set_id: u32,  // ue(v)
+    pub dependent_slice_segments_enabled_flag: u8, // u(1)
+    pub output_flag_present_flag: u8,   // u(1)
+    pub num_extra_slice_header_bits: u8, // u(3)
+    pub sign_data_hiding_enabled_flag: u8, // u(1)
+    pub cabac_init_present_flag: u8,    // u(1)
+    pub num_ref_idx_l0_default_active_minus1: u32, // ue(v)
+    pub num_ref_idx_l1_default_active_minus1: u32, // ue(v)
+    pub init_qp_minus26: i32,           // se(v)
+    pub constrained_intra_pred_flag: u8, // u(1)
+    pub transform_skip_enabled_flag: u8, // u(1)
+    pub diff_cu_qp_delta_depth: Option<u32>, // ue(v), cu_qp_delta_enabled_flag 为 1 时存在
+    pub pps_cb_qp_offset: i32,          // se(v)
+    pub pps_cr_qp_offset: i32,          // se(v)
+    pub pps_slice_chroma_qp_offsets_present_flag: u8, // u(1)
+    pub weighted_pred_flag: u8,         // u(1)
+    pub weighted_bipred_flag: u8,       // u(1)
+    pub transquant_bypass_enabled_flag: u8, // u(1)
+    pub tiles_enabled_flag: u8,         // u(1)
+    pub entropy_coding_sync_enabled_flag: u8, // u(1)
+    pub num_tile_columns_minus1: u32,   // ue(v)
+    pub num_tile_rows_minus1: u32,      // ue(v)
+    pub pps_loop_filter_across_slices_enabled_flag: u8, // u(1)
+    pub deblocking_filter_control_present_flag: u8,     // u(1)
+    pub pps_deblocking_filter_disabled_flag: u8,        // u(1)
+}
+
+impl Pps {
+    pub fn parse(nalu: &[u8]) -> Result<Self, BitStreamError> {
+        let bs = &mut BitStream::from_nalu(nalu, NAL_HEADER_SIZE);
+        let mut pps = Pps { pps_pic_parameter_set_id: bs.read_ue()?, ..Default::default() };
+        pps.pps_seq_parameter_set_id = bs.read_ue()?;
+        pps.dependent_slice_segments_enabled_flag = bs.read_u1()?;
+        pps.output_flag_present_flag = bs.read_u1()?;
+        pps.num_extra_slice_header_bits = bs.read_u(3)? as u8;
+        pps.sign_data_hiding_enabled_flag = bs.read_u1()?;
+        pps.cabac_init_present_flag = bs.read_u1()?;
+        pps.num_ref_idx_l0_default_active_minus1 = bs.read_ue()?;
+        pps.num_ref_idx_l1_default_active_minus1 = bs.read_ue()?;
+        pps.init_qp_minus26 = bs.read_se()?;
+        pps.constrained_intra_pred_flag = bs.read_u1()?;
+        pps.transform_skip_enabled_flag = bs.read_u1()?;
+        if bs.read_u1()? == 1 {
+            pps.diff_cu_qp_delta_depth = Some(bs.read_ue()?);
+        }
+        pps.pps_cb_qp_offset = bs.read_se()?;
+        pps.pps_cr_qp_offset = bs.read_se()?;
+        pps.pps_slice_chroma_qp_offsets_present_flag = bs.read_u1()?;
+        pps.weighted_pred_flag = bs.read_u1()?;
+        pps.weighted_bipred_flag = bs.read_u1()?;
+        pps.transquant_bypass_enabled_flag = bs.read_u1()?;
+        pps.tiles_enabled_flag = bs.read_u1()?;
+        pps.entropy_coding_sync_enabled_flag = bs.read_u1()?;
+        if pps.tiles_enabled_flag == 1 {
+            pps.num_tile_columns_minus1 = bs.read_ue()?;
+            pps.num_tile_rows_minus1 = bs.read_ue()?;
+            if bs.read_u1()? == 0 {
+                for _ in 0..pps.num_tile_columns_minus1.saturating_add(pps.num_tile_rows_minus1) {
+                    bs.read_ue()?; // column_width_minus1, row_height_minus1
+                }
+            }
+            bs.read_u1()?; // loop_filter_across_tiles_enabled_flag
+        }
+        pps.pps_loop_filter_across_slices_enabled_flag = bs.read_u1()?;
+        pps.deblocking_filter_control_present_flag = bs.read_u1()?;
+        if pps.deblocking_filter_control_present_flag == 1 {
+            bs.read_u1()?; // deblocking_filter_override_enabled_flag
+            pps.pps_deblocking_filter_disabled_flag = bs.read_u1()?;
+        }
+        Ok(pps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_data::{H265_PPS, H265_SPS, H265_VPS};
+
+    #[test]
+    fn test_parse_parameter_sets() {
+        let vps = Vps::parse(&H265_VPS).unwrap();
+        assert_eq!(vps.vps_max_sub_layers_minus1, 0);
+        assert_eq!(vps.profile_tier_level.general_profile_idc, 1);
+        assert_eq!(vps.profile_tier_level.general_level_idc, 120);
+        assert_eq!(vps.vps_max_num_reorder_pics, vec![2]);
+
+        let sps = Sps::parse(&H265_SPS).unwrap();
+        let ptl = &sps.profile_tier_level;
+        assert_eq!((ptl.general_profile_idc, ptl.general_tier_flag, ptl.general_level_idc), (1, 0, 120));
+        // Main profile 同时兼容 Main 10
+        assert_eq!(ptl.general_profile_compatibility_flags, 0x6000_0000);
+        assert_eq!(sps.chroma_format_idc, 1);
+        assert_eq!(sps.bit_depth(), (8, 8));
+        assert_eq!(sps.width_height(), (1920, 1080));
+        assert_eq!(sps.frame_rate(), Some(25.0));
+
+        let pps = Pps::parse(&H265_PPS).unwrap();
+        assert_eq!(pps.pps_seq_parameter_set_id, 0);
+        assert_eq!(pps.diff_cu_qp_delta_depth, Some(1));
+        assert_eq!(pps.entropy_coding_sync_enabled_flag, 1);
+    }
+
+    #[test]
+    fn test_conformance_window() {
+        let sps = Sps {
+            chroma_format_idc: 1,
+            pic_width_in_luma_samples: 1920,
+            pic_height_in_luma_samples: 1088,
+            conformance_window: Some([0, 0, 0, 4]),
+            ..Default::default()
+        };
+        assert_eq!(sps.width_height(), (1920, 1080));
+    }
+
+    #[test]
+    fn test_reject_too_many_ref_pic_sets() {
+        use crate::codec::{bitwriter::BitWriter, rbsp};
+
+        let mut writer = BitWriter::new();
+        writer.write_u(8, 0x01); // sps_video_parameter_set_id, sps_max_sub_layers_minus1, sps_temporal_id_nesting_flag
+        writer.write_u(8, 0x01);
+        writer.write_u(48, 0); // 其余 general profile_tier_level
+        writer.write_u(40, 0);
+        for value in [0, 1, 1920, 1080] {
+            writer.write_ue(value);
+        }
+        writer.write_u1(0); // conformance_window_flag
+        for value in [0, 0, 4] {
+            writer.write_ue(value);
+        }
+        writer.write_u1(1); // sps_sub_layer_ordering_info_present_flag
+        for _ in 0..9 {
+            writer.write_ue(0);
+        }
+        writer.write_u(4, 0); // scaling_list, amp, sao, pcm
+        // 伪造的 SPS 会让预分配的数组超过 16 GB
+        writer.write_ue(u32::MAX - 1);
+        writer.write_rbsp_trailing_bits();
+        let nalu = [&[0x42, 0x01][..], &rbsp::rbsp_to_nal(&writer.into_bytes())].concat();
+        assert_eq!(Sps::parse(&nalu), Err(BitStreamError::OutOfRange("num_short_term_ref_pic_sets")));
+    }
+}
@@ -1,38 +1,84 @@
 pub mod bitstream;
+pub mod bitwriter;
+pub mod h264_sps;
+pub mod h265_ps;
+pub mod nal_format;
 pub mod parse;
+pub mod probe;
+pub mod rbsp;
+#[cfg(test)]
+pub(crate) mod test_data;
  
 
 #[cfg(test)]
 mod tests {
-    use super::bitstream::BitStream;
+    use super::bitstream::{BitStream, BitStreamError};
 
     #[test]
     fn it_works() {
         let bytes = bytes::Bytes::from_static(&[0x10, 0x42, 0x30, 0xd0]);
         let mut bs = BitStream::new(bytes);
-        assert_eq!(bs.read_byte(), 0x10);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 1);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 0);
+        assert_eq!(bs.read_byte().unwrap(), 0x10);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 1);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 0);
         
-        assert_eq!(bs.read_byte(), 0x42);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 1);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 0);
-        assert_eq!(bs.read_u1(), 1);
-        assert_eq!(bs.read_u1(), 0);
-
-        assert_eq!(bs.read_byte(), 0x30);
-        assert_eq!(bs.read_ue(), 0x5);
-
-        assert_eq!(bs.read_se(), -5);
+        assert_eq!(bs.read_byte().unwrap(), 0x42);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 1);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+        assert_eq!(bs.read_u1().unwrap(), 1);
+        assert_eq!(bs.read_u1().unwrap(), 0);
+
+        assert_eq!(bs.read_byte().unwrap(), 0x30);
+        assert_eq!(bs.read_ue().unwrap(), 0x5);
+
+        assert_eq!(bs.read_se().unwrap(), -6); // 0001101 -> ue 12 -> se -6
+    }
+
+    #[test]
+    fn test_bitstream_bounds() {
+        // 数据: 0x5a = 0101 1010, rbsp_stop_one_bit 在 0x80 的最高位
+        let mut bs = BitStream::new(bytes::Bytes::from_static(&[0x5a, 0x80]));
+        assert_eq!(bs.bits_remaining(), 16);
+        assert_eq!(bs.peek_u(4), Ok(0x5));
+        assert_eq!(bs.bits_remaining(), 16);
+        bs.skip(3).unwrap();
+        assert!(!bs.is_byte_aligned());
+        assert_eq!(bs.read_u(2), Ok(0b11));
+        assert!(bs.more_rbsp_data());
+        bs.byte_align();
+        assert_eq!(bs.bits_remaining(), 8);
+        assert!(!bs.more_rbsp_data());
+
+        // 不足时返回错误且不移动位置
+        assert_eq!(bs.read_u(9), Err(BitStreamError::Eof));
+        assert_eq!(bs.skip(9), Err(BitStreamError::Eof));
+        assert_eq!(bs.read_u(8), Ok(0x80));
+        assert_eq!(bs.read_u1(), Err(BitStreamError::Eof));
+        assert_eq!(bs.read_byte(), Err(BitStreamError::Eof));
+
+        // 全零时前导零过多
+        let mut bs = BitStream::new(bytes::Bytes::from_static(&[0; 8]));
+        assert_eq!(bs.read_ue(), Err(BitStreamError::InvalidExpGolomb));
+        // ue 读到一半结束
+        let mut bs = BitStream::new(bytes::Bytes::from_static(&[0x01]));
+        assert_eq!(bs.read_ue(), Err(BitStreamError::Eof));
+    }
+
+    #[test]
+    fn test_truncated_sps() {
+        use super::h264_sps::Sps;
+        let sps = &super::test_data::H264_SPS[..8];
+        assert_eq!(Sps::parse(sps).err(), Some(BitStreamError::Eof));
+        assert_eq!(super::h265_ps::Sps::parse(&[0x42, 0x01, 0x01]).err(), Some(BitStreamError::Eof));
     }
 }
@@ -0,0 +1,258 @@
+//! Annex-B 起始码格式与长度前缀格式 (AVCC/HVCC, ISO/IEC 14496-15) 之间的转换,
+//! 以及 avcC/hvcC 解码器配置记录的生成和解析, 供容器的解复用和复用使用.
+
+use super::{bitstream::BitStreamError, h264_sps, h265_ps, parse::{NalReader, ParameterSet}, rbsp};
+
+const START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+/// 生成配置记录时使用的 NAL 长度字段字节数.
+pub const DEFAULT_LENGTH_SIZE: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormatError {
+    /// 数据在长度字段或 NAL 中间结束
+    Truncated,
+    /// NAL 长度字段只能是 1, 2, 4 字节
+    InvalidLengthSize(u8),
+    /// NAL 超出长度字段能表示的范围
+    NaluTooLarge(usize),
+    /// 配置记录不合法
+    InvalidRecord(&'static str),
+    /// 参数集无法解析
+    InvalidParameterSet(BitStreamError),
+}
+
+impl From<BitStreamError> for FormatError {
+    fn from(e: BitStreamError) -> Self {
+        FormatError::InvalidParameterSet(e)
+    }
+}
+
+/// 解码器配置记录中的参数集和 NAL 长度字段字节数.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecoderConfig {
+    pub parameter_set: ParameterSet,
+    pub length_size: u8,
+}
+
+fn check_length_size(length_size: u8) -> Result<(), FormatError> {
+    match length_size {
+        1 | 2 | 4 => Ok(()),
+        _ => Err(FormatError::InvalidLengthSize(length_size)),
+    }
+}
+
+/// 拆分长度前缀格式的一个样本.
+pub fn split_length_prefixed(mut data: &[u8], length_size: u8) -> Result<Vec<&[u8]>, FormatError> {
+    check_length_size(length_size)?;
+    let length_size = length_size as usize;
+    let mut nalus = Vec::new();
+    while !data.is_empty() {
+        let len = data.get(..length_size).ok_or(FormatError::Truncated)?.iter().fold(0, |len, &byte| len << 8 | byte as usize);
+        let nalu = data.get(length_size..length_size + len).ok_or(FormatError::Truncated)?;
+        nalus.push(nalu);
+        data = &data[length_size + len..];
+    }
+    Ok(nalus)
+}
+
+/// 长度前缀格式转为 Annex-B, 每个 NAL 前加 4 字节起始码.
+pub fn length_prefixed_to_annexb(data: &[u8], length_size: u8) -> Result<Vec<u8>, FormatError> {
+    let mut annexb = Vec::with_capacity(data.len() + data.len() / 256);
+    for nalu in split_length_prefixed(data, length_size)? {
+        annexb.extend_from_slice(&START_CODE);
+        annexb.extend_from_slice(nalu);
+    }
+    Ok(annexb)
+}
+
+/// Annex-B 转为长度前缀格式.
+pub fn annexb_to_length_prefixed(data: &[u8], length_size: u8) -> Result<Vec<u8>, FormatError> {
+    check_length_size(length_size)?;
+    let mut output = Vec::with_capacity(data.len());
+    // 从内存读取不会失败
+    for nalu in NalReader::new(data).map_while(Result::ok) {
+        if length_size < 4 && nalu.len() >> (8 * length_size) != 0 {
+            return Err(FormatError::NaluTooLarge(nalu.len()));
+        }
+        output.extend_from_slice(&(nalu.len() as u32).to_be_bytes()[4 - length_size as usize..]);
+        output.extend_from_slice(&nalu);
+    }
+    Ok(output)
+}
+
+/// 按参数集的编码格式生成 avcC 或 hvcC.
+pub fn build_decoder_config(parameter_set: &ParameterSet, length_size: u8) -> Result<Vec<u8>, FormatError> {
+    check_length_size(length_size)?;
+    match parameter_set {
+        ParameterSet::H264 { sps, pps } => build_avcc(sps, pps, length_size),
+        ParameterSet::H265 { vps, sps, pps } => build_hvcc(vps, sps, pps, length_size),
+        ParameterSet::Other => Err(FormatError::InvalidRecord("no parameter sets")),
+    }
+}
+
+fn push_nalu(record: &mut Vec<u8>, nalu: &[u8]) -> Result<(), FormatError> {
+    let len = u16::try_from(nalu.len()).map_err(|_| FormatError::NaluTooLarge(nalu.len()))?;
+    record.extend_from_slice(&len.to_be_bytes());
+    record.extend_from_slice(nalu);
+    Ok(())
+}
+
+/// AVCDecoderConfigurationRecord (14496-15 5.3.3.1).
+fn build_avcc(sps: &[u8], pps: &[u8], length_size: u8) -> Result<Vec<u8>, FormatError> {
+    let parsed = h264_sps::Sps::parse(sps)?;
+    let mut record = vec![1, sps[1], sps[2], sps[3], 0xFC | (length_size - 1), 0xE0 | 1];
+    push_nalu(&mut record, sps)?;
+    record.push(1);
+    push_nalu(&mut record, pps)?;
+    // Baseline/Main/Extended 以外的 profile 带色度格式和位深 (ISO/IEC 14496-15 5.3.3.1.2)
+    if !matches!(parsed.profile_idc, 66 | 77 | 88) {
+        let (luma, chroma) = parsed.bit_depth();
+        record.push(0xFC | parsed.chroma_format_idc() as u8 & 0x03);
+        record.push(0xF8 | (luma - 8) as u8 & 0x07);
+        record.push(0xF8 | (chroma - 8) as u8 & 0x07);
+        record.push(0); // numOfSequenceParameterSetExt
+    }
+    Ok(record)
+}
+
+/// HEVCDecoderConfigurationRecord (14496-15 8.3.3.1).
+fn build_hvcc(vps: &[u8], sps: &[u8], pps: &[u8], length_size: u8) -> Result<Vec<u8>, FormatError> {
+    let parsed = h265_ps::Sps::parse(sps)?;
+    // SPS 的 RBSP 第一个字节之后依次是 general profile, 兼容性标志, 约束标志和 level, 与记录中的顺序相同
+    let rbsp = rbsp::nal_to_rbsp(sps.get(2..).unwrap_or_default());
+    let general_ptl = rbsp.get(1..13).ok_or(FormatError::InvalidParameterSet(BitStreamError::Eof))?;
+    let (luma, chroma) = parsed.bit_depth();
+    let mut record = vec![1];
+    record.extend_from_slice(general_ptl);
+    record.extend_from_slice(&[
+        0xF0, 0x00, // min_spatial_segmentation_idc
+        0xFC,       // parallelismType
+        0xFC | parsed.chroma_format_idc as u8 & 0x03,
+        0xF8 | (luma - 8) as u8 & 0x07,
+        0xF8 | (chroma - 8) as u8 & 0x07,
+        0x00, 0x00, // avgFrameRate
+        (parsed.sps_max_sub_layers_minus1 + 1) << 3 | parsed.sps_temporal_id_nesting_flag << 2 | (length_size - 1),
+        3,          // numOfArrays
+    ]);
+    for (nal_unit_type, nalu) in [(32, vps), (33, sps), (34, pps)] {
+        // array_completeness = 1
+        record.extend_from_slice(&[0x80 | nal_unit_type, 0x00, 0x01]);
+        push_nalu(&mut record, nalu)?;
+    }
+    Ok(record)
+}
+
+/// 读取配置记录中的 n 个 NAL, 每个前有 2 字节长度.
+fn read_nalus<'a>(data: &mut &'a [u8], count: usize) -> Result<Vec<&'a [u8]>, FormatError> {
+    let mut nalus = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = data.get(..2).ok_or(FormatError::Truncated).map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)?;
+        nalus.push(data.get(2..2 + len).ok_or(FormatError::Truncated)?);
+        *data = &data[2 + len..];
+    }
+    Ok(nalus)
+}
+
+/// 解析 avcC, 有多个参数集时取第一个.
+pub fn parse_avcc(record: &[u8]) -> Result<DecoderConfig, FormatError> {
+    let header = record.get(..6).ok_or(FormatError::Truncated)?;
+    if header[0] != 1 {
+        return Err(FormatError::InvalidRecord("unsupported avcC version"));
+    }
+    let length_size = (header[4] & 0x03) + 1;
+    check_length_size(length_size)?;
+    let mut data = &record[6..];
+    let sps = read_nalus(&mut data, (header[5] & 0x1F) as usize)?;
+    let num_pps = *data.first().ok_or(FormatError::Truncated)? as usize;
+    data = &data[1..];
+    let pps = read_nalus(&mut data, num_pps)?;
+    match (sps.first(), pps.first()) {
+        (Some(sps), Some(pps)) => Ok(DecoderConfig {
+            parameter_set: ParameterSet::H264 { sps: sps.to_vec(), pps: pps.to_vec() },
+            length_size,
+        }),
+        _ => Err(FormatError::InvalidRecord("avcC without sps or pps")),
+    }
+}
+
+/// 解析 hvcC, 每种参数集取第一个.
+pub fn parse_hvcc(record: &[u8]) -> Result<DecoderConfig, FormatError> {
+    let header = record.get(..23).ok_or(FormatError::Truncated)?;
+    if header[0] != 1 {
+        return Err(FormatError::InvalidRecord("unsupported hvcC version"));
+    }
+    let length_size = (header[21] & 0x03) + 1;
+    check_length_size(length_size)?;
+    let (mut vps, mut sps, mut pps) = (None, None, None);
+    let mut data = &record[23..];
+    for _ in 0..header[22] {
+        let array = data.get(..3).ok_or(FormatError::Truncated)?;
+        let (nal_unit_type, count) = (array[0] & 0x3F, u16::from_be_bytes([array[1], array[2]]) as usize);
+        data = &data[3..];
+        let first = read_nalus(&mut data, count)?.first().map(|nalu| nalu.to_vec());
+        match nal_unit_type {
+            32 => vps = vps.or(first),
+            33 => sps = sps.or(first),
+            34 => pps = pps.or(first),
+            _ => {} // SEI 等
+        }
+    }
+    match (vps, sps, pps) {
+        (Some(vps), Some(sps), Some(pps)) => Ok(DecoderConfig { parameter_set: ParameterSet::H265 { vps, sps, pps }, length_size }),
+        _ => Err(FormatError::InvalidRecord("hvcC without vps, sps or pps")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_data::{H264_PPS, H264_SPS, H265_PPS, H265_SPS, H265_VPS};
+
+    #[test]
+    fn test_convert_stream() {
+        let annexb = [0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x68, 0xce, 0, 0, 0, 1, 0x65, 0x88, 0, 0, 3, 1];
+        let avcc = annexb_to_length_prefixed(&annexb, 4).unwrap();
+        assert_eq!(avcc, [0, 0, 0, 2, 0x67, 0x42, 0, 0, 0, 2, 0x68, 0xce, 0, 0, 0, 6, 0x65, 0x88, 0, 0, 3, 1]);
+        assert_eq!(length_prefixed_to_annexb(&avcc, 4).unwrap(), [
+            0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xce, 0, 0, 0, 1, 0x65, 0x88, 0, 0, 3, 1,
+        ]);
+        let short = annexb_to_length_prefixed(&annexb, 1).unwrap();
+        assert_eq!(split_length_prefixed(&short, 1).unwrap(), vec![&annexb[4..6], &annexb[9..11], &annexb[15..]]);
+
+        assert_eq!(annexb_to_length_prefixed(&annexb, 3), Err(FormatError::InvalidLengthSize(3)));
+        let large = [&[0, 0, 1][..], &[0x65; 300]].concat();
+        assert_eq!(annexb_to_length_prefixed(&large, 1), Err(FormatError::NaluTooLarge(300)));
+        assert_eq!(split_length_prefixed(&avcc[..avcc.len() - 1], 4), Err(FormatError::Truncated));
+        assert_eq!(split_length_prefixed(&avcc[..2], 4), Err(FormatError::Truncated));
+    }
+
+    #[test]
+    fn test_avcc_record() {
+        let parameter_set = ParameterSet::H264 { sps: H264_SPS.to_vec(), pps: H264_PPS.to_vec() };
+        let record = build_decoder_config(&parameter_set, DEFAULT_LENGTH_SIZE).unwrap();
+        assert_eq!(&record[..8], &[0x01, 0x64, 0x00, 0x28, 0xff, 0xe1, 0x00, 27]);
+        // High profile: 4:2:0, 8 位
+        assert_eq!(&record[record.len() - 4..], &[0xfd, 0xf8, 0xf8, 0x00]);
+        assert_eq!(parse_avcc(&record), Ok(DecoderConfig { parameter_set, length_size: 4 }));
+        assert_eq!(parse_avcc(&record[..20]), Err(FormatError::Truncated));
+        assert_eq!(parse_avcc(&[1, 0x64, 0, 0x28, 0xff, 0xe0, 0]), Err(FormatError::InvalidRecord("avcC without sps or pps")));
+
+        // High 4:4:4 Predictive 等其他 profile 同样带扩展字段
+        let mut sps = H264_SPS.to_vec();
+        sps[1] = 244;
+        let record = build_decoder_config(&ParameterSet::H264 { sps, pps: H264_PPS.to_vec() }, DEFAULT_LENGTH_SIZE).unwrap();
+        assert_eq!(&record[record.len() - 4..], &[0xfd, 0xf8, 0xf8, 0x00]);
+    }
+
+    #[test]
+    fn test_hvcc_record() {
+        let parameter_set = ParameterSet::H265 { vps: H265_VPS.to_vec(), sps: H265_SPS.to_vec(), pps: H265_PPS.to_vec() };
+        let record = build_decoder_config(&parameter_set, 2).unwrap();
+        // Main profile, 兼容 Main/Main 10, level 4
+        assert_eq!(&record[..13], &[0x01, 0x01, 0x60, 0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 120]);
+        assert_eq!(&record[16..23], &[0xfd, 0xf8, 0xf8, 0x00, 0x00, 0x0d, 0x03]);
+        assert_eq!(record.len(), 23 + 3 * 5 + 24 + 43 + 7);
+        assert_eq!(parse_hvcc(&record), Ok(DecoderConfig { parameter_set, length_size: 2 }));
+        assert_eq!(build_decoder_config(&ParameterSet::Other, 4), Err(FormatError::InvalidRecord("no parameter sets")));
+    }
+}
@@ -1,60 +1,151 @@
-use std::{fs::File, io::{Read, Seek, SeekFrom}};
-pub fn find_nalu(data: &[u8]) -> Option<&[u8]> {
-    let mut start = 0;
-    while start < data.len() {
-        if start + 3 < data.len()
-            && (&data[start..start + 3] == b"\x00\x00\x01"
-                || &data[start..start + 4] == b"\x00\x00\x00\x01")
-        {
-            let nalu_start = if &data[start..start + 3] == b"\x00\x00\x01" {
-                start + 3
-            } else {
-                start + 4
-            };
-            let mut end = nalu_start;
-            while end < data.len() - 3 {
-                if &data[end..end + 3] == b"\x00\x00\x01"
-                    || &data[end..end + 4] == b"\x00\x00\x00\x01"
-                {
-                    break;
-                }
-                end += 1;
-            }
-            return Some(&data[nalu_start..end]);
+use std::{fs::File, io::{self, Read, Seek, SeekFrom}};
+
+/// 每次从数据源读取的字节数.
+const READ_SIZE: usize = 64 * 1024;
+
+/// 查找 00 00 01 起始码, 返回其位置.
+fn find_start_code(data: &[u8], from: usize) -> Option<usize> {
+    let mut i = from;
+    while i + 2 < data.len() {
+        if data[i + 2] > 1 {
+            i += 3;
+        } else if data[i + 2] == 1 && data[i + 1] == 0 && data[i] == 0 {
+            return Some(i);
         } else {
-            start += 1;
+            i += 1;
         }
     }
     None
 }
 
-/// Get the index of `needle` in `haystack`.
-fn get_index(haystack: &[u8], needle: &[u8]) -> Option<usize> {
-    let haystack_ptr = haystack.as_ptr();
-    let needle_ptr = needle.as_ptr();
+/// 去掉 NAL 末尾的 0: 4 字节起始码的第一个字节和 trailing_zero_8bits, NAL 本身不会以 0 结尾.
+fn trim_trailing_zeros(nalu: &[u8]) -> &[u8] {
+    let end = nalu.iter().rposition(|&byte| byte != 0).map_or(0, |i| i + 1);
+    &nalu[..end]
+}
 
-    // Safety: The caller must ensure that `needle` is within `haystack`.
-    unsafe {
-        if needle_ptr >= haystack_ptr && needle_ptr < haystack_ptr.add(haystack.len()) {
-            Some(needle_ptr.offset_from(haystack_ptr) as usize)
-        } else {
-            None
+/// 返回 `data` 中的第一个 NAL 单元, 没有下一个起始码时到数据结尾.
+pub fn find_nalu(data: &[u8]) -> Option<&[u8]> {
+    let nalu_start = find_start_code(data, 0)? + 3;
+    let end = find_start_code(data, nalu_start).unwrap_or(data.len());
+    Some(trim_trailing_zeros(&data[nalu_start..end]))
+}
+
+/// Annex-B 字节流的 NAL 单元读取器, 适用于任意 [`Read`] (文件, 管道, socket).
+///
+/// 数据只读取一次, NAL 的大小不受读取块大小限制, 起始码可以跨越两次读取.
+pub struct NalReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    nalu_start: Option<usize>, // 当前 NAL 在 buffer 中的起点 (起始码之后), 找到第一个起始码之前为空
+    scanned: usize,            // 已经查找过起始码的位置, 新数据到来后从这里继续
+    eof: bool,
+}
+
+impl<R: Read> NalReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, buffer: Vec::with_capacity(READ_SIZE), nalu_start: None, scanned: 0, eof: false }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// 丢弃已缓存的数据, 数据源重新定位后调用.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.nalu_start = None;
+        self.scanned = 0;
+        self.eof = false;
+    }
+
+    /// 读取下一个 NAL 单元 (不含起始码), 数据结束时返回 `None`.
+    pub fn read_nalu(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.nalu_start {
+                None => {
+                    if let Some(pos) = find_start_code(&self.buffer, self.scanned) {
+                        self.nalu_start = Some(pos + 3);
+                        self.scanned = pos + 3;
+                        continue;
+                    }
+                }
+                Some(start) => {
+                    if let Some(pos) = find_start_code(&self.buffer, self.scanned) {
+                        let nalu = trim_trailing_zeros(&self.buffer[start..pos]).to_vec();
+                        self.nalu_start = Some(pos + 3);
+                        self.scanned = pos + 3;
+                        if nalu.is_empty() {
+                            continue;
+                        }
+                        return Ok(Some(nalu));
+                    }
+                }
+            }
+            if self.eof {
+                let Some(start) = self.nalu_start.take() else {
+                    return Ok(None);
+                };
+                let nalu = trim_trailing_zeros(&self.buffer[start..]).to_vec();
+                self.buffer.clear();
+                self.scanned = 0;
+                if !nalu.is_empty() {
+                    return Ok(Some(nalu));
+                }
+                return Ok(None);
+            }
+            // 起始码可能被两次读取分开, 保留最后两个字节重新查找
+            self.scanned = self.buffer.len().saturating_sub(2).max(self.nalu_start.unwrap_or(0));
+            self.fill_buffer()?;
         }
     }
+
+    /// 丢弃已经返回的数据, 追加读取一块.
+    fn fill_buffer(&mut self) -> io::Result<()> {
+        let consumed = match self.nalu_start {
+            Some(start) => start,
+            None => self.scanned,
+        };
+        self.buffer.drain(..consumed);
+        self.scanned -= consumed;
+        self.nalu_start = self.nalu_start.map(|start| start - consumed);
+
+        let len = self.buffer.len();
+        self.buffer.resize(len + READ_SIZE, 0);
+        let result = loop {
+            match self.reader.read(&mut self.buffer[len..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        let bytes_read = *result.as_ref().unwrap_or(&0);
+        self.buffer.truncate(len + bytes_read);
+        self.eof = bytes_read == 0;
+        result.map(|_| ())
+    }
+}
+
+impl<R: Read> Iterator for NalReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_nalu().transpose()
+    }
 }
 
+/// 文件的 NAL 单元迭代器, `infinite` 时到结尾后从头循环.
 pub struct NaluIterator {
-    file: File,
-    buffer: Vec<u8>, 
+    reader: NalReader<File>,
     infinite: bool, // Add a boolean field to indicate if the iterator should be infinite
+    looped_empty: bool, // 从头读取后还没有得到 NAL, 避免空文件无限循环
 }
 
 impl NaluIterator {
     pub fn new(file: File, infinite: bool) -> Self {
         let res = NaluIterator {
-            file,
-            buffer: vec![0; CHUNK_SIZE],
+            reader: NalReader::new(file),
             infinite,
+            looped_empty: false,
         };
         log::info!("NaluIterator created");
         res
@@ -66,39 +157,28 @@ impl Iterator for NaluIterator {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let bytes_read = self.file.read(&mut self.buffer).ok()?;
-            if bytes_read == 0 {
-                // log::info!("End of file");
-                if self.infinite {
-                    self.file.seek(SeekFrom::Start(0)).ok()?;
-                    continue;
+            match self.reader.read_nalu() {
+                Ok(Some(nalu)) => {
+                    log::debug!("Found NALU of length: {}", nalu.len());
+                    self.looped_empty = false;
+                    return Some(nalu);
+                }
+                Ok(None) if self.infinite && !self.looped_empty => {
+                    self.reader.get_mut().seek(SeekFrom::Start(0)).ok()?;
+                    self.reader.reset();
+                    self.looped_empty = true;
+                }
+                Ok(None) => return None,
+                Err(e) => {
+                    log::warn!("read nalu failed: {}", e);
+                    return None;
                 }
-                return None;
-            }
-            
-            let data = &self.buffer[..bytes_read];
-            if let Some(nalu) = find_nalu(data) { 
-                // log::debug!("nalu: {:?}", nalu.iter().map(|x| format!("{:x}", x)).collect::<Vec<String>>());
-                log::debug!("nalu: {:x?}", nalu);
-                
-                let start_index = get_index(data, nalu).expect("NALU not found in buffer"); 
-                let pos = self.file.seek(SeekFrom::Current(((nalu.len() + start_index) as i64) - (bytes_read as i64))).ok()?;
-                log::debug!("Found NALU of length: {}, bytes_read: {}, pos after seek: {}", nalu.len(), bytes_read, pos);
-
-                return Some(nalu.to_vec());
-            }
-            
-            log::warn!("No NALU found in this chunk, continue to read next chunk with size {}", CHUNK_SIZE);
-    
-            if self.infinite {
-                self.file.seek(SeekFrom::Start(0)).ok()?;
-            }else {
-                return None;
             }
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParameterSet{
     H264{
         sps: Vec<u8>,
@@ -111,93 +191,57 @@ pub enum ParameterSet{
     },
     Other
 }
-const CHUNK_SIZE: usize = 500 * 1024; // 500KB
-pub fn parse_h264(file: &mut File) -> ParameterSet {
-    let mut buffer = vec![0; CHUNK_SIZE];
-    let mut sps = vec![];
-    let mut pps = vec![];
-    let mut is_h264 = false;
-    file.seek(SeekFrom::Start(0)).unwrap();
-    loop {
-        let bytes_read = file.read(&mut buffer).unwrap();
-        if bytes_read == 0 {
-            break;
-        }
-        let data = &buffer[..bytes_read];
-        match find_nalu(data) {
-            Some(nalu) => {
-                log::info!("Found NALU of length: {}", nalu.len());
-                // log::debug!("nalu: {:#?}", nalu.iter().map(|x| format!("{:x}", x)).collect::<Vec<String>>());
-                // Process the NALU data here, exaple: parse SPS/PPS for H264
-                let nalu_type = nalu[0] & 0x1f;
-                if nalu_type == 7 {
-                    sps = nalu.to_vec();
-                } else if nalu_type == 8 {
-                    pps = nalu.to_vec();
-                }
-                let start_index = get_index(data, nalu).expect("NALU not found in buffer"); 
-                let _ = file.seek(SeekFrom::Current(((nalu.len() + start_index) as i64) - (bytes_read as i64))).unwrap();   
-            }
-            None => {
-                log::debug!("No NALU found in this chunk");
-            }
-        }
-        if !sps.is_empty() && !pps.is_empty() {
-            is_h264 = true;
-            break
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 每次最多返回 `step` 字节, 模拟管道和 socket.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
         }
     }
-    match is_h264 {
-        true => ParameterSet::H264{
-            sps,
-            pps,
-        },
-        false => ParameterSet::Other,
+
+    fn read_all(data: &[u8], step: usize) -> Vec<Vec<u8>> {
+        NalReader::new(Trickle { data, step }).collect::<io::Result<_>>().unwrap()
     }
-}
-pub fn parse_h265(file: &mut File) -> ParameterSet {
-    let mut buffer = vec![0; CHUNK_SIZE];
-    let mut vps: Vec<u8> = vec![];
-    let mut sps = vec![];
-    let mut pps = vec![];
-    let mut is_h265 = false;
-    file.seek(SeekFrom::Start(0)).unwrap();
-    loop {
-        let bytes_read = file.read(&mut buffer).unwrap();
-        if bytes_read == 0 {
-            break;
-        }
-        let data = &buffer[..bytes_read];
-        match find_nalu(data) {
-            Some(nalu) => {
-                log::info!("Found NALU of length: {}", nalu.len());
-                // log::debug!("nalu: {:#?}", nalu.iter().map(|x| format!("{:x}", x)).collect::<Vec<String>>());
-                // Process the NALU data here, exaple: parse VPS/SPS/PPS for H265
-                let nalu_type = (nalu[0] & 0x7e) >> 1;
-                match nalu_type {
-                    32 => vps = nalu.to_vec(),
-                    33 => sps = nalu.to_vec(),
-                    34 => pps = nalu.to_vec(),
-                    _ => (),
-                };
-                let start_index = get_index(data, nalu).expect("NALU not found in buffer"); 
-                let _ = file.seek(SeekFrom::Current(((nalu.len() + start_index) as i64) - (bytes_read as i64))).unwrap();   
-            }
-            None => {
-                log::debug!("No NALU found in this chunk");
-            }
-        }
-        if !vps.is_empty() && !sps.is_empty() && !pps.is_empty() {
-            is_h265 = true;
-            break
+
+    #[test]
+    fn test_split_annexb() {
+        // 开头的垃圾数据, 4 字节起始码, trailing_zero_8bits, 空 NAL, 结尾没有起始码
+        let data = [
+            0xff, 0x00, 0x00, 0x00, 0x01, 0x67, 0x42, 0x00, 0x00, 0x01, 0x68, 0xce, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x00, 0x01, 0x65, 0x88, 0x00, 0x00, 0x03, 0x01, 0x84,
+        ];
+        let expected = vec![vec![0x67, 0x42], vec![0x68, 0xce], vec![0x65, 0x88, 0x00, 0x00, 0x03, 0x01, 0x84]];
+        // 每次读 1~3 字节时起始码会跨越两次读取
+        for step in [1, 2, 3, 5, READ_SIZE] {
+            assert_eq!(read_all(&data, step), expected, "step {}", step);
         }
+        assert_eq!(find_nalu(&data), Some(&[0x67, 0x42][..]));
+        assert_eq!(find_nalu(&data[17..]), Some(&expected[2][..]));
+        assert_eq!(find_nalu(&data[5..7]), None);
+        assert!(read_all(&[0x00, 0x00, 0x00], 1).is_empty());
     }
-    match is_h265 {
-        true => ParameterSet::H265{
-            vps,
-            sps,
-            pps,
-        },
-        false => ParameterSet::Other,
+
+    #[test]
+    fn test_large_nalu() {
+        let large: Vec<u8> = [0x65].into_iter().chain((0..3 * READ_SIZE).map(|i| (i % 251) as u8 | 0x04)).collect();
+        let mut data = vec![0x00, 0x00, 0x00, 0x01];
+        data.extend_from_slice(&large);
+        data.extend_from_slice(&[0x00, 0x00, 0x01, 0x41, 0x9a]);
+        let nalus = read_all(&data, 10000);
+        assert_eq!(nalus.len(), 2);
+        assert_eq!(nalus[0], large);
+        assert_eq!(nalus[1], vec![0x41, 0x9a]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,267 @@
+//! 探测 Annex-B 视频流的编码格式.
+//!
+//! 读取开头的若干 NAL 单元, 每种编码格式按 NAL 头是否合法, 参数集能否解析打分,
+//! 选出参数集完整且得分最高的一种, 同时给出 profile, 分辨率和帧率.
+
+use std::{fmt, fs::File, io::{self, Read}, path::Path};
+
+use super::{h264_sps, h265_ps, parse::{NalReader, ParameterSet}};
+
+/// 最多检查的 NAL 单元数, 参数集一般在流的最前面.
+const PROBE_NALUS: usize = 64;
+/// 参数集完整解析的得分, 远高于单个 NAL 头合法的得分.
+const PARAMETER_SET_SCORE: i32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    H264,
+    H265,
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Codec::H264 => write!(f, "H.264"),
+            Codec::H265 => write!(f, "H.265"),
+        }
+    }
+}
+
+/// 探测结果.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeInfo {
+    pub codec: Codec,
+    pub profile_idc: u8,
+    pub level_idc: u8,
+    pub tier_flag: u8, // 只有 H.265 有, H.264 为 0
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: Option<f64>, // 参数集中没有时序信息时为空
+    pub parameter_set: ParameterSet,
+}
+
+#[derive(Debug)]
+pub enum ProbeError {
+    Io(io::Error),
+    /// 没有找到起始码, 不是 Annex-B 字节流
+    NotAnnexB,
+    /// 开头的 NAL 单元中没有任何一种编码格式的完整参数集
+    Unsupported,
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Io(e) => write!(f, "read failed: {}", e),
+            ProbeError::NotAnnexB => write!(f, "no start code found, not an annex-b stream"),
+            ProbeError::Unsupported => {
+                write!(f, "no h264 sps/pps or h265 vps/sps/pps in the first {} nal units", PROBE_NALUS)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl From<io::Error> for ProbeError {
+    fn from(e: io::Error) -> Self {
+        ProbeError::Io(e)
+    }
+}
+
+/// 一种编码格式的探测状态, 新的编码格式实现该 trait 并加入 [`probe`] 的候选.
+trait Candidate {
+    fn feed(&mut self, nalu: &[u8]);
+    fn score(&self) -> i32;
+    /// 参数集完整时返回探测结果.
+    fn info(&self) -> Option<ProbeInfo>;
+}
+
+#[derive(Default)]
+struct H264Candidate {
+    score: i32,
+    sps: Option<(Vec<u8>, h264_sps::Sps)>,
+    pps: Option<Vec<u8>>,
+}
+
+impl Candidate for H264Candidate {
+    fn feed(&mut self, nalu: &[u8]) {
+        let nal_ref_idc = (nalu[0] >> 5) & 0x03;
+        // forbidden_zero_bit
+        if nalu[0] & 0x80 != 0 {
+            self.score -= 1;
+            return;
+        }
+        match nalu[0] & 0x1F {
+            7 if nal_ref_idc != 0 => match h264_sps::Sps::parse(nalu) {
+                Ok(sps) => {
+                    self.score += PARAMETER_SET_SCORE;
+                    self.sps.get_or_insert((nalu.to_vec(), sps));
+                }
+                Err(_) => self.score -= 1,
+            },
+            8 if nal_ref_idc != 0 => {
+                self.score += 1;
+                self.pps.get_or_insert(nalu.to_vec());
+            }
+            // IDR 和参数集的 nal_ref_idc 不能为 0
+            5 | 7 | 8 => self.score -= 1,
+            1..=15 | 19..=21 => self.score += 1,
+            _ => self.score -= 1,
+        }
+    }
+
+    fn score(&self) -> i32 {
+        self.score
+    }
+
+    fn info(&self) -> Option<ProbeInfo> {
+        let ((sps, parsed), pps) = (self.sps.as_ref()?, self.pps.as_ref()?);
+        let (width, height) = parsed.parse_width_height();
+        Some(ProbeInfo {
+            codec: Codec::H264,
+            profile_idc: parsed.profile_idc,
+            level_idc: parsed.level_idc,
+            tier_flag: 0,
+            width,
+            height,
+            frame_rate: parsed.frame_rate(),
+            parameter_set: ParameterSet::H264 { sps: sps.clone(), pps: pps.clone() },
+        })
+    }
+}
+
+#[derive(Default)]
+struct H265Candidate {
+    score: i32,
+    vps: Option<(Vec<u8>, h265_ps::Vps)>,
+    sps: Option<(Vec<u8>, h265_ps::Sps)>,
+    pps: Option<Vec<u8>>,
+}
+
+impl Candidate for H265Candidate {
+    fn feed(&mut self, nalu: &[u8]) {
+        // forbidden_zero_bit, nuh_temporal_id_plus1 不能为 0
+        if nalu.len() < 2 || nalu[0] & 0x80 != 0 || nalu[1] & 0x07 == 0 {
+            self.score -= 1;
+            return;
+        }
+        match (nalu[0] >> 1) & 0x3F {
+            32 => match h265_ps::Vps::parse(nalu) {
+                Ok(vps) => {
+                    self.score += PARAMETER_SET_SCORE;
+                    self.vps.get_or_insert((nalu.to_vec(), vps));
+                }
+                Err(_) => self.score -= 1,
+            },
+            33 => match h265_ps::Sps::parse(nalu) {
+                Ok(sps) => {
+                    self.score += PARAMETER_SET_SCORE;
+                    self.sps.get_or_insert((nalu.to_vec(), sps));
+                }
+                Err(_) => self.score -= 1,
+            },
+            34 => {
+                self.score += 1;
+                self.pps.get_or_insert(nalu.to_vec());
+            }
+            0..=9 | 16..=21 | 35..=40 => self.score += 1,
+            _ => self.score -= 1,
+        }
+    }
+
+    fn score(&self) -> i32 {
+        self.score
+    }
+
+    fn info(&self) -> Option<ProbeInfo> {
+        let ((vps, parsed_vps), (sps, parsed), pps) = (self.vps.as_ref()?, self.sps.as_ref()?, self.pps.as_ref()?);
+        let ptl = &parsed.profile_tier_level;
+        let (width, height) = parsed.width_height();
+        Some(ProbeInfo {
+            codec: Codec::H265,
+            profile_idc: ptl.general_profile_idc,
+            level_idc: ptl.general_level_idc,
+            tier_flag: ptl.general_tier_flag,
+            width,
+            height,
+            // SPS 的 VUI 中没有时序信息时取 VPS 的
+            frame_rate: parsed.frame_rate().or_else(|| parsed_vps.timing_info?.frame_rate()),
+            parameter_set: ParameterSet::H265 { vps: vps.clone(), sps: sps.clone(), pps: pps.clone() },
+        })
+    }
+}
+
+/// 读取开头的 NAL 单元探测编码格式, 参数集完整的候选中得分最高的胜出.
+pub fn probe(reader: impl Read) -> Result<ProbeInfo, ProbeError> {
+    let mut candidates: Vec<Box<dyn Candidate>> = vec![Box::<H264Candidate>::default(), Box::<H265Candidate>::default()];
+    let mut reader = NalReader::new(reader);
+    let mut count = 0;
+    while count < PROBE_NALUS {
+        let Some(nalu) = reader.read_nalu()? else {
+            break;
+        };
+        count += 1;
+        for candidate in candidates.iter_mut() {
+            candidate.feed(&nalu);
+        }
+        // 得分最高的候选参数集已经完整时不再继续读
+        let best = candidates.iter().max_by_key(|candidate| candidate.score());
+        if let Some(info) = best.and_then(|candidate| candidate.info()) {
+            return Ok(info);
+        }
+    }
+    if count == 0 {
+        return Err(ProbeError::NotAnnexB);
+    }
+    candidates
+        .iter()
+        .filter_map(|candidate| Some((candidate.score(), candidate.info()?)))
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, info)| info)
+        .ok_or(ProbeError::Unsupported)
+}
+
+pub fn probe_file(path: impl AsRef<Path>) -> Result<ProbeInfo, ProbeError> {
+    probe(File::open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_data::{H264_PPS, H264_SPS, H265_PPS, H265_SPS, H265_VPS};
+
+    fn annexb(nalus: &[&[u8]]) -> Vec<u8> {
+        nalus.iter().flat_map(|nalu| [&[0x00, 0x00, 0x00, 0x01][..], nalu].concat()).collect()
+    }
+
+    #[test]
+    fn test_probe_h264() {
+        let stream = annexb(&[&[0x09, 0xf0], &H264_SPS, &H264_PPS, &[0x65, 0x88, 0x84]]);
+        let info = probe(&stream[..]).unwrap();
+        assert_eq!(info.codec, Codec::H264);
+        assert_eq!((info.profile_idc, info.level_idc), (100, 40));
+        assert_eq!((info.width, info.height, info.frame_rate), (1920, 1080, Some(25.0)));
+        assert_eq!(info.parameter_set, ParameterSet::H264 { sps: H264_SPS.to_vec(), pps: H264_PPS.to_vec() });
+    }
+
+    #[test]
+    fn test_probe_h265() {
+        // AUD 和 SEI 在前面, 其 NAL 头按 H.264 解释时也合法
+        let stream = annexb(&[&[0x46, 0x01, 0x10], &[0x4e, 0x01, 0x05, 0x01, 0x80], &H265_VPS, &H265_SPS, &H265_PPS, &[0x26, 0x01, 0xaf]]);
+        let info = probe(&stream[..]).unwrap();
+        assert_eq!(info.codec, Codec::H265);
+        assert_eq!((info.profile_idc, info.tier_flag, info.level_idc), (1, 0, 120));
+        assert_eq!((info.width, info.height, info.frame_rate), (1920, 1080, Some(25.0)));
+    }
+
+    #[test]
+    fn test_probe_errors() {
+        assert!(matches!(probe(&b"not a video"[..]), Err(ProbeError::NotAnnexB)));
+        // 只有 SPS, 没有 PPS
+        let stream = annexb(&[&H264_SPS, &[0x65, 0x88, 0x84]]);
+        let err = probe(&stream[..]).unwrap_err();
+        assert!(matches!(err, ProbeError::Unsupported));
+        assert!(err.to_string().contains("first 64 nal units"));
+    }
+}
@@ -0,0 +1,67 @@
+//! NAL 单元负载与 RBSP 之间的转换 (H.264 7.4.1, H.265 7.4.2).
+//!
+//! 编码器在 NAL 中连续两个 0x00 之后, 若下一个字节不大于 0x03, 会插入防竞争字节 0x03,
+//! 避免负载中出现起始码. 解析头部语法前必须去掉, 写入时再插回.
+
+const EMULATION_PREVENTION_BYTE: u8 = 0x03;
+
+/// 去掉 00 00 03 中的防竞争字节, 得到 RBSP.
+pub fn nal_to_rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == EMULATION_PREVENTION_BYTE {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+/// 在 RBSP 中插入防竞争字节; 结尾为 00 00 时 (如 cabac_zero_word) 也补上 03.
+pub fn rbsp_to_nal(rbsp: &[u8]) -> Vec<u8> {
+    let mut nal = Vec::with_capacity(rbsp.len() + rbsp.len() / 64);
+    let mut zeros = 0;
+    for &byte in rbsp {
+        if zeros >= 2 && byte <= EMULATION_PREVENTION_BYTE {
+            nal.push(EMULATION_PREVENTION_BYTE);
+            zeros = 0;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        nal.push(byte);
+    }
+    if zeros >= 2 {
+        nal.push(EMULATION_PREVENTION_BYTE);
+    }
+    nal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_data::{H264_SPS, H265_VPS};
+
+    #[test]
+    fn test_real_parameter_sets() {
+        let rbsp = nal_to_rbsp(&H264_SPS);
+        assert_eq!(rbsp.len(), H264_SPS.len() - 2);
+        assert_eq!(&rbsp[13..20], &[0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00]);
+        assert_eq!(rbsp_to_nal(&rbsp), H264_SPS);
+
+        let rbsp = nal_to_rbsp(&H265_VPS);
+        assert_eq!(&rbsp[8..17], &[0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(rbsp_to_nal(&rbsp), H265_VPS);
+    }
+
+    #[test]
+    fn test_escape_rules() {
+        // 00 00 之后不大于 03 的字节都要转义, 03 本身也要
+        assert_eq!(rbsp_to_nal(&[0, 0, 1, 0, 0, 3, 0, 0, 4]), vec![0, 0, 3, 1, 0, 0, 3, 3, 0, 0, 4]);
+        assert_eq!(rbsp_to_nal(&[0, 0, 0, 0]), vec![0, 0, 3, 0, 0, 3]);
+        assert_eq!(nal_to_rbsp(&[0, 0, 3, 0, 0, 3]), vec![0, 0, 0, 0]);
+        // 03 之后重新计数
+        assert_eq!(nal_to_rbsp(&[0, 0, 3, 3]), vec![0, 0, 3]);
+    }
+}
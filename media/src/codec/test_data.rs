@@ -0,0 +1,20 @@
+//! 测试共用的参数集.
+
+/// x264 编码的 1920x1080 25fps, High profile, level 4.0; VUI 中有两处 00 00 03
+pub const H264_SPS: [u8; 27] = [
+    0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0xc0, 0x44, 0x00, 0x00, 0x03,
+    0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xc8, 0x3c, 0x60, 0xc6, 0x58,
+];
+pub const H264_PPS: [u8; 5] = [0x68, 0xeb, 0xe3, 0xcb, 0x22];
+
+/// x265 编码的 1920x1080 25fps, Main profile, level 4; profile_tier_level 中有三处 00 00 03
+pub const H265_VPS: [u8; 24] = [
+    0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03,
+    0x00, 0x00, 0x03, 0x00, 0x78, 0x95, 0x98, 0x09,
+];
+pub const H265_SPS: [u8; 43] = [
+    0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03,
+    0x00, 0x78, 0xa0, 0x03, 0xc0, 0x80, 0x10, 0xe5, 0x96, 0x56, 0x69, 0x24, 0xca, 0xe0, 0x10, 0x00,
+    0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03, 0x01, 0x90, 0x80, 0x00,
+];
+pub const H265_PPS: [u8; 7] = [0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
@@ -0,0 +1,269 @@
+use super::rtp_packet::RtpPacket;
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// rtp 负载格式.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PayloadFormat {
+    /// RFC 6184, 支持 single NAL, STAP-A/B, MTAP16/24, FU-A/B.
+    /// 交织模式下假定按解码顺序接收, 不按 DON 重排
+    H264,
+    /// RFC 7798, 支持 single NAL, AP, FU (不带 DONL)
+    H265,
+}
+
+/// 重组出的一个访问单元, NAL 以 Annex-B 起始码分隔.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessUnit {
+    pub timestamp: u32,
+    pub data: Vec<u8>,
+    /// 重组期间检测到丢包, 数据可能不完整
+    pub lost: bool,
+}
+
+/// 把 rtp 包重组为 Annex-B 访问单元.
+///
+/// 以 marker 或时间戳变化作为访问单元的边界, 按序号间隔检测丢包:
+/// 丢包时丢弃未完成的分片, 并把当前访问单元标记为 `lost`. 乱序或重复的包直接丢弃.
+pub struct Depacketizer {
+    format: PayloadFormat,
+    last_sequence_number: Option<u16>,
+    timestamp: u32,
+    data: Vec<u8>,
+    fragment: Option<Vec<u8>>,
+    lost: bool,
+    lost_packets: u64,
+}
+
+impl Depacketizer {
+    pub fn new(format: PayloadFormat) -> Self {
+        Self {
+            format,
+            last_sequence_number: None,
+            timestamp: 0,
+            data: Vec::new(),
+            fragment: None,
+            lost: false,
+            lost_packets: 0,
+        }
+    }
+
+    /// 累计丢失的包数.
+    pub fn lost_packets(&self) -> u64 {
+        self.lost_packets
+    }
+
+    /// 处理一个 rtp 包, 返回已经完整的访问单元, 最多两个 (上一个缺少 marker 时).
+    pub fn push(&mut self, packet: &RtpPacket) -> Vec<AccessUnit> {
+        let mut units = Vec::new();
+        if let Some(last) = self.last_sequence_number {
+            let gap = packet.sequence_number.wrapping_sub(last.wrapping_add(1));
+            if gap >= 0x8000 {
+                log::debug!("drop reordered rtp packet {}", packet.sequence_number);
+                return units;
+            }
+            if gap != 0 {
+                log::debug!("lost {} rtp packets before {}", gap, packet.sequence_number);
+                self.lost_packets += gap as u64;
+                self.lost = true;
+                self.fragment = None;
+            }
+        }
+        self.last_sequence_number = Some(packet.sequence_number);
+
+        if packet.timestamp != self.timestamp && (!self.data.is_empty() || self.fragment.is_some()) {
+            units.extend(self.finish());
+        }
+        self.timestamp = packet.timestamp;
+
+        match self.format {
+            PayloadFormat::H264 => self.push_h264(&packet.payload),
+            PayloadFormat::H265 => self.push_h265(&packet.payload),
+        }
+        if packet.marker {
+            units.extend(self.finish());
+        }
+        units
+    }
+
+    fn finish(&mut self) -> Option<AccessUnit> {
+        if self.fragment.take().is_some() {
+            self.lost = true;
+        }
+        let lost = std::mem::take(&mut self.lost);
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(AccessUnit { timestamp: self.timestamp, data: std::mem::take(&mut self.data), lost })
+    }
+
+    fn push_nalu(&mut self, nalu: &[u8]) {
+        if !nalu.is_empty() {
+            self.data.extend(START_CODE);
+            self.data.extend(nalu);
+        }
+    }
+
+    /// 聚合包: 每个单元前有 2 字节长度, 单元开头的 `skip` 字节 (MTAP 的 DOND 和时间戳偏移) 不属于 NAL.
+    fn push_aggregated(&mut self, mut payload: &[u8], skip: usize) {
+        while payload.len() >= 2 {
+            let len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+            let Some(nalu) = payload.get(2..2 + len).and_then(|unit| unit.get(skip..)) else {
+                log::debug!("truncated aggregation packet");
+                self.lost = true;
+                return;
+            };
+            self.push_nalu(nalu);
+            payload = &payload[2 + len..];
+        }
+    }
+
+    /// 分片单元: `header` 为还原的 NAL 头, `start`/`end` 为 S/E 位.
+    fn push_fragment(&mut self, header: &[u8], start: bool, end: bool, data: &[u8]) {
+        if start {
+            if self.fragment.is_some() {
+                self.lost = true;
+            }
+            self.fragment = Some(header.to_vec());
+        }
+        // 没有收到起始分片时丢弃
+        let Some(fragment) = self.fragment.as_mut() else {
+            self.lost = true;
+            return;
+        };
+        fragment.extend(data);
+        if end {
+            let nalu = self.fragment.take().unwrap();
+            self.push_nalu(&nalu);
+        }
+    }
+
+    fn push_h264(&mut self, payload: &[u8]) {
+        let Some(&indicator) = payload.first() else {
+            return;
+        };
+        match indicator & 0x1F {
+            1..=23 => self.push_nalu(payload),
+            24 => self.push_aggregated(&payload[1..], 0),
+            // STAP-B, MTAP16, MTAP24: 包头后有 2 字节 DON
+            25 if payload.len() >= 3 => self.push_aggregated(&payload[3..], 0),
+            26 if payload.len() >= 3 => self.push_aggregated(&payload[3..], 3),
+            27 if payload.len() >= 3 => self.push_aggregated(&payload[3..], 4),
+            // FU-A, FU-B (带 2 字节 DON)
+            28 | 29 if payload.len() >= 2 => {
+                let fu_header = payload[1];
+                let header = [(indicator & 0xE0) | (fu_header & 0x1F)];
+                let data = if indicator & 0x1F == 29 { payload.get(4..).unwrap_or_default() } else { &payload[2..] };
+                self.push_fragment(&header, fu_header & 0x80 != 0, fu_header & 0x40 != 0, data);
+            }
+            nalu_type => log::debug!("unsupported h264 payload type {}", nalu_type),
+        }
+    }
+
+    fn push_h265(&mut self, payload: &[u8]) {
+        if payload.len() < 2 {
+            return;
+        }
+        match (payload[0] >> 1) & 0x3F {
+            48 => self.push_aggregated(&payload[2..], 0),
+            49 if payload.len() >= 3 => {
+                let fu_header = payload[2];
+                let header = [(payload[0] & 0x81) | ((fu_header & 0x3F) << 1), payload[1]];
+                self.push_fragment(&header, fu_header & 0x80 != 0, fu_header & 0x40 != 0, &payload[3..]);
+            }
+            0..=47 => self.push_nalu(payload),
+            nalu_type => log::debug!("unsupported h265 payload type {}", nalu_type),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::{rtp_h264::RtpSinkH264, rtp_packet::RtpSink, transport::SharedBuf};
+    use std::sync::Arc;
+
+    fn packet(sequence_number: u16, timestamp: u32, marker: bool, payload: &[u8]) -> RtpPacket {
+        let mut packet = RtpPacket::new(96, sequence_number, timestamp, 1234, marker);
+        packet.payload = payload.to_vec();
+        packet
+    }
+
+    #[test]
+    fn test_h264_roundtrip_fu_a() {
+        let buf = SharedBuf::default();
+        let mut sender = buf.sender();
+        let mut sink = RtpSinkH264::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false);
+        let nalu: Vec<u8> = std::iter::once(0x65).chain((0..3000).map(|i| i as u8)).collect();
+        sink.handle(&nalu, &mut sender).unwrap();
+        sink.end_of_stream(&mut sender).unwrap();
+
+        let mut depacketizer = Depacketizer::new(PayloadFormat::H264);
+        let units: Vec<_> = buf.rtp_packets().iter().flat_map(|packet| depacketizer.push(packet)).collect();
+        assert_eq!(units.len(), 1);
+        assert!(!units[0].lost);
+        assert_eq!(&units[0].data[..4], &START_CODE);
+        assert_eq!(&units[0].data[4..], nalu.as_slice());
+    }
+
+    #[test]
+    fn test_h264_stap_a_and_loss() {
+        let mut depacketizer = Depacketizer::new(PayloadFormat::H264);
+        let stap_a = [24, 0, 2, 0x67, 1, 0, 1, 0x68];
+        assert!(depacketizer.push(&packet(10, 0, false, &stap_a)).is_empty());
+        let units = depacketizer.push(&packet(11, 0, true, &[0x65, 9]));
+        assert_eq!(units[0].data, vec![0, 0, 0, 1, 0x67, 1, 0, 0, 0, 1, 0x68, 0, 0, 0, 1, 0x65, 9]);
+
+        // FU-A 中间分片丢失, 时间戳变化时结束上一个访问单元
+        assert!(depacketizer.push(&packet(12, 3600, false, &[0x41, 7])).is_empty());
+        assert!(depacketizer.push(&packet(13, 3600, false, &[0x7C, 0x81, 1])).is_empty());
+        assert!(depacketizer.push(&packet(15, 3600, false, &[0x7C, 0x41, 3])).is_empty());
+        let units = depacketizer.push(&packet(16, 7200, true, &[0x41, 8]));
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[0], AccessUnit { timestamp: 3600, data: vec![0, 0, 0, 1, 0x41, 7], lost: true });
+        assert_eq!(units[1], AccessUnit { timestamp: 7200, data: vec![0, 0, 0, 1, 0x41, 8], lost: false });
+        assert_eq!(depacketizer.lost_packets(), 1);
+
+        // 重复的包被丢弃
+        assert!(depacketizer.push(&packet(16, 7200, true, &[0x41, 8])).is_empty());
+    }
+
+    #[test]
+    fn test_h264_interleaved_mode() {
+        let mut depacketizer = Depacketizer::new(PayloadFormat::H264);
+        // STAP-B (DON 0): SPS + PPS
+        let stap_b = [25, 0, 0, 0, 2, 0x67, 1, 0, 1, 0x68];
+        assert!(depacketizer.push(&packet(0, 0, false, &stap_b)).is_empty());
+        // FU-B (DON 2) + FU-A
+        assert!(depacketizer.push(&packet(1, 0, false, &[0x7D, 0x85, 0, 2, 1])).is_empty());
+        let units = depacketizer.push(&packet(2, 0, true, &[0x7C, 0x45, 2]));
+        assert_eq!(units[0].data, vec![0, 0, 0, 1, 0x67, 1, 0, 0, 0, 1, 0x68, 0, 0, 0, 1, 0x65, 1, 2]);
+
+        // MTAP16: DONB, 每个单元 DOND + 2 字节时间戳偏移
+        let mtap16 = [26, 0, 3, 0, 5, 0, 0, 0, 0x41, 7, 0, 4, 1, 0, 0, 0x41];
+        let units = depacketizer.push(&packet(3, 3600, true, &mtap16));
+        assert_eq!(units[0].data, vec![0, 0, 0, 1, 0x41, 7, 0, 0, 0, 1, 0x41]);
+    }
+
+    #[test]
+    fn test_h265_ap_and_fu() {
+        let mut depacketizer = Depacketizer::new(PayloadFormat::H265);
+        // AP: VPS + SPS
+        let ap = [48 << 1, 1, 0, 3, 32 << 1, 1, 0xAA, 0, 3, 33 << 1, 1, 0xBB];
+        assert!(depacketizer.push(&packet(0, 0, false, &ap)).is_empty());
+        // FU: IDR_W_RADL (19)
+        assert!(depacketizer.push(&packet(1, 0, false, &[49 << 1, 1, 0x80 | 19, 1, 2])).is_empty());
+        let units = depacketizer.push(&packet(2, 0, true, &[49 << 1, 1, 0x40 | 19, 3]));
+        assert_eq!(
+            units[0].data,
+            vec![0, 0, 0, 1, 64, 1, 0xAA, 0, 0, 0, 1, 66, 1, 0xBB, 0, 0, 0, 1, 38, 1, 1, 2, 3]
+        );
+        assert!(!units[0].lost);
+
+        // 缺少起始分片
+        let units = depacketizer.push(&packet(3, 3600, true, &[49 << 1, 1, 0x40 | 1, 3]));
+        assert!(units.is_empty());
+        let units = depacketizer.push(&packet(4, 7200, true, &[1 << 1, 1, 5]));
+        assert_eq!(units[0], AccessUnit { timestamp: 7200, data: vec![0, 0, 0, 1, 2, 1, 5], lost: false });
+    }
+}
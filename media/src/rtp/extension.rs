@@ -0,0 +1,170 @@
+use std::time::SystemTime;
+
+use super::{rtcp::ntp_timestamp, rtp_packet::RtpPacket};
+
+/// 支持的 RTP 头扩展.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtensionKind {
+    /// 发送时间, 24 位 6.18 定点秒
+    AbsSendTime,
+    /// 帧的采集时间, 64 位 NTP 时间戳
+    AbsCaptureTime,
+    /// 帧边界和关键帧标记 (非分层编码的 1 字节格式: S|E|I|D|0000)
+    FrameMarking,
+}
+
+impl ExtensionKind {
+    pub fn uri(&self) -> &'static str {
+        match self {
+            ExtensionKind::AbsSendTime => "http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time",
+            ExtensionKind::AbsCaptureTime => "http://www.webrtc.org/experiments/rtp-hdrext/abs-capture-time",
+            ExtensionKind::FrameMarking => "urn:ietf:params:rtp-hdrext:framemarking",
+        }
+    }
+}
+
+/// 启用的头扩展及其 id, id 从 1 开始按顺序分配.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtensionMap {
+    entries: Vec<(u8, ExtensionKind)>,
+}
+
+impl ExtensionMap {
+    pub fn new(kinds: &[ExtensionKind]) -> Self {
+        let mut entries: Vec<(u8, ExtensionKind)> = Vec::new();
+        for kind in kinds {
+            if !entries.iter().any(|(_, k)| k == kind) {
+                entries.push((entries.len() as u8 + 1, *kind));
+            }
+        }
+        Self { entries }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// SDP 中的 a=extmap 属性值, 如 "1 http://...".
+    pub fn extmap(&self) -> Vec<String> {
+        self.entries.iter().map(|(id, kind)| format!("{} {}", id, kind.uri())).collect()
+    }
+}
+
+/// 发送时给 rtp 包加上协商的头扩展.
+///
+/// 采集时间和关键帧标记按访问单元确定: sink 在发送一个访问单元之前调用 [`HeaderExtender::begin_access_unit`],
+/// 之后的包都使用同样的值; 第一个包带 S 位, 最后一个包 (marker) 带 E 位.
+pub struct HeaderExtender {
+    map: ExtensionMap,
+    clock_rate: u32,
+    last_timestamp: Option<u32>,
+    capture_time: u64, // 当前访问单元的显示时间, NTP 时间戳
+    independent: bool,
+    first: bool, // 下一个包是访问单元的第一个包
+}
+
+impl HeaderExtender {
+    pub fn new(map: ExtensionMap, clock_rate: u32) -> Self {
+        Self {
+            map,
+            clock_rate,
+            last_timestamp: None,
+            capture_time: 0,
+            independent: false,
+            first: false,
+        }
+    }
+
+    /// 开始发送时间戳为 `timestamp` 的访问单元, `keyframe` 表示可以独立解码.
+    ///
+    /// 第一个访问单元的显示时间为 `now`, 之后按时间戳差推算, 与发送节奏无关;
+    /// 时间戳回退时从 `now` 重新计算.
+    pub fn begin_access_unit(&mut self, timestamp: u32, keyframe: bool, now: SystemTime) {
+        let delta = self.last_timestamp.map(|last| timestamp.wrapping_sub(last) as i32);
+        self.capture_time = match delta {
+            Some(delta) if delta >= 0 => {
+                self.capture_time.wrapping_add(((delta as u64) << 32) / self.clock_rate as u64)
+            }
+            _ => ntp_timestamp(now),
+        };
+        self.last_timestamp = Some(timestamp);
+        self.independent = keyframe;
+        self.first = true;
+    }
+
+    pub fn apply(&mut self, packet: &mut RtpPacket, now: SystemTime) {
+        let start = std::mem::take(&mut self.first);
+        for (id, kind) in &self.map.entries {
+            let data = match kind {
+                ExtensionKind::AbsSendTime => ((ntp_timestamp(now) >> 14) as u32 & 0x00ff_ffff).to_be_bytes()[1..].to_vec(),
+                ExtensionKind::AbsCaptureTime => self.capture_time.to_be_bytes().to_vec(),
+                ExtensionKind::FrameMarking => {
+                    vec![(start as u8) << 7 | (packet.marker as u8) << 6 | (self.independent as u8) << 5]
+                }
+            };
+            // id 由 ExtensionMap 从 1 开始分配, 数据不超过 8 字节
+            packet.add_extension(*id, data).expect("valid header extension");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_extmap_and_apply() {
+        let map = ExtensionMap::new(&[ExtensionKind::AbsSendTime, ExtensionKind::FrameMarking, ExtensionKind::AbsSendTime]);
+        assert_eq!(
+            map.extmap(),
+            vec![
+                "1 http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time".to_string(),
+                "2 urn:ietf:params:rtp-hdrext:framemarking".to_string(),
+            ]
+        );
+
+        let mut extender = HeaderExtender::new(map, 90000);
+        let now = UNIX_EPOCH + Duration::from_millis(1_500);
+        extender.begin_access_unit(3000, true, now);
+        let mut first = RtpPacket::new(96, 0, 3000, 1234, false);
+        extender.apply(&mut first, now);
+        // 1.5 秒 (加上 NTP 与 unix 的偏移, 整秒部分只保留低 6 位)
+        let abs_send_time = ((ntp_timestamp(now) >> 14) & 0xff_ffff) as u32;
+        assert_eq!(first.extensions[0].data, abs_send_time.to_be_bytes()[1..].to_vec());
+        assert_eq!(abs_send_time & 0x3_ffff, 1 << 17);
+        assert_eq!(first.extensions[1].data, vec![0b1010_0000]);
+
+        // 同一访问单元的后续包保持 I 位
+        let mut last = RtpPacket::new(96, 1, 3000, 1234, true);
+        extender.apply(&mut last, now);
+        assert_eq!(last.extensions[1].data, vec![0b0110_0000]);
+
+        extender.begin_access_unit(6000, false, now);
+        let mut next = RtpPacket::new(96, 2, 6000, 1234, true);
+        extender.apply(&mut next, now);
+        assert_eq!(next.extensions[1].data, vec![0b1100_0000]);
+    }
+
+    #[test]
+    fn test_capture_time_from_timestamp() {
+        let map = ExtensionMap::new(&[ExtensionKind::AbsCaptureTime]);
+        let mut extender = HeaderExtender::new(map, 90000);
+        let start = UNIX_EPOCH + Duration::from_secs(100);
+        let capture_time = |extender: &mut HeaderExtender, sent: SystemTime| {
+            let mut packet = RtpPacket::new(96, 0, 0, 1234, true);
+            extender.apply(&mut packet, sent);
+            u64::from_be_bytes(packet.extensions[0].data[..].try_into().unwrap())
+        };
+
+        extender.begin_access_unit(u32::MAX - 899, true, start);
+        assert_eq!(capture_time(&mut extender, start), ntp_timestamp(start));
+        // 晚发送 300ms 不影响采集时间, 时间戳回绕后仍然连续
+        let late = start + Duration::from_millis(310);
+        extender.begin_access_unit(900, false, late);
+        assert_eq!(capture_time(&mut extender, late), ntp_timestamp(start + Duration::from_millis(20)));
+        // 时间戳回退时重新对齐
+        extender.begin_access_unit(0, false, late);
+        assert_eq!(capture_time(&mut extender, late), ntp_timestamp(late));
+    }
+}
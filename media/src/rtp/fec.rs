@@ -0,0 +1,140 @@
+use super::rtp_packet::RtpPacket;
+
+/// 单个 FEC 包最多保护的 rtp 包数, 对应 L=0 时 16 位的掩码.
+pub const MAX_GROUP_SIZE: u8 = 16;
+
+const RTP_HEADER_SIZE: usize = 12;
+
+/// RFC 5109 ULPFEC 参数, FEC 包用单独的 ssrc 和负载类型发送.
+///
+/// FEC 包不封装在 RED (RFC 2198) 中, SDP 也不声明 FEC-FR 分组 (那是 FlexFEC 的信令),
+/// 接收端需要按 ulpfec 负载类型识别纠错流.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ulpfec {
+    pub payload_type: u8,
+    pub ssrc: u32,
+    /// 每组 rtp 包数, 每组生成一个 FEC 包, 越小保护越强, 开销为 1/group_size
+    pub group_size: u8,
+}
+
+/// 把连续发送的 rtp 包分组做异或, 生成 level 0 的 ULPFEC 包.
+///
+/// 一帧的最后一个包 (marker) 也会结束当前分组, 避免等待下一帧才能恢复.
+pub struct FecEncoder {
+    config: Ulpfec,
+    sequence_number: u16,
+    group: Vec<Vec<u8>>, // 组内 rtp 包的字节, 第一个包的序号为 SN base
+}
+
+impl FecEncoder {
+    pub fn new(config: Ulpfec) -> Self {
+        let group_size = config.group_size.clamp(1, MAX_GROUP_SIZE);
+        Self {
+            config: Ulpfec { group_size, ..config },
+            sequence_number: rand::random(),
+            group: Vec::with_capacity(group_size as usize),
+        }
+    }
+
+    /// 记录一个已发送的媒体包, 分组结束时返回 FEC 包.
+    pub fn on_sent(&mut self, packet: &RtpPacket) -> Option<RtpPacket> {
+        self.group.push(packet.to_bytes());
+        if self.group.len() < self.config.group_size as usize && !packet.marker {
+            return None;
+        }
+        let fec = self.encode(packet.timestamp);
+        self.group.clear();
+        Some(fec)
+    }
+
+    /// FEC 头 (RFC 5109 7.3) + level 0 头 (7.4) + 负载异或.
+    fn encode(&mut self, timestamp: u32) -> RtpPacket {
+        let protection_length = self.group.iter().map(|bytes| bytes.len() - RTP_HEADER_SIZE).max().unwrap_or(0);
+        let mut recovery = [0u8; 8];
+        let mut length_recovery = 0u16;
+        let mut payload = vec![0u8; protection_length];
+        for bytes in &self.group {
+            for (r, b) in recovery.iter_mut().zip(&bytes[..8]) {
+                *r ^= b;
+            }
+            length_recovery ^= (bytes.len() - RTP_HEADER_SIZE) as u16;
+            for (p, b) in payload.iter_mut().zip(&bytes[RTP_HEADER_SIZE..]) {
+                *p ^= b;
+            }
+        }
+        let sn_base = u16::from_be_bytes([self.group[0][2], self.group[0][3]]);
+        let mask = !0u16 << (16 - self.group.len());
+
+        let mut fec = RtpPacket::new(self.config.payload_type, self.sequence_number, timestamp, self.config.ssrc, false);
+        // E=0, L=0, 其余为 P/X/CC, M/PT 的异或
+        fec.payload.push(recovery[0] & 0x3f);
+        fec.payload.push(recovery[1]);
+        fec.payload.extend(sn_base.to_be_bytes());
+        fec.payload.extend(&recovery[4..8]);
+        fec.payload.extend(length_recovery.to_be_bytes());
+        fec.payload.extend((protection_length as u16).to_be_bytes());
+        fec.payload.extend(mask.to_be_bytes());
+        fec.payload.extend(payload);
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        fec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(sequence_number: u16, timestamp: u32, len: usize, marker: bool) -> RtpPacket {
+        let mut packet = RtpPacket::new(96, sequence_number, timestamp, 1234, marker);
+        packet.payload = (0..len).map(|i| (i as u8).wrapping_mul(sequence_number as u8)).collect();
+        packet
+    }
+
+    #[test]
+    fn test_recover_lost_packet() {
+        let mut encoder = FecEncoder::new(Ulpfec { payload_type: 99, ssrc: 5678, group_size: 3 });
+        let packets = [packet(100, 3000, 20, false), packet(101, 3000, 7, false), packet(102, 3000, 13, false)];
+        assert!(encoder.on_sent(&packets[0]).is_none());
+        assert!(encoder.on_sent(&packets[1]).is_none());
+        let fec = encoder.on_sent(&packets[2]).unwrap();
+        assert_eq!(fec.payload_type, 99);
+        assert_eq!(fec.ssrc, 5678);
+
+        let header = &fec.payload[..14];
+        assert_eq!(&header[2..4], &100u16.to_be_bytes());
+        assert_eq!(&header[10..12], &20u16.to_be_bytes()); // protection length
+        assert_eq!(&header[12..14], &[0b1110_0000, 0]);
+
+        // 丢失 101: 用 FEC 和其余两个包异或恢复
+        let mut length = u16::from_be_bytes([header[8], header[9]]);
+        let mut payload = fec.payload[14..].to_vec();
+        let mut header_recovery = [header[0], header[1]];
+        for received in [&packets[0], &packets[2]] {
+            let bytes = received.to_bytes();
+            length ^= (bytes.len() - 12) as u16;
+            header_recovery[0] ^= bytes[0];
+            header_recovery[1] ^= bytes[1];
+            for (p, b) in payload.iter_mut().zip(&bytes[12..]) {
+                *p ^= b;
+            }
+        }
+        assert_eq!(length, 7);
+        assert_eq!(header_recovery[1], 96);
+        assert_eq!(&payload[..length as usize], packets[1].payload.as_slice());
+    }
+
+    #[test]
+    fn test_marker_ends_group() {
+        let mut encoder = FecEncoder::new(Ulpfec { payload_type: 99, ssrc: 5678, group_size: 40 });
+        for seq in 0..15 {
+            assert!(encoder.on_sent(&packet(seq, 0, 10, false)).is_none());
+        }
+        let fec = encoder.on_sent(&packet(15, 0, 10, false)).unwrap();
+        assert_eq!(&fec.payload[12..14], &[0xff, 0xff]);
+
+        let fec = encoder.on_sent(&packet(16, 3600, 10, true)).unwrap();
+        assert_eq!(&fec.payload[12..14], &[0x80, 0]);
+        assert_eq!(fec.timestamp, 3600);
+        assert!(!fec.marker);
+    }
+}
@@ -1,3 +1,11 @@
 pub mod rtp_packet;
 pub mod rtp_h264;
-pub mod rtp_h265;
\ No newline at end of file
+pub mod rtp_h265;
+pub mod rtcp;
+pub mod qos;
+pub mod retransmit;
+pub mod fec;
+pub mod extension;
+pub mod depacketizer;
+pub mod pacer;
+pub mod transport;
@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+
+/// 落后超过该时长时, [`LatePolicy::Skip`] 放弃追赶.
+pub const MAX_LATENESS: Duration = Duration::from_millis(200);
+/// 单次等待的上限, 超过时认为时间戳不连续, 重新对齐.
+const MAX_WAIT: Duration = Duration::from_secs(1);
+
+/// 单调时钟, 测试时可以替换.
+pub trait MediaClock: Send {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl MediaClock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// 发送落后于计划时间时的处理方式.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LatePolicy {
+    /// 不等待地连续发送, 直到追上计划时间
+    #[default]
+    CatchUp,
+    /// 落后超过 MAX_LATENESS 时从当前时间重新计划, 放弃落后的时间
+    Skip,
+}
+
+/// 按 rtp 时间戳控制发送节奏: 每个访问单元的第一个包在其显示时间发送.
+///
+/// 显示时间由上一个访问单元的计划时间加上时间戳差得到, 时间戳回绕时也连续.
+pub struct Pacer {
+    clock: Box<dyn MediaClock>,
+    clock_rate: u32,
+    policy: LatePolicy,
+    last: Option<(u32, Instant)>, // 上一个访问单元的时间戳和计划发送时间
+}
+
+impl Pacer {
+    pub fn new(clock: Box<dyn MediaClock>, clock_rate: u32, policy: LatePolicy) -> Self {
+        Self { clock, clock_rate, policy, last: None }
+    }
+
+    /// 发送时间戳为 `timestamp` 的包之前调用, 新的访问单元未到时间时等待.
+    pub fn wait(&mut self, timestamp: u32) {
+        let now = self.clock.now();
+        let Some((last_timestamp, last_due)) = self.last else {
+            self.last = Some((timestamp, now));
+            return;
+        };
+        if timestamp == last_timestamp {
+            return;
+        }
+
+        let delta = timestamp.wrapping_sub(last_timestamp) as i32;
+        let due = last_due + Duration::from_secs_f64(delta.max(0) as f64 / self.clock_rate as f64);
+        if delta < 0 || due.saturating_duration_since(now) > MAX_WAIT {
+            log::debug!("rtp timestamp jumped from {} to {}, resync pacing", last_timestamp, timestamp);
+            self.last = Some((timestamp, now));
+            return;
+        }
+
+        if due > now {
+            self.clock.sleep(due - now);
+        } else if self.policy == LatePolicy::Skip && now - due > MAX_LATENESS {
+            log::debug!("sending {:?} behind schedule, skip ahead", now - due);
+            self.last = Some((timestamp, now));
+            return;
+        }
+        self.last = Some((timestamp, due));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// 手动推进的时钟, sleep 直接前进并记录等待时长.
+    #[derive(Clone)]
+    struct FakeClock {
+        now: Arc<Mutex<Instant>>,
+        sleeps: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl FakeClock {
+        fn new() -> Self {
+            Self { now: Arc::new(Mutex::new(Instant::now())), sleeps: Default::default() }
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+
+        fn take_sleeps(&self) -> Vec<Duration> {
+            std::mem::take(&mut self.sleeps.lock().unwrap())
+        }
+    }
+
+    impl MediaClock for FakeClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.advance(duration);
+            self.sleeps.lock().unwrap().push(duration);
+        }
+    }
+
+    const FRAME: Duration = Duration::from_millis(40);
+
+    #[test]
+    fn test_paces_by_timestamp() {
+        let clock = FakeClock::new();
+        let mut pacer = Pacer::new(Box::new(clock.clone()), 90000, LatePolicy::CatchUp);
+        pacer.wait(u32::MAX - 999);
+        // 同一访问单元的其余包不等待
+        pacer.wait(u32::MAX - 999);
+        // 处理耗时 10ms, 再等 30ms; 时间戳回绕
+        clock.advance(Duration::from_millis(10));
+        pacer.wait(2600);
+        assert_eq!(clock.take_sleeps(), vec![Duration::from_millis(30)]);
+        pacer.wait(6200);
+        assert_eq!(clock.take_sleeps(), vec![FRAME]);
+    }
+
+    #[test]
+    fn test_late_policies() {
+        let clock = FakeClock::new();
+        let mut catch_up = Pacer::new(Box::new(clock.clone()), 90000, LatePolicy::CatchUp);
+        catch_up.wait(0);
+        clock.advance(Duration::from_millis(500));
+        // 落后 460ms: 连续发送, 之后按原计划
+        for frame in 1..=12 {
+            catch_up.wait(frame * 3600);
+        }
+        assert!(clock.take_sleeps().is_empty());
+        catch_up.wait(13 * 3600);
+        assert_eq!(clock.take_sleeps(), vec![Duration::from_millis(20)]);
+
+        let clock = FakeClock::new();
+        let mut skip = Pacer::new(Box::new(clock.clone()), 90000, LatePolicy::Skip);
+        skip.wait(0);
+        clock.advance(Duration::from_millis(500));
+        skip.wait(3600);
+        skip.wait(7200);
+        assert_eq!(clock.take_sleeps(), vec![FRAME]);
+    }
+
+    #[test]
+    fn test_resync_on_jump() {
+        let clock = FakeClock::new();
+        let mut pacer = Pacer::new(Box::new(clock.clone()), 90000, LatePolicy::CatchUp);
+        pacer.wait(90000 * 10);
+        pacer.wait(0);
+        pacer.wait(90000 * 20);
+        assert!(clock.take_sleeps().is_empty());
+        pacer.wait(90000 * 20 + 3600);
+        assert_eq!(clock.take_sleeps(), vec![FRAME]);
+    }
+}
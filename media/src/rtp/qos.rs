@@ -0,0 +1,176 @@
+use std::time::{Duration, SystemTime};
+
+use super::retransmit::parse_generic_nack;
+use super::rtcp::{ntp_middle, ntp_timestamp, parse_compound, ReportBlock, RtcpError, RtcpPacket, RTCP_PT_PSFB, RTCP_PT_RTPFB, SDES_CNAME};
+
+/// 客户端的接收质量, 由其发来的 RTCP 更新.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QosStats {
+    pub cname: Option<String>,
+    pub fraction_lost: f32,     // 最近一个报告周期的丢包率, 0.0 ~ 1.0
+    pub cumulative_lost: i32,
+    pub highest_seq: u32,
+    pub jitter: Duration,
+    pub rtt: Option<Duration>,  // 由 RR 的 LSR/DLSR 计算, 客户端还没收到过 SR 时为空
+    pub reports: u64,           // 收到的接收报告块个数
+    pub nacks: u64,             // Generic NACK 个数
+    pub keyframe_requests: u64, // PLI/FIR 个数
+    pub bye: bool,
+    pub last_report: Option<SystemTime>,
+}
+
+impl QosStats {
+    /// 处理一个 RTCP 复合包, 只统计针对 `ssrc` 的报告块; 返回 NACK 请求重传的序号.
+    pub fn on_rtcp(&mut self, data: &[u8], ssrc: u32, clock_rate: u32, now: SystemTime) -> Result<Vec<u16>, RtcpError> {
+        let mut lost = Vec::new();
+        for packet in parse_compound(data)? {
+            match packet {
+                RtcpPacket::ReceiverReport { blocks, .. } | RtcpPacket::SenderReport { blocks, .. } => {
+                    for block in blocks.iter().filter(|block| block.ssrc == ssrc) {
+                        self.on_report_block(block, clock_rate, now);
+                    }
+                }
+                RtcpPacket::SourceDescription(chunks) => {
+                    let cname = chunks
+                        .into_iter()
+                        .flat_map(|(_, items)| items)
+                        .find(|(item_type, _)| *item_type == SDES_CNAME);
+                    if let Some((_, cname)) = cname {
+                        self.cname = Some(cname);
+                    }
+                }
+                RtcpPacket::Bye { reason, .. } => {
+                    log::info!("rtcp bye from {:?}: {:?}", self.cname, reason);
+                    self.bye = true;
+                }
+                RtcpPacket::Feedback { packet_type: RTCP_PT_RTPFB, fmt: 1, media_ssrc, fci, .. } if media_ssrc == ssrc => {
+                    self.nacks += 1;
+                    lost.extend(parse_generic_nack(&fci));
+                }
+                RtcpPacket::Feedback { packet_type: RTCP_PT_PSFB, fmt: 1 | 4, .. } => {
+                    self.keyframe_requests += 1;
+                }
+                RtcpPacket::App { name, .. } => {
+                    log::debug!("rtcp app {:?}", String::from_utf8_lossy(&name));
+                }
+                _ => {}
+            }
+        }
+        Ok(lost)
+    }
+
+    fn on_report_block(&mut self, block: &ReportBlock, clock_rate: u32, now: SystemTime) {
+        self.fraction_lost = block.fraction_lost as f32 / 256.0;
+        self.cumulative_lost = block.cumulative_lost;
+        self.highest_seq = block.highest_seq;
+        self.jitter = Duration::from_secs_f64(block.jitter as f64 / clock_rate as f64);
+        // RTT = A - LSR - DLSR (RFC 3550 6.4.1), 单位 1/65536 秒
+        if block.lsr != 0 {
+            let arrival = ntp_middle(ntp_timestamp(now));
+            let rtt = arrival.wrapping_sub(block.lsr).wrapping_sub(block.dlsr);
+            // 时钟回绕或报告异常时差值会非常大, 丢弃
+            if rtt < 0x8000_0000 {
+                self.rtt = Some(Duration::from_secs_f64(rtt as f64 / 65536.0));
+            }
+        }
+        self.reports += 1;
+        self.last_report = Some(now);
+        log::info!(
+            "rtcp rr from {}: lost {:.1}% (total {}), jitter {:?}, rtt {:?}",
+            self.cname.as_deref().unwrap_or("-"),
+            self.fraction_lost * 100.0,
+            self.cumulative_lost,
+            self.jitter,
+            self.rtt
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    /// RR(1 个报告块) + SDES(CNAME) + PLI
+    fn receiver_report(ssrc: u32, lsr: u32, dlsr: u32) -> Vec<u8> {
+        let mut rr = vec![0x81, 201, 0, 7];
+        rr.extend(0xdeadbeefu32.to_be_bytes());
+        rr.extend(ssrc.to_be_bytes());
+        rr.extend([64, 0xff, 0xff, 0xfe]); // 25% 丢包, 累计 -2
+        rr.extend(70_000u32.to_be_bytes());
+        rr.extend(900u32.to_be_bytes());
+        rr.extend(lsr.to_be_bytes());
+        rr.extend(dlsr.to_be_bytes());
+
+        rr.extend([0x81, 202, 0, 3]);
+        rr.extend(0xdeadbeefu32.to_be_bytes());
+        rr.extend([1, 5]);
+        rr.extend(b"alice");
+        rr.push(0);
+
+        rr.extend([0x81, 206, 0, 2]);
+        rr.extend(0xdeadbeefu32.to_be_bytes());
+        rr.extend(ssrc.to_be_bytes());
+        rr
+    }
+
+    #[test]
+    fn test_receiver_report_stats() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let arrival = ntp_middle(ntp_timestamp(now));
+        // SR 在 300ms 前发出, 客户端收到后 100ms 回报, RTT = 200ms
+        let lsr = arrival - (65536 * 3 / 10);
+        let dlsr = 65536 / 10;
+
+        let mut stats = QosStats::default();
+        stats.on_rtcp(&receiver_report(1234, lsr, dlsr), 1234, 90000, now).unwrap();
+        assert_eq!(stats.cname.as_deref(), Some("alice"));
+        assert_eq!(stats.fraction_lost, 0.25);
+        assert_eq!(stats.cumulative_lost, -2);
+        assert_eq!(stats.highest_seq, 70_000);
+        assert_eq!(stats.jitter, Duration::from_millis(10));
+        let rtt = stats.rtt.unwrap();
+        assert!(rtt > Duration::from_millis(199) && rtt < Duration::from_millis(201), "{:?}", rtt);
+        assert_eq!(stats.reports, 1);
+        assert_eq!(stats.keyframe_requests, 1);
+
+        // 其他源的报告块不统计
+        let mut other = QosStats::default();
+        other.on_rtcp(&receiver_report(99, 0, 0), 1234, 90000, now).unwrap();
+        assert_eq!(other.reports, 0);
+        assert_eq!(other.rtt, None);
+    }
+
+    #[test]
+    fn test_parse_bye_app_and_nack() {
+        let mut data = vec![0x81, 203, 0, 3];
+        data.extend(1234u32.to_be_bytes());
+        data.extend([4]);
+        data.extend(b"done");
+        data.extend([0, 0, 0]);
+        data.extend([0x80 | 3, 204, 0, 3]);
+        data.extend(1234u32.to_be_bytes());
+        data.extend(b"TEST");
+        data.extend([1, 2, 3, 4]);
+        data.extend([0x81, 205, 0, 3]);
+        data.extend(1u32.to_be_bytes());
+        data.extend(1234u32.to_be_bytes());
+        data.extend([0, 10, 0, 0]);
+
+        let packets = parse_compound(&data).unwrap();
+        assert_eq!(packets[0], RtcpPacket::Bye { sources: vec![1234], reason: Some("done".to_string()) });
+        assert_eq!(packets[1], RtcpPacket::App { subtype: 3, ssrc: 1234, name: *b"TEST", data: vec![1, 2, 3, 4] });
+        assert_eq!(
+            packets[2],
+            RtcpPacket::Feedback { packet_type: 205, fmt: 1, sender_ssrc: 1, media_ssrc: 1234, fci: vec![0, 10, 0, 0] }
+        );
+
+        let mut stats = QosStats::default();
+        assert_eq!(stats.on_rtcp(&data, 1234, 90000, UNIX_EPOCH).unwrap(), vec![10]);
+        assert!(stats.bye);
+        assert_eq!(stats.nacks, 1);
+
+        assert_eq!(parse_compound(&data[..10]), Err(RtcpError::Truncated));
+        assert_eq!(parse_compound(&[0x40, 201, 0, 0]), Err(RtcpError::InvalidVersion(1)));
+    }
+}
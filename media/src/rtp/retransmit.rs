@@ -0,0 +1,222 @@
+use std::{collections::HashSet, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use super::rtp_packet::RtpPacket;
+
+/// 缓存最近发送的包数, 按 25fps, 每帧十几个包计算约一秒.
+pub const HISTORY_SIZE: usize = 512;
+
+/// 同一个序号两次重传的最小间隔, 约一个 RTT, 避免重复的 NACK 放大流量.
+pub const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+
+/// RTCP 接收线程收到的 NACK 序号, 由 rtp 发送线程取走重传.
+pub type NackQueue = Arc<Mutex<PendingNacks>>;
+
+/// 等待重传的序号, 去重, 最多 HISTORY_SIZE 个, 更早的包已经不在历史记录中.
+#[derive(Debug, Default)]
+pub struct PendingNacks {
+    sequence_numbers: Vec<u16>,
+    queued: HashSet<u16>,
+}
+
+impl PendingNacks {
+    /// 加入 NACK 的序号, 已在队列中的忽略, 队列满后丢弃.
+    pub fn extend(&mut self, lost: impl IntoIterator<Item = u16>) {
+        for sequence_number in lost {
+            if self.sequence_numbers.len() >= HISTORY_SIZE {
+                log::debug!("nack queue full, drop the rest");
+                break;
+            }
+            if self.queued.insert(sequence_number) {
+                self.sequence_numbers.push(sequence_number);
+            }
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<u16> {
+        self.queued.clear();
+        std::mem::take(&mut self.sequence_numbers)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequence_numbers.is_empty()
+    }
+}
+
+/// 丢包重传方式.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Retransmission {
+    #[default]
+    Off,
+    /// 用原来的 ssrc 和序号重发
+    InStream,
+    /// RFC 4588, 用单独的负载类型和 ssrc 发送, 负载前加 2 字节原始序号
+    Rtx { payload_type: u8, ssrc: u32 },
+}
+
+/// 最近发送的 rtp 包, 按序号取模存放.
+pub struct PacketHistory {
+    packets: Vec<Option<RtpPacket>>,
+}
+
+impl PacketHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self { packets: vec![None; capacity] }
+    }
+
+    pub fn push(&mut self, packet: &RtpPacket) {
+        let index = packet.sequence_number as usize % self.packets.len();
+        self.packets[index] = Some(packet.clone());
+    }
+
+    /// 已被更新的包覆盖时返回 None.
+    pub fn get(&self, sequence_number: u16) -> Option<&RtpPacket> {
+        self.packets[sequence_number as usize % self.packets.len()]
+            .as_ref()
+            .filter(|packet| packet.sequence_number == sequence_number)
+    }
+}
+
+/// 解析 Generic NACK 的 FCI (RFC 4585 6.2.1), 返回丢失的序号.
+///
+/// 每 4 字节一项: PID 为丢失包的序号, BLP 的第 i 位表示 PID + i + 1 也丢失.
+pub fn parse_generic_nack(fci: &[u8]) -> Vec<u16> {
+    let mut lost = Vec::new();
+    for item in fci.chunks_exact(4) {
+        let pid = u16::from_be_bytes([item[0], item[1]]);
+        let blp = u16::from_be_bytes([item[2], item[3]]);
+        lost.push(pid);
+        for i in 0..16 {
+            if blp & (1 << i) != 0 {
+                lost.push(pid.wrapping_add(i + 1));
+            }
+        }
+    }
+    lost
+}
+
+/// 按 NACK 从历史记录中取出要重发的包, 同一个包在 RESEND_INTERVAL 内只重发一次.
+pub struct Retransmitter {
+    mode: Retransmission,
+    history: PacketHistory,
+    resent: Vec<Option<(u16, Instant)>>, // 与 history 同样按序号取模, 记录上次重传时间
+    rtx_sequence_number: u16,
+}
+
+impl Retransmitter {
+    pub fn new(mode: Retransmission, capacity: usize) -> Self {
+        Self {
+            mode,
+            history: PacketHistory::new(capacity),
+            resent: vec![None; capacity],
+            rtx_sequence_number: rand::random(),
+        }
+    }
+
+    pub fn on_sent(&mut self, packet: &RtpPacket) {
+        self.history.push(packet);
+    }
+
+    pub fn retransmit(&mut self, lost: &[u16], now: Instant) -> Vec<RtpPacket> {
+        let mut packets = Vec::new();
+        for &sequence_number in lost {
+            let Some(original) = self.history.get(sequence_number) else {
+                log::debug!("nack for {} too old to retransmit", sequence_number);
+                continue;
+            };
+            let index = sequence_number as usize % self.resent.len();
+            let resent = &mut self.resent[index];
+            if matches!(*resent, Some((seq, at)) if seq == sequence_number && now.duration_since(at) < RESEND_INTERVAL) {
+                continue;
+            }
+            *resent = Some((sequence_number, now));
+            match self.mode {
+                Retransmission::Off => {}
+                Retransmission::InStream => packets.push(original.clone()),
+                Retransmission::Rtx { payload_type, ssrc } => {
+                    let mut rtx = RtpPacket::new(payload_type, self.rtx_sequence_number, original.timestamp, ssrc, original.marker);
+                    rtx.payload.extend(original.sequence_number.to_be_bytes());
+                    rtx.payload.extend(&original.payload);
+                    self.rtx_sequence_number = self.rtx_sequence_number.wrapping_add(1);
+                    packets.push(rtx);
+                }
+            }
+        }
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(sequence_number: u16) -> RtpPacket {
+        let mut packet = RtpPacket::new(96, sequence_number, 3000, 1234, false);
+        packet.payload = vec![sequence_number as u8; 10];
+        packet
+    }
+
+    #[test]
+    fn test_parse_generic_nack() {
+        assert_eq!(parse_generic_nack(&[0, 10, 0, 0]), vec![10]);
+        assert_eq!(parse_generic_nack(&[0, 10, 0b1000_0000, 0b0000_0101]), vec![10, 11, 13, 26]);
+        assert_eq!(parse_generic_nack(&[0xff, 0xff, 0, 1, 0, 5, 0, 0]), vec![65535, 0, 5]);
+    }
+
+    #[test]
+    fn test_history_wraps() {
+        let mut history = PacketHistory::new(4);
+        for seq in 0..6 {
+            history.push(&packet(seq));
+        }
+        assert!(history.get(1).is_none());
+        assert_eq!(history.get(5).unwrap().sequence_number, 5);
+    }
+
+    #[test]
+    fn test_retransmit_in_stream_and_rtx() {
+        let mut in_stream = Retransmitter::new(Retransmission::InStream, 16);
+        in_stream.on_sent(&packet(7));
+        let packets = in_stream.retransmit(&[7, 8], Instant::now());
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].to_bytes(), packet(7).to_bytes());
+
+        let mut rtx = Retransmitter::new(Retransmission::Rtx { payload_type: 98, ssrc: 5678 }, 16);
+        rtx.on_sent(&packet(7));
+        rtx.on_sent(&packet(8));
+        let packets = rtx.retransmit(&[7, 8], Instant::now());
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].payload_type, 98);
+        assert_eq!(packets[0].ssrc, 5678);
+        assert_eq!(packets[0].timestamp, 3000);
+        assert_eq!(&packets[0].payload[..2], &[0, 7]);
+        assert_eq!(&packets[0].payload[2..], &[7u8; 10]);
+        assert_eq!(packets[1].sequence_number, packets[0].sequence_number.wrapping_add(1));
+    }
+
+    #[test]
+    fn test_repeated_nack_resent_once() {
+        let mut retransmitter = Retransmitter::new(Retransmission::InStream, 16);
+        retransmitter.on_sent(&packet(7));
+        // 同一个 PID 重复多次的 NACK
+        let fci: Vec<u8> = [0u8, 7, 0, 0].repeat(300);
+        let mut queue = PendingNacks::default();
+        queue.extend(parse_generic_nack(&fci));
+        let lost = queue.take();
+        assert_eq!(lost, vec![7]);
+
+        let now = Instant::now();
+        assert_eq!(retransmitter.retransmit(&[7, 7, 7], now).len(), 1);
+        assert!(retransmitter.retransmit(&lost, now + RESEND_INTERVAL / 2).is_empty());
+        assert_eq!(retransmitter.retransmit(&lost, now + RESEND_INTERVAL).len(), 1);
+    }
+
+    #[test]
+    fn test_pending_nacks_bounded() {
+        let mut queue = PendingNacks::default();
+        // PID + 满 BLP, 每项 17 个序号
+        let fci: Vec<u8> = (0..1000u16).flat_map(|i| [(i * 17).to_be_bytes(), [0xff, 0xff]].concat()).collect();
+        queue.extend(parse_generic_nack(&fci));
+        assert_eq!(queue.take().len(), HISTORY_SIZE);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::rtp_packet::RtpPacket;
+
+pub const RTCP_PT_SR: u8 = 200;
+pub const RTCP_PT_RR: u8 = 201;
+pub const RTCP_PT_SDES: u8 = 202;
+pub const RTCP_PT_BYE: u8 = 203;
+pub const RTCP_PT_APP: u8 = 204;
+pub const RTCP_PT_RTPFB: u8 = 205; // 传输层反馈, 如 Generic NACK (RFC 4585)
+pub const RTCP_PT_PSFB: u8 = 206;  // 负载相关反馈, 如 PLI/FIR
+
+pub const SDES_CNAME: u8 = 1;
+/// 1900-01-01 到 1970-01-01 的秒数.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+/// RFC 3550 6.2 建议的最小发送间隔.
+pub const RTCP_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 64 位 NTP 时间戳, 高 32 位为秒, 低 32 位为秒的小数部分.
+pub fn ntp_timestamp(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).expect("Time went backwards");
+    let secs = since_epoch.as_secs() + NTP_UNIX_OFFSET;
+    let frac = ((since_epoch.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    (secs << 32) | frac
+}
+
+/// NTP 时间戳的中间 32 位, 即 SR 中的 LSR 和计算 RTT 用的单位 (1/65536 秒).
+pub fn ntp_middle(ntp: u64) -> u32 {
+    (ntp >> 16) as u32
+}
+
+/// RTCP 公共头, `count` 为 RC/SC 字段, `length` 为整个包的 32 位字数减一.
+fn header(count: u8, packet_type: u8, len_bytes: usize) -> [u8; 4] {
+    let length = (len_bytes / 4 - 1) as u16;
+    let length = length.to_be_bytes();
+    [0x80 | (count & 0x1f), packet_type, length[0], length[1]]
+}
+
+// Sender Report (RFC 3550 6.4.1), 不带接收报告块
+//+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//|V=2|P|    RC   |   PT=SR=200   |             length            |
+//+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//|                         SSRC of sender                        |
+//+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+
+//|              NTP timestamp, most significant word             |
+//|             NTP timestamp, least significant word             |
+//|                         RTP timestamp                         |
+//|                     sender's packet count                     |
+//|                      sender's octet count                     |
+//+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+=+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SenderReport {
+    pub ssrc: u32,
+    pub ntp_timestamp: u64,
+    pub rtp_timestamp: u32,
+    pub packet_count: u32,
+    pub octet_count: u32,
+}
+
+impl SenderReport {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(28);
+        bytes.extend(header(0, RTCP_PT_SR, 28));
+        bytes.extend(self.ssrc.to_be_bytes());
+        bytes.extend(self.ntp_timestamp.to_be_bytes());
+        bytes.extend(self.rtp_timestamp.to_be_bytes());
+        bytes.extend(self.packet_count.to_be_bytes());
+        bytes.extend(self.octet_count.to_be_bytes());
+        bytes
+    }
+}
+
+/// 只带 CNAME 的 SDES 包, 每个 SR 都要跟一个 (RFC 3550 6.1).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceDescription {
+    pub ssrc: u32,
+    pub cname: String,
+}
+
+impl SourceDescription {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let cname = &self.cname.as_bytes()[..self.cname.len().min(255)];
+        let mut chunk = Vec::new();
+        chunk.extend(self.ssrc.to_be_bytes());
+        chunk.push(SDES_CNAME);
+        chunk.push(cname.len() as u8);
+        chunk.extend(cname);
+        // 以 END(0) 结束, 并补齐到 32 位边界
+        chunk.push(0);
+        while chunk.len() % 4 != 0 {
+            chunk.push(0);
+        }
+
+        let mut bytes = Vec::with_capacity(4 + chunk.len());
+        bytes.extend(header(1, RTCP_PT_SDES, 4 + chunk.len()));
+        bytes.extend(chunk);
+        bytes
+    }
+}
+
+/// 统计已发送的 rtp 包, 定期生成 SR + SDES 复合包.
+///
+/// SR 中的 rtp 时间戳由最近一次发送的包按时钟频率外推到生成报告的时刻,
+/// 接收端据此把 rtp 时间戳映射到发送端的墙上时钟, 用于音视频同步和时延测量.
+#[derive(Debug)]
+pub struct RtcpSender {
+    cname: String,
+    clock_rate: u32,
+    interval: Duration,
+    ssrc: u32,
+    packet_count: u32,
+    octet_count: u32,
+    last_rtp: Option<(u32, Instant)>, // 最近发送的 rtp 时间戳及发送时刻
+    last_report: Option<Instant>,
+}
+
+impl RtcpSender {
+    pub fn new(cname: String, clock_rate: u32) -> Self {
+        Self {
+            cname,
+            clock_rate,
+            interval: RTCP_REPORT_INTERVAL,
+            ssrc: 0,
+            packet_count: 0,
+            octet_count: 0,
+            last_rtp: None,
+            last_report: None,
+        }
+    }
+
+    pub fn packet_count(&self) -> u32 {
+        self.packet_count
+    }
+
+    pub fn octet_count(&self) -> u32 {
+        self.octet_count
+    }
+
+    /// 每发送一个 rtp 包调用一次, 八位组计数只算负载.
+    pub fn on_rtp_sent(&mut self, packet: &RtpPacket, now: Instant) {
+        self.ssrc = packet.ssrc;
+        self.packet_count = self.packet_count.wrapping_add(1);
+        self.octet_count = self.octet_count.wrapping_add(packet.payload.len() as u32);
+        self.last_rtp = Some((packet.timestamp, now));
+    }
+
+    /// 发出第一个 rtp 包后立即发送一次, 之后每隔 `interval` 发送.
+    pub fn report_due(&self, now: Instant) -> bool {
+        self.last_rtp.is_some()
+            && self.last_report.is_none_or(|last| now.duration_since(last) >= self.interval)
+    }
+
+    /// 生成 SR + SDES 复合包, `wallclock` 与 `now` 应为同一时刻.
+    pub fn report(&mut self, now: Instant, wallclock: SystemTime) -> Option<Vec<u8>> {
+        let (last_timestamp, sent_at) = self.last_rtp?;
+        let elapsed = now.saturating_duration_since(sent_at);
+        let ticks = (elapsed.as_nanos() * self.clock_rate as u128 / 1_000_000_000) as u32;
+        let sr = SenderReport {
+            ssrc: self.ssrc,
+            ntp_timestamp: ntp_timestamp(wallclock),
+            rtp_timestamp: last_timestamp.wrapping_add(ticks),
+            packet_count: self.packet_count,
+            octet_count: self.octet_count,
+        };
+        let sdes = SourceDescription { ssrc: self.ssrc, cname: self.cname.clone() };
+        self.last_report = Some(now);
+
+        let mut bytes = sr.to_bytes();
+        bytes.extend(sdes.to_bytes());
+        Some(bytes)
+    }
+}
+
+/// 接收报告块, SR 和 RR 中每个被报告的源一个.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportBlock {
+    pub ssrc: u32,
+    pub fraction_lost: u8,    // 上次报告以来的丢包率, 定点数 x/256
+    pub cumulative_lost: i32, // 24 位有符号数, 重复包可能使其为负
+    pub highest_seq: u32,     // 扩展的最高序号
+    pub jitter: u32,          // 到达间隔抖动, rtp 时间戳单位
+    pub lsr: u32,             // 最近收到的 SR 的 NTP 中间 32 位
+    pub dlsr: u32,            // 从收到该 SR 到发送本报告的延时, 1/65536 秒
+}
+
+impl ReportBlock {
+    fn parse(b: &[u8]) -> Self {
+        let word = |i: usize| u32::from_be_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+        // 符号扩展 24 位累计丢包数
+        let cumulative_lost = ((word(4) << 8) as i32) >> 8;
+        Self {
+            ssrc: word(0),
+            fraction_lost: b[4],
+            cumulative_lost,
+            highest_seq: word(8),
+            jitter: word(12),
+            lsr: word(16),
+            dlsr: word(20),
+        }
+    }
+}
+
+/// 解析出的 RTCP 包.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RtcpPacket {
+    SenderReport { report: SenderReport, blocks: Vec<ReportBlock> },
+    ReceiverReport { ssrc: u32, blocks: Vec<ReportBlock> },
+    SourceDescription(Vec<(u32, Vec<(u8, String)>)>), // ssrc -> [(item 类型, 内容)]
+    Bye { sources: Vec<u32>, reason: Option<String> },
+    App { subtype: u8, ssrc: u32, name: [u8; 4], data: Vec<u8> },
+    /// RTPFB/PSFB, `fmt` 区分具体类型, `fci` 为反馈控制信息
+    Feedback { packet_type: u8, fmt: u8, sender_ssrc: u32, media_ssrc: u32, fci: Vec<u8> },
+    Unknown { packet_type: u8 },
+}
+
+/// RTCP 解析失败的原因.
+#[derive(Debug, PartialEq)]
+pub enum RtcpError {
+    Truncated,
+    InvalidVersion(u8),
+}
+
+/// 解析 RTCP 复合包.
+pub fn parse_compound(mut data: &[u8]) -> Result<Vec<RtcpPacket>, RtcpError> {
+    let mut packets = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 4 {
+            return Err(RtcpError::Truncated);
+        }
+        let version = data[0] >> 6;
+        if version != 2 {
+            return Err(RtcpError::InvalidVersion(version));
+        }
+        let padding = data[0] & 0x20 != 0;
+        let count = data[0] & 0x1f;
+        let packet_type = data[1];
+        let len = (u16::from_be_bytes([data[2], data[3]]) as usize + 1) * 4;
+        if data.len() < len {
+            return Err(RtcpError::Truncated);
+        }
+        let mut body = &data[4..len];
+        if padding {
+            let pad = *body.last().ok_or(RtcpError::Truncated)? as usize;
+            body = body.get(..body.len().checked_sub(pad).ok_or(RtcpError::Truncated)?).ok_or(RtcpError::Truncated)?;
+        }
+        packets.push(parse_packet(packet_type, count, body)?);
+        data = &data[len..];
+    }
+    Ok(packets)
+}
+
+fn parse_packet(packet_type: u8, count: u8, body: &[u8]) -> Result<RtcpPacket, RtcpError> {
+    let word = |i: usize| -> Result<u32, RtcpError> {
+        body.get(i..i + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or(RtcpError::Truncated)
+    };
+    let blocks = |offset: usize| -> Result<Vec<ReportBlock>, RtcpError> {
+        (0..count as usize)
+            .map(|i| {
+                let start = offset + i * 24;
+                body.get(start..start + 24).map(ReportBlock::parse).ok_or(RtcpError::Truncated)
+            })
+            .collect()
+    };
+
+    let packet = match packet_type {
+        RTCP_PT_SR => RtcpPacket::SenderReport {
+            report: SenderReport {
+                ssrc: word(0)?,
+                ntp_timestamp: (word(4)? as u64) << 32 | word(8)? as u64,
+                rtp_timestamp: word(12)?,
+                packet_count: word(16)?,
+                octet_count: word(20)?,
+            },
+            blocks: blocks(24)?,
+        },
+        RTCP_PT_RR => RtcpPacket::ReceiverReport { ssrc: word(0)?, blocks: blocks(4)? },
+        RTCP_PT_SDES => {
+            let mut chunks = Vec::new();
+            let mut pos = 0;
+            for _ in 0..count {
+                let ssrc = word(pos)?;
+                pos += 4;
+                let mut items = Vec::new();
+                loop {
+                    let item_type = *body.get(pos).ok_or(RtcpError::Truncated)?;
+                    if item_type == 0 {
+                        // END 之后补齐到 32 位边界
+                        pos = (pos + 4) & !3;
+                        break;
+                    }
+                    let len = *body.get(pos + 1).ok_or(RtcpError::Truncated)? as usize;
+                    let text = body.get(pos + 2..pos + 2 + len).ok_or(RtcpError::Truncated)?;
+                    items.push((item_type, String::from_utf8_lossy(text).into_owned()));
+                    pos += 2 + len;
+                }
+                chunks.push((ssrc, items));
+            }
+            RtcpPacket::SourceDescription(chunks)
+        }
+        RTCP_PT_BYE => {
+            let sources = (0..count as usize).map(|i| word(i * 4)).collect::<Result<Vec<_>, _>>()?;
+            let reason_at = count as usize * 4;
+            let reason = body.get(reason_at).map(|&len| {
+                let text = body.get(reason_at + 1..reason_at + 1 + len as usize).unwrap_or_default();
+                String::from_utf8_lossy(text).into_owned()
+            });
+            RtcpPacket::Bye { sources, reason }
+        }
+        RTCP_PT_APP => {
+            let name = body.get(4..8).ok_or(RtcpError::Truncated)?;
+            RtcpPacket::App {
+                subtype: count,
+                ssrc: word(0)?,
+                name: [name[0], name[1], name[2], name[3]],
+                data: body[8..].to_vec(),
+            }
+        }
+        RTCP_PT_RTPFB | RTCP_PT_PSFB => RtcpPacket::Feedback {
+            packet_type,
+            fmt: count,
+            sender_ssrc: word(0)?,
+            media_ssrc: word(4)?,
+            fci: body[8..].to_vec(),
+        },
+        _ => RtcpPacket::Unknown { packet_type },
+    };
+    Ok(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ntp_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_500);
+        let ntp = ntp_timestamp(time);
+        assert_eq!(ntp >> 32, NTP_UNIX_OFFSET + 1);
+        assert_eq!(ntp & 0xffff_ffff, 0x8000_0000);
+    }
+
+    #[test]
+    fn test_sender_report_and_sdes() {
+        let mut sender = RtcpSender::new("rtsp-server-42".to_string(), 90000);
+        let t0 = Instant::now();
+        assert!(!sender.report_due(t0));
+
+        let mut packet = RtpPacket::new(96, 0, 3000, 0x11223344, false);
+        packet.payload = vec![0u8; 100];
+        sender.on_rtp_sent(&packet, t0);
+        sender.on_rtp_sent(&packet, t0);
+        assert!(sender.report_due(t0));
+
+        let wallclock = UNIX_EPOCH + Duration::from_secs(1_000);
+        let report = sender.report(t0 + Duration::from_millis(40), wallclock).unwrap();
+        // SR 28 字节, SDES: 4 头 + 4 ssrc + 2 + 14 cname + 1 END, 补齐到 28
+        assert_eq!(report.len(), 28 + 28);
+        assert_eq!(&report[..4], &[0x80, 200, 0, 6]);
+        assert_eq!(&report[4..8], &0x11223344u32.to_be_bytes());
+        assert_eq!(&report[8..16], &ntp_timestamp(wallclock).to_be_bytes());
+        assert_eq!(&report[16..20], &(3000u32 + 3600).to_be_bytes()); // 外推 40ms
+        assert_eq!(&report[20..24], &2u32.to_be_bytes());
+        assert_eq!(&report[24..28], &200u32.to_be_bytes());
+
+        let sdes = &report[28..];
+        assert_eq!(&sdes[..4], &[0x81, 202, 0, 6]);
+        assert_eq!(&sdes[4..8], &0x11223344u32.to_be_bytes());
+        assert_eq!(sdes[8], SDES_CNAME);
+        assert_eq!(&sdes[10..10 + sdes[9] as usize], b"rtsp-server-42");
+        assert_eq!(sdes[24], 0);
+
+        assert!(!sender.report_due(t0 + Duration::from_secs(1)));
+        assert!(sender.report_due(t0 + Duration::from_secs(6)));
+    }
+}
@@ -1,25 +1,63 @@
 use std::fs::File;
-use std::io::{IoSlice, Seek, SeekFrom, Write};
+use std::io::{self, Seek, SeekFrom};
 use std::sync::Arc;
-use crate::codec::parse::NaluIterator;
+use crate::codec::{h264_sps::SpsRewrite, parse::NaluIterator};
 use crate::rtp::rtp_packet::RtpPacket;
+use crate::rtp::transport::RtpSender;
 
-use super::rtp_packet::{RtpSink, RTP_MAX_PACKET_SIZE};
+use super::rtp_packet::{frame_timestamp, RtpSink, RTP_MAX_PACKET_SIZE};
 
 const NALU_HEADER_SIZE: usize = 1;
+const STAP_A_HEADER_SIZE: usize = 1;
+const STAP_B_HEADER_SIZE: usize = 3; // 类型 + 2 字节 DON
+/// mode 0 不能分片, NAL 加上 rtp 头 (预留头扩展) 要放进一个 UDP 数据报或 interleaved 帧.
+const MAX_SINGLE_NAL_SIZE: usize = 65_507 - 12 - 64;
+
+/// RFC 6184 的 packetization-mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PacketizationMode {
+    /// 0: 只发送 single NAL, 超过 MTU 的 NAL 不分片
+    SingleNal = 0,
+    /// 1: single NAL, STAP-A, FU-A
+    #[default]
+    NonInterleaved = 1,
+    /// 2: STAP-B, FU-B/FU-A, 带 DON. 按解码顺序发送, 交织深度为 0
+    Interleaved = 2,
+}
+
+impl TryFrom<u8> for PacketizationMode {
+    type Error = u8;
+
+    fn try_from(mode: u8) -> Result<Self, Self::Error> {
+        match mode {
+            0 => Ok(PacketizationMode::SingleNal),
+            1 => Ok(PacketizationMode::NonInterleaved),
+            2 => Ok(PacketizationMode::Interleaved),
+            _ => Err(mode),
+        }
+    }
+}
 
 pub struct RtpSinkH264 {
     payload_type: u8,
     clock_rate: u32,
-    fps: u32,
+    fps: f64,
+    frames: u64, // 已发送的访问单元数, 用于计算时间戳
     packet: RtpPacket,
     ssrc: u32,
     filename: Arc<String>,
     infinite: bool,
+    pending: Vec<Vec<u8>>, // 尚未发送的 NAL, 多个时可以组成一个 STAP-A/STAP-B
+    access_unit: Vec<Vec<u8>>, // 当前访问单元的 NAL, 访问单元结束时发送
+    in_picture: bool,      // 当前访问单元已经有 VCL NAL
+    mode: PacketizationMode,
+    don: u16,              // 下一个 NAL 的解码顺序号, 仅 mode 2 使用
+    sps_rewrite: SpsRewrite,
+    rewritten_sps: Option<(Vec<u8>, Vec<u8>)>, // 上一次改写的 SPS 和结果, 文件循环播放时重复出现
 }
 
 impl RtpSinkH264 {
-    pub fn new(filename: Arc<String>, payload_type: u8, clock_rate: u32, fps: u32, ssrc: u32, infinite: bool) -> Self {
+    pub fn new(filename: Arc<String>, payload_type: u8, clock_rate: u32, fps: f64, ssrc: u32, infinite: bool) -> Self {
         let sequence_number = 0;
         let timestamp: u32 = 0;
         let packet = RtpPacket::new(payload_type, sequence_number, timestamp, ssrc, false);
@@ -27,10 +65,51 @@ impl RtpSinkH264 {
             payload_type,
             clock_rate,
             fps,
+            frames: 0,
             packet,
             ssrc,
             filename,
             infinite,
+            pending: Vec::new(),
+            access_unit: Vec::new(),
+            in_picture: false,
+            mode: PacketizationMode::default(),
+            don: 0,
+            sps_rewrite: SpsRewrite::default(),
+            rewritten_sps: None,
+        }
+    }
+
+    pub fn with_packetization_mode(mut self, mode: PacketizationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// 发送前改写码流中的 SPS, 与 SDP 中的 sprop-parameter-sets 保持一致.
+    pub fn with_sps_rewrite(mut self, rewrite: SpsRewrite) -> Self {
+        self.sps_rewrite = rewrite;
+        self
+    }
+
+    /// 需要改写时返回新的 SPS, 解析失败时原样发送.
+    fn rewrite_sps(&mut self, nalu: &[u8]) -> Option<Vec<u8>> {
+        if self.sps_rewrite.is_empty() || nalu[0] & 0x1F != 7 {
+            return None;
+        }
+        if let Some((original, rewritten)) = &self.rewritten_sps {
+            if original == nalu {
+                return Some(rewritten.clone());
+            }
+        }
+        match self.sps_rewrite.rewrite(nalu) {
+            Ok(rewritten) => {
+                self.rewritten_sps = Some((nalu.to_vec(), rewritten.clone()));
+                Some(rewritten)
+            }
+            Err(e) => {
+                log::warn!("rewrite sps failed: {:?}", e);
+                None
+            }
         }
     }
 
@@ -39,6 +118,23 @@ impl RtpSinkH264 {
     }
 
 }
+
+/// NAL 是否开始一个新的访问单元 (H.264 7.4.1.2.3), `in_picture` 表示当前访问单元已有 VCL NAL.
+///
+/// AUD/SEI/SPS/PPS 等出现在 VCL 之后, 或 first_mb_in_slice 为 0 的 slice, 都表示新的图像开始.
+pub fn starts_access_unit(nalu: &[u8], in_picture: bool) -> bool {
+    match nalu[0] & 0x1F {
+        // first_mb_in_slice 为 ue(v), 值为 0 时编码为单个 1 比特
+        1..=5 => in_picture && nalu.get(1).is_some_and(|b| b & 0x80 != 0),
+        6..=9 | 14..=18 => in_picture,
+        _ => false,
+    }
+}
+
+/// NAL 是否为 IDR slice, 含有 IDR slice 的访问单元可以独立解码.
+pub fn is_keyframe(nalu: &[u8]) -> bool {
+    nalu[0] & 0x1F == 5
+}
 //！ FU-A  header for H264
 //+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 //| FU indicator  |   FU header   |                               |
@@ -47,87 +143,144 @@ impl RtpSinkH264 {
 // 0 1 2 3 4 5 6 7 0 1 2 3 4 5 6 7 0 1 2 3 4 5 6 7 0 1 2 3 4 5 6 7 
 //+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 //|F|NRI|  Type   |S|E|R|  Type   |                               |
-impl RtpSink for RtpSinkH264 {
-    fn handle(&mut self, nalu: &[u8], mut write_stream: Box<dyn Write>) {
+impl RtpSinkH264 {
+    /// 单个 NAL 放入一个包, 超过 MTU 时拆成 FU-A (mode 0 不分片), `marker` 只设在最后一个包上.
+    /// mode 2 不允许 single NAL, 放不进 STAP-B 的 NAL 都拆成 FU-B + FU-A, 至少两个分片.
+    fn send_nalu(&mut self, nalu: &[u8], marker: bool, sender: &mut RtpSender) -> io::Result<()> {
+        let interleaved = self.mode == PacketizationMode::Interleaved;
+        let don = self.next_don(1);
         let rtp_packet: &mut RtpPacket = &mut self.packet;
         let nalu_type = nalu[0];
 
-        let mut send_packet = |rtp_packet: &mut RtpPacket| {
-            let len = rtp_packet.payload.len() + 12;
-            let interleaved: &[u8] = &[0x24, 0, ((len >> 8) & 0xFF) as u8, (len & 0xFF) as u8];
-            write_stream
-                .write_all_vectored(&mut [
-                    IoSlice::new(interleaved),
-                    IoSlice::new(rtp_packet.to_bytes().as_slice()),
-                ])
-                .unwrap();
-        };
-
-        if nalu.len() <= RTP_MAX_PACKET_SIZE {
-            rtp_packet.payload.extend_from_slice(&nalu[..nalu.len()]);
-            rtp_packet.marker = true;
-
-            send_packet(rtp_packet);
-
+        if self.mode == PacketizationMode::SingleNal && nalu.len() > MAX_SINGLE_NAL_SIZE {
+            log::warn!("drop {} byte nal, too large for packetization-mode 0", nalu.len());
+            return Ok(());
+        }
+        if !interleaved && (nalu.len() <= RTP_MAX_PACKET_SIZE || self.mode == PacketizationMode::SingleNal) {
+            rtp_packet.payload.extend_from_slice(nalu);
+            rtp_packet.marker = marker;
+            sender.send(rtp_packet)?;
             rtp_packet.payload.clear();
-            rtp_packet.sequence_number += 1;
-            rtp_packet.marker = false;
-
-            if (nalu_type & 0x1F) == 7 || (nalu_type & 0x1F) == 8 {
-                return;
-            }
+            rtp_packet.sequence_number = rtp_packet.sequence_number.wrapping_add(1);
         } else {
-            let pkt_num = nalu.len() / RTP_MAX_PACKET_SIZE;
-            let remain_pkt_size = nalu.len() % RTP_MAX_PACKET_SIZE;
-            let mut pos = NALU_HEADER_SIZE; // skip nalu header
-
-            for i in 0..pkt_num {
-                // F/NRI bit 保持不变, Type 为 28
-                rtp_packet.payload.insert(0, (nalu_type & 0xE0) | 28);
-                rtp_packet.payload.insert(1, nalu_type & 0x1F);
-
+            let data = &nalu[NALU_HEADER_SIZE..];
+            // 同一个 FU 不能同时设置 start 和 end 位, 放得下一个包时也分成两片
+            let size = if data.len() <= RTP_MAX_PACKET_SIZE { data.len().div_ceil(2).max(1) } else { RTP_MAX_PACKET_SIZE };
+            let fragments = data.chunks(size);
+            let last = fragments.len() - 1;
+            for (i, fragment) in fragments.enumerate() {
+                // F/NRI bit 保持不变, Type 为 28; mode 2 的第一个分片为 FU-B (29), 带 DON
+                let fu_b = interleaved && i == 0;
+                rtp_packet.payload.push((nalu_type & 0xE0) | if fu_b { 29 } else { 28 });
+                rtp_packet.payload.push(nalu_type & 0x1F);
                 if i == 0 {
                     rtp_packet.payload[1] |= 0x80; // start
-                } else if remain_pkt_size <= NALU_HEADER_SIZE && i == pkt_num - 1 {
+                }
+                if i == last {
                     rtp_packet.payload[1] |= 0x40; // end
-                    rtp_packet.marker = true;
                 }
-
-                rtp_packet
-                    .payload
-                    .extend_from_slice(&nalu[pos..pos + RTP_MAX_PACKET_SIZE]);
-
-                send_packet(rtp_packet);
-
+                if fu_b {
+                    rtp_packet.payload.extend(don.to_be_bytes());
+                }
+                rtp_packet.payload.extend_from_slice(fragment);
+                rtp_packet.marker = marker && i == last;
+                sender.send(rtp_packet)?;
                 rtp_packet.payload.clear();
-                rtp_packet.sequence_number += 1;
-                pos += RTP_MAX_PACKET_SIZE;
+                rtp_packet.sequence_number = rtp_packet.sequence_number.wrapping_add(1);
             }
+        }
+        rtp_packet.marker = false;
+        Ok(())
+    }
 
-            if remain_pkt_size > NALU_HEADER_SIZE {
-                rtp_packet.payload.insert(0, (nalu_type & 0xE0) | 28);
-                rtp_packet.payload.insert(1, nalu_type & 0x1F);
-                rtp_packet.payload[1] |= 0x40; // end
-                rtp_packet.marker = true;
+    /// 分配 `count` 个连续的 DON, 返回第一个.
+    fn next_don(&mut self, count: u16) -> u16 {
+        let don = self.don;
+        if self.mode == PacketizationMode::Interleaved {
+            self.don = self.don.wrapping_add(count);
+        }
+        don
+    }
 
-                log::info!(
-                    "pos: {} remain: {} nalu.len: {}",
-                    pos,
-                    remain_pkt_size,
-                    nalu.len()
-                );
-                rtp_packet
-                    .payload
-                    .extend_from_slice(&nalu[pos..pos + remain_pkt_size - NALU_HEADER_SIZE]);
+    fn aggregation_header_size(&self) -> usize {
+        if self.mode == PacketizationMode::Interleaved { STAP_B_HEADER_SIZE } else { STAP_A_HEADER_SIZE }
+    }
 
-                send_packet(rtp_packet);
+    /// 加入 `nalu` 后 STAP-A/STAP-B 包的负载大小: 包头, 每个 NAL 前加 2 字节长度.
+    fn aggregated_size(&self, nalu: &[u8]) -> usize {
+        self.aggregation_header_size() + self.pending.iter().map(|n| 2 + n.len()).sum::<usize>() + 2 + nalu.len()
+    }
 
+    /// 发送攒下的 NAL, 多于一个时组成 STAP-A.
+    /// mode 2 不允许 single NAL, 放得下的单个 NAL 也用 STAP-B 发送.
+    fn flush(&mut self, marker: bool, sender: &mut RtpSender) -> io::Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        let interleaved = self.mode == PacketizationMode::Interleaved;
+        match pending.as_slice() {
+            [] => Ok(()),
+            [nalu] if !interleaved || STAP_B_HEADER_SIZE + 2 + nalu.len() > RTP_MAX_PACKET_SIZE => {
+                self.send_nalu(nalu, marker, sender)
+            }
+            nalus => {
+                // F 取或, NRI 取最大值
+                let f = nalus.iter().fold(0, |f, n| f | (n[0] & 0x80));
+                let nri = nalus.iter().map(|n| n[0] & 0x60).max().unwrap_or(0);
+                let don = self.next_don(nalus.len() as u16);
+                let rtp_packet = &mut self.packet;
+                if interleaved {
+                    rtp_packet.payload.push(f | nri | 25);
+                    rtp_packet.payload.extend(don.to_be_bytes());
+                } else {
+                    rtp_packet.payload.push(f | nri | 24);
+                }
+                for nalu in nalus {
+                    rtp_packet.payload.extend((nalu.len() as u16).to_be_bytes());
+                    rtp_packet.payload.extend_from_slice(nalu);
+                }
+                rtp_packet.marker = marker;
+                sender.send(rtp_packet)?;
                 rtp_packet.payload.clear();
-                rtp_packet.sequence_number += 1;
+                rtp_packet.sequence_number = rtp_packet.sequence_number.wrapping_add(1);
+                rtp_packet.marker = false;
+                Ok(())
             }
-            rtp_packet.marker = false;
         }
-        rtp_packet.timestamp += self.clock_rate / self.fps;
+    }
+}
+
+impl RtpSink for RtpSinkH264 {
+    /// NAL 先放入当前访问单元, 访问单元结束时整体发送: 放得下的 NAL 组成 STAP-A/STAP-B,
+    /// 最后一个包带 marker, 之后时间戳前进一帧.
+    fn handle(&mut self, nalu: &[u8], sender: &mut RtpSender) -> io::Result<()> {
+        let rewritten = self.rewrite_sps(nalu);
+        let nalu = rewritten.as_deref().unwrap_or(nalu);
+        if starts_access_unit(nalu, self.in_picture) {
+            self.end_of_stream(sender)?;
+        }
+        self.in_picture |= (1..=5).contains(&(nalu[0] & 0x1F));
+        self.access_unit.push(nalu.to_vec());
+        Ok(())
+    }
+
+    fn end_of_stream(&mut self, sender: &mut RtpSender) -> io::Result<()> {
+        let access_unit = std::mem::take(&mut self.access_unit);
+        if !access_unit.is_empty() {
+            sender.begin_access_unit(self.packet.timestamp, access_unit.iter().any(|nalu| is_keyframe(nalu)));
+        }
+        for nalu in access_unit {
+            let aggregate = self.mode != PacketizationMode::SingleNal && self.aggregated_size(&nalu) <= RTP_MAX_PACKET_SIZE;
+            if !aggregate {
+                self.flush(false, sender)?;
+            }
+            self.pending.push(nalu);
+        }
+        self.flush(true, sender)?;
+        if self.in_picture {
+            self.in_picture = false;
+            self.frames += 1;
+            self.packet.timestamp = frame_timestamp(self.frames, self.clock_rate, self.fps);
+        }
+        Ok(())
     }
 
     fn get_nalu_iter(&self) -> NaluIterator {
@@ -147,3 +300,144 @@ impl IntoIterator for RtpSinkH264 {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::{depacketizer::{Depacketizer, PayloadFormat}, extension::{ExtensionKind, ExtensionMap, HeaderExtender}, transport::SharedBuf};
+
+    /// slice 的第二个字节最高位为 1, 即 first_mb_in_slice 为 0.
+    fn nalu(header: u8, len: usize) -> Vec<u8> {
+        std::iter::once(header).chain((1..len).map(|i| i as u8 | 0x80)).collect()
+    }
+
+    #[test]
+    fn test_stap_a_aggregation() {
+        let buf = SharedBuf::default();
+        let mut sender = buf.sender();
+        let mut sink = RtpSinkH264::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false);
+        let (sps, pps, sei, idr, slice) = (nalu(0x67, 10), nalu(0x68, 5), nalu(0x06, 20), nalu(0x65, 100), nalu(0x41, 3000));
+        for nalu in [&sps, &pps, &sei, &idr, &slice] {
+            sink.handle(nalu, &mut sender).unwrap();
+        }
+        sink.end_of_stream(&mut sender).unwrap();
+
+        let packets = buf.rtp_packets();
+        assert_eq!(packets.len(), 4);
+        // 参数集, SEI 和 IDR 在同一个 STAP-A 中
+        assert_eq!(packets[0].payload[0], 0x60 | 24);
+        assert!(packets[0].marker);
+        assert_eq!(packets[1].payload[0] & 0x1F, 28);
+        assert_eq!(packets[1].timestamp, packets[0].timestamp + 3600);
+        assert!(!packets[1].marker && packets[3].marker);
+
+        let mut depacketizer = Depacketizer::new(PayloadFormat::H264);
+        let units: Vec<_> = packets.iter().flat_map(|packet| depacketizer.push(packet)).collect();
+        let annexb = |nalus: &[&Vec<u8>]| nalus.iter().flat_map(|n| [0, 0, 0, 1].iter().chain(n.iter()).copied()).collect::<Vec<u8>>();
+        assert_eq!(units[0].data, annexb(&[&sps, &pps, &sei, &idr]));
+        assert_eq!(units[1].data, annexb(&[&slice]));
+    }
+
+    #[test]
+    fn test_packetization_modes() {
+        let (sps, idr) = (nalu(0x67, 10), nalu(0x65, 3000));
+
+        let buf = SharedBuf::default();
+        let mut sender = buf.sender();
+        let mut sink = RtpSinkH264::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false)
+            .with_packetization_mode(PacketizationMode::SingleNal);
+        sink.handle(&sps, &mut sender).unwrap();
+        sink.handle(&idr, &mut sender).unwrap();
+        sink.end_of_stream(&mut sender).unwrap();
+        let packets = buf.rtp_packets();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].payload, sps);
+        assert_eq!(packets[1].payload, idr);
+        // 超过一个数据报的 NAL 丢弃, 不发送长度错误的 interleaved 帧
+        sink.handle(&nalu(0x65, 70000), &mut sender).unwrap();
+        sink.end_of_stream(&mut sender).unwrap();
+        assert_eq!(buf.rtp_packets().len(), 2);
+
+        let buf = SharedBuf::default();
+        let mut sender = buf.sender();
+        let mut sink = RtpSinkH264::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false)
+            .with_packetization_mode(PacketizationMode::Interleaved);
+        sink.handle(&sps, &mut sender).unwrap();
+        sink.handle(&idr, &mut sender).unwrap();
+        sink.end_of_stream(&mut sender).unwrap();
+        let packets = buf.rtp_packets();
+        // STAP-B (DON 0) + FU-B (DON 1) + 2 个 FU-A
+        assert_eq!(packets.len(), 4);
+        assert_eq!(&packets[0].payload[..3], &[0x60 | 25, 0, 0]);
+        assert_eq!(&packets[1].payload[..4], &[0x60 | 29, 0x85, 0, 1]);
+        assert_eq!(packets[2].payload[0] & 0x1F, 28);
+
+        let mut depacketizer = Depacketizer::new(PayloadFormat::H264);
+        let units: Vec<_> = packets.iter().flat_map(|packet| depacketizer.push(packet)).collect();
+        assert_eq!(units.len(), 1);
+        assert_eq!(&units[0].data[4..14], sps.as_slice());
+        assert_eq!(&units[0].data[18..], idr.as_slice());
+        assert_eq!(PacketizationMode::try_from(3), Err(3));
+    }
+
+    #[test]
+    fn test_interleaved_without_single_nal() {
+        // 放不进 STAP-B 但不超过 MTU 的 NAL
+        for len in [RTP_MAX_PACKET_SIZE - STAP_B_HEADER_SIZE - 1, RTP_MAX_PACKET_SIZE] {
+            let idr = nalu(0x65, len);
+            let buf = SharedBuf::default();
+            let mut sender = buf.sender();
+            let mut sink = RtpSinkH264::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false)
+                .with_packetization_mode(PacketizationMode::Interleaved);
+            sink.handle(&idr, &mut sender).unwrap();
+            sink.end_of_stream(&mut sender).unwrap();
+            let packets = buf.rtp_packets();
+            assert_eq!(packets.len(), 2, "len {}", len);
+            assert_eq!(&packets[0].payload[..4], &[0x60 | 29, 0x85, 0, 0]);
+            assert_eq!(&packets[1].payload[..2], &[0x60 | 28, 0x45]);
+
+            let mut depacketizer = Depacketizer::new(PayloadFormat::H264);
+            let units: Vec<_> = packets.iter().flat_map(|packet| depacketizer.push(packet)).collect();
+            assert_eq!(&units[0].data[4..], idr.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_frame_marking_per_access_unit() {
+        let buf = SharedBuf::default();
+        let map = ExtensionMap::new(&[ExtensionKind::FrameMarking]);
+        let mut sender = buf.sender().with_extensions(HeaderExtender::new(map, 90000));
+        let mut sink = RtpSinkH264::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false);
+        // SEI 在 IDR 之前单独发送, 也要带 I 位
+        for nalu in [&nalu(0x06, 1390), &nalu(0x65, 3000), &nalu(0x41, 100)] {
+            sink.handle(nalu, &mut sender).unwrap();
+        }
+        sink.end_of_stream(&mut sender).unwrap();
+
+        let marks: Vec<u8> = buf.rtp_packets().iter().map(|p| p.extensions[0].data[0]).collect();
+        assert_eq!(marks, vec![0b1010_0000, 0b0010_0000, 0b0010_0000, 0b0110_0000, 0b1100_0000]);
+    }
+
+    #[test]
+    fn test_access_unit_timestamps() {
+        let buf = SharedBuf::default();
+        let mut sender = buf.sender();
+        let mut sink = RtpSinkH264::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false);
+        let aud = vec![0x09, 0xF0];
+        let first_slice = nalu(0x41, 800);
+        let mut second_slice = nalu(0x41, 800);
+        second_slice[1] = 0x40; // first_mb_in_slice != 0, 同一图像的第二个 slice
+        for nalu in [&aud, &first_slice, &second_slice, &aud, &first_slice] {
+            sink.handle(nalu, &mut sender).unwrap();
+        }
+        sink.end_of_stream(&mut sender).unwrap();
+
+        let packets = buf.rtp_packets();
+        let summary: Vec<_> = packets.iter().map(|p| (p.timestamp, p.marker)).collect();
+        // 第一帧: STAP-A(AUD + slice), slice; 第二帧: STAP-A(AUD + slice)
+        assert_eq!(summary, vec![(0, false), (0, true), (3600, true)]);
+        assert!(starts_access_unit(&[0x06, 0], true));
+        assert!(!starts_access_unit(&[0x06, 0], false));
+        assert!(!starts_access_unit(&[0x0C, 0], true));
+    }
+}
@@ -1,24 +1,29 @@
 use std::fs::File;
-use std::io::IoSlice;
-use std::io::Write;
+use std::io;
 use std::sync::Arc;
 use crate::codec::parse::NaluIterator;
 use super::rtp_packet::RtpPacket;
-use super::rtp_packet::{RtpSink, RTP_MAX_PACKET_SIZE};
+use super::transport::RtpSender;
+use super::rtp_packet::{frame_timestamp, RtpSink, RTP_MAX_PACKET_SIZE};
 
 const NALU_HEADER_SIZE: usize = 2; // 2 bytes for NALU header
+const AP_HEADER_SIZE: usize = 2;
 pub struct RtpSinkH265 {
     payload_type: u8,
     clock_rate: u32,
-    fps: u32,
+    fps: f64,
+    frames: u64, // 已发送的访问单元数, 用于计算时间戳
     packet: RtpPacket,
     ssrc: u32,
     filename: Arc<String>,
     infinite: bool,
+    pending: Vec<Vec<u8>>, // 尚未发送的 NAL, 多个时可以组成一个 AP
+    access_unit: Vec<Vec<u8>>, // 当前访问单元的 NAL, 访问单元结束时发送
+    in_picture: bool,      // 当前访问单元已经有 VCL NAL
 }
 
 impl RtpSinkH265 {
-    pub fn new(filename: Arc<String>, payload_type: u8, clock_rate: u32, fps: u32, ssrc: u32, infinite: bool) -> Self {
+    pub fn new(filename: Arc<String>, payload_type: u8, clock_rate: u32, fps: f64, ssrc: u32, infinite: bool) -> Self {
         let sequence_number = 0;
         let timestamp: u32 = 0;
         let packet = RtpPacket::new(payload_type, sequence_number, timestamp, ssrc, false);
@@ -26,15 +31,35 @@ impl RtpSinkH265 {
             payload_type,
             clock_rate,
             fps,
+            frames: 0,
             packet,
             ssrc,
             filename,
             infinite,
+            pending: Vec::new(),
+            access_unit: Vec::new(),
+            in_picture: false,
         }
     }
 
 }
 
+/// NAL 是否开始一个新的访问单元 (H.265 7.4.2.4.4), `in_picture` 表示当前访问单元已有 VCL NAL.
+///
+/// VPS/SPS/PPS/AUD/前缀 SEI 等出现在 VCL 之后, 或 first_slice_segment_in_pic_flag 为 1 的 slice, 都表示新的图像开始.
+pub fn starts_access_unit(nalu: &[u8], in_picture: bool) -> bool {
+    match (nalu[0] >> 1) & 0x3F {
+        0..=31 => in_picture && nalu.get(2).is_some_and(|b| b & 0x80 != 0),
+        32..=35 | 39 | 41..=44 | 48..=55 => in_picture,
+        _ => false,
+    }
+}
+
+/// NAL 是否为 IRAP slice, 含有 IRAP slice 的访问单元可以独立解码.
+pub fn is_keyframe(nalu: &[u8]) -> bool {
+    matches!((nalu[0] >> 1) & 0x3F, 16..=23)
+}
+
 //！ FU-A  header for HEVC NAL units
 //+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 //| FU indicator                  |   FU header   |               | 
@@ -51,94 +76,188 @@ impl RtpSinkH265 {
 //  S: 1 bit，表示NAL单元的开始位。如果为1，表示这是NAL单元的第一个分包。
 //  E: 1 bit，表示NAL单元的结束位。如果为1，表示这是NAL单元的最后一个分包。
 //  Type: 6 bit，表示FU-A的NAL单元的类型。
-impl RtpSink for RtpSinkH265 {
-    fn handle(&mut self, nalu: &[u8], mut write_stream: Box<dyn Write>) {
-        //! 实现 H265 的 发送nalu rtp 包 
+impl RtpSinkH265 {
+    /// 单个 NAL 放入一个包, 超过 MTU 时拆成 FU, `marker` 只设在最后一个包上.
+    fn send_nalu(&mut self, nalu: &[u8], marker: bool, sender: &mut RtpSender) -> io::Result<()> {
         let rtp_packet: &mut RtpPacket = &mut self.packet;
-
         let nalu_type = (nalu[0] & 0x7e) >> 1;
-        let mut send_packet = |rtp_packet: &mut RtpPacket| {
-            let len = rtp_packet.payload.len() + 12;
-            let interleaved: &[u8] = &[0x24, 0, ((len >> 8) & 0xFF) as u8, (len & 0xFF) as u8];
-            write_stream
-                .write_all_vectored(&mut [
-                    IoSlice::new(interleaved),
-                    IoSlice::new(rtp_packet.to_bytes().as_slice()),
-                ])
-                .unwrap();
-        };
 
         if nalu.len() <= RTP_MAX_PACKET_SIZE {
-            rtp_packet.payload.extend_from_slice(&nalu[..nalu.len()]);
-            rtp_packet.marker = true;
-
-            send_packet(rtp_packet);
-
+            rtp_packet.payload.extend_from_slice(nalu);
+            rtp_packet.marker = marker;
+            sender.send(rtp_packet)?;
             rtp_packet.payload.clear();
-            rtp_packet.sequence_number += 1;
-            rtp_packet.marker = false;
-
-            // STAP-A 单一时间的组合包, sps pps vps 一起发送
-            if let 32 | 33 | 34 = nalu_type {
-                return;
-            }
+            rtp_packet.sequence_number = rtp_packet.sequence_number.wrapping_add(1);
         } else {
-            let pkt_num = nalu.len() / RTP_MAX_PACKET_SIZE;
-            let remain_pkt_size = nalu.len() % RTP_MAX_PACKET_SIZE;
-            let mut pos = NALU_HEADER_SIZE; // 2 bytes for NALU header
-
-            for i in 0..pkt_num {
-                rtp_packet.payload.insert(0, (nalu[0] & 0x81) | (49 >> 1));
-                rtp_packet.payload.insert(1, nalu[1]);
-                rtp_packet.payload.insert(2, nalu_type);
-
+            let fragments = nalu[NALU_HEADER_SIZE..].chunks(RTP_MAX_PACKET_SIZE);
+            let last = fragments.len() - 1;
+            for (i, fragment) in fragments.enumerate() {
+                rtp_packet.payload.push((nalu[0] & 0x81) | (49 << 1));
+                rtp_packet.payload.push(nalu[1]);
+                rtp_packet.payload.push(nalu_type);
                 if i == 0 {
                     rtp_packet.payload[2] |= 0x80; // start
-                } else if remain_pkt_size <= NALU_HEADER_SIZE && i == pkt_num - 1 {
+                }
+                if i == last {
                     rtp_packet.payload[2] |= 0x40; // end
-                    rtp_packet.marker = true;
                 }
+                rtp_packet.payload.extend_from_slice(fragment);
+                rtp_packet.marker = marker && i == last;
+                sender.send(rtp_packet)?;
+                rtp_packet.payload.clear();
+                rtp_packet.sequence_number = rtp_packet.sequence_number.wrapping_add(1);
+            }
+        }
+        rtp_packet.marker = false;
+        Ok(())
+    }
 
-                rtp_packet
-                    .payload
-                    .extend_from_slice(&nalu[pos..pos + RTP_MAX_PACKET_SIZE]);
-
-                send_packet(rtp_packet);
+    /// AP 包的负载大小: 2 字节头, 每个 NAL 前加 2 字节长度.
+    fn aggregated_size(&self, nalu: &[u8]) -> usize {
+        AP_HEADER_SIZE + self.pending.iter().map(|n| 2 + n.len()).sum::<usize>() + 2 + nalu.len()
+    }
 
+    /// 发送攒下的 NAL, 多于一个时组成 AP.
+    fn flush(&mut self, marker: bool, sender: &mut RtpSender) -> io::Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        match pending.as_slice() {
+            [] => Ok(()),
+            [nalu] => self.send_nalu(nalu, marker, sender),
+            nalus => {
+                // F 取或, LayerId 和 TID 取最小值 (RFC 7798 4.4.2)
+                let f = nalus.iter().fold(0, |f, n| f | (n[0] & 0x80));
+                let layer_id = nalus.iter().map(|n| ((n[0] & 0x01) << 5) | (n[1] >> 3)).min().unwrap_or(0);
+                let tid = nalus.iter().map(|n| n[1] & 0x07).min().unwrap_or(1);
+                let rtp_packet = &mut self.packet;
+                rtp_packet.payload.push(f | (48 << 1) | (layer_id >> 5));
+                rtp_packet.payload.push((layer_id << 3) | tid);
+                for nalu in nalus {
+                    rtp_packet.payload.extend((nalu.len() as u16).to_be_bytes());
+                    rtp_packet.payload.extend_from_slice(nalu);
+                }
+                rtp_packet.marker = marker;
+                sender.send(rtp_packet)?;
                 rtp_packet.payload.clear();
-                rtp_packet.sequence_number += 1;
-                pos += RTP_MAX_PACKET_SIZE;
+                rtp_packet.sequence_number = rtp_packet.sequence_number.wrapping_add(1);
+                rtp_packet.marker = false;
+                Ok(())
             }
+        }
+    }
+}
 
-            if remain_pkt_size > NALU_HEADER_SIZE {
-                rtp_packet.payload.insert(0, (nalu[0] & 0x81) | (49 >> 1));
-                rtp_packet.payload.insert(1, nalu[1]);
-                rtp_packet.payload.insert(2, nalu_type);
-                rtp_packet.payload[2] |= 0x40; // end
-                rtp_packet.marker = true;
-
-                log::info!(
-                    "pos: {} remain: {} nalu.len: {}",
-                    pos,
-                    remain_pkt_size,
-                    nalu.len()
-                );
-                rtp_packet
-                    .payload
-                    .extend_from_slice(&nalu[pos..pos + remain_pkt_size - NALU_HEADER_SIZE]);
-
-                send_packet(rtp_packet);
+impl RtpSink for RtpSinkH265 {
+    /// NAL 先放入当前访问单元, 访问单元结束时整体发送: 放得下的 NAL 组成 AP,
+    /// 最后一个包带 marker, 之后时间戳前进一帧.
+    fn handle(&mut self, nalu: &[u8], sender: &mut RtpSender) -> io::Result<()> {
+        // 损坏的码流中可能出现不足 2 字节 NAL 头的单元
+        if nalu.len() < NALU_HEADER_SIZE {
+            log::warn!("drop {} byte nal, shorter than the nal header", nalu.len());
+            return Ok(());
+        }
+        if starts_access_unit(nalu, self.in_picture) {
+            self.end_of_stream(sender)?;
+        }
+        self.in_picture |= (nalu[0] >> 1) & 0x3F < 32;
+        self.access_unit.push(nalu.to_vec());
+        Ok(())
+    }
 
-                rtp_packet.payload.clear();
-                rtp_packet.sequence_number += 1;
+    fn end_of_stream(&mut self, sender: &mut RtpSender) -> io::Result<()> {
+        let access_unit = std::mem::take(&mut self.access_unit);
+        if !access_unit.is_empty() {
+            sender.begin_access_unit(self.packet.timestamp, access_unit.iter().any(|nalu| is_keyframe(nalu)));
+        }
+        for nalu in access_unit {
+            if self.aggregated_size(&nalu) > RTP_MAX_PACKET_SIZE {
+                self.flush(false, sender)?;
             }
-            rtp_packet.marker = false;
+            self.pending.push(nalu);
         }
-        rtp_packet.timestamp += self.clock_rate / self.fps;
+        self.flush(true, sender)?;
+        if self.in_picture {
+            self.in_picture = false;
+            self.frames += 1;
+            self.packet.timestamp = frame_timestamp(self.frames, self.clock_rate, self.fps);
+        }
+        Ok(())
     }
 
     fn get_nalu_iter(&self) -> NaluIterator {
         let file = File::open(self.filename.as_ref()).unwrap();
         NaluIterator::new(file, self.infinite)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::{depacketizer::{Depacketizer, PayloadFormat}, transport::SharedBuf};
+
+    /// slice 的第三个字节最高位为 1, 即 first_slice_segment_in_pic_flag 为 1.
+    fn nalu(nalu_type: u8, len: usize) -> Vec<u8> {
+        [nalu_type << 1, 1].into_iter().chain((2..len).map(|i| i as u8 | 0x80)).collect()
+    }
+
+    #[test]
+    fn test_ap_aggregation_and_fu() {
+        let buf = SharedBuf::default();
+        let mut sender = buf.sender();
+        let mut sink = RtpSinkH265::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false);
+        let (vps, sps, pps, idr) = (nalu(32, 20), nalu(33, 40), nalu(34, 8), nalu(19, 2000));
+        for nalu in [&vps, &sps, &pps, &idr] {
+            sink.handle(nalu, &mut sender).unwrap();
+        }
+        sink.end_of_stream(&mut sender).unwrap();
+
+        let packets = buf.rtp_packets();
+        assert_eq!(packets.len(), 3);
+        assert_eq!(&packets[0].payload[..2], &[48 << 1, 1]);
+        assert!(!packets[0].marker);
+        assert_eq!(&packets[1].payload[..3], &[49 << 1, 1, 0x80 | 19]);
+        assert_eq!(&packets[2].payload[..3], &[49 << 1, 1, 0x40 | 19]);
+        assert!(packets[2].marker);
+        assert!(packets.iter().all(|packet| packet.timestamp == 0));
+
+        let mut depacketizer = Depacketizer::new(PayloadFormat::H265);
+        let units: Vec<_> = packets.iter().flat_map(|packet| depacketizer.push(packet)).collect();
+        let annexb: Vec<u8> = [&vps, &sps, &pps, &idr].iter().flat_map(|n| [0, 0, 0, 1].iter().chain(n.iter()).copied()).collect();
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].data, annexb);
+    }
+
+    #[test]
+    fn test_drop_truncated_nal() {
+        let buf = SharedBuf::default();
+        let mut sender = buf.sender();
+        let mut sink = RtpSinkH265::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false);
+        // 00 00 01 40 00 00 01 ... 得到只有 1 字节的 NAL
+        for nalu in [&[0x40][..], &nalu(32, 20), &[0x42], &nalu(19, 100)] {
+            sink.handle(nalu, &mut sender).unwrap();
+        }
+        sink.end_of_stream(&mut sender).unwrap();
+
+        let packets = buf.rtp_packets();
+        assert_eq!(packets.len(), 1);
+        let mut depacketizer = Depacketizer::new(PayloadFormat::H265);
+        let units: Vec<_> = packets.iter().flat_map(|packet| depacketizer.push(packet)).collect();
+        let annexb: Vec<u8> = [&nalu(32, 20), &nalu(19, 100)].iter().flat_map(|n| [0, 0, 0, 1].iter().chain(n.iter()).copied()).collect();
+        assert_eq!(units[0].data, annexb);
+    }
+
+    #[test]
+    fn test_access_unit_timestamps() {
+        let buf = SharedBuf::default();
+        let mut sender = buf.sender();
+        let mut sink = RtpSinkH265::new(Arc::new(String::new()), 96, 90000, 25.0, 1234, false);
+        let mut second_segment = nalu(1, 900);
+        second_segment[2] = 0x40;
+        // 前缀 SEI + 两个 slice segment, 后缀 SEI 仍属于同一图像, 然后下一帧
+        for nalu in [&nalu(39, 10), &nalu(1, 900), &second_segment, &nalu(40, 10), &nalu(1, 900)] {
+            sink.handle(nalu, &mut sender).unwrap();
+        }
+        sink.end_of_stream(&mut sender).unwrap();
+
+        let summary: Vec<_> = buf.rtp_packets().iter().map(|p| (p.timestamp, p.marker)).collect();
+        assert_eq!(summary, vec![(0, false), (0, true), (3600, true)]);
+    }
+}
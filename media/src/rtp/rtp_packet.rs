@@ -1,18 +1,40 @@
-use std::io::Write;
+use std::io;
 use crate::codec::parse::NaluIterator;
+use super::transport::RtpSender;
 pub const RTP_MAX_PACKET_SIZE: usize = 1400;
 
+/// RFC 8285 头扩展元素, `id` 由 SDP 的 a=extmap 协商.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderExtension {
+    pub id: u8,
+    pub data: Vec<u8>,
+}
+
+const ONE_BYTE_PROFILE: u16 = 0xBEDE;
+const TWO_BYTE_PROFILE: u16 = 0x1000;
+const RTP_HEADER_SIZE: usize = 12;
+
+/// RTP 解析失败的原因.
+#[derive(Debug, PartialEq)]
+pub enum RtpError {
+    Truncated,
+    InvalidVersion(u8),
+    InvalidPadding,
+    /// 头扩展 id 为 0 (RFC 8285 4.3 保留给填充) 或数据超过 255 字节
+    InvalidExtension(u8),
+}
+
 #[derive(Debug, Clone)]
 pub struct RtpPacket {
     version: u8,
     padding: bool,
-    extension: bool,
-    csrc_count: u8,
     pub marker: bool,
     pub payload_type: u8,
     pub sequence_number: u16,
     pub timestamp: u32,
     pub ssrc: u32,
+    pub csrcs: Vec<u32>, // 最多 15 个
+    pub extensions: Vec<HeaderExtension>,
     pub payload: Vec<u8>,
 }
 
@@ -20,25 +42,106 @@ impl RtpPacket {
     pub fn new(payload_type: u8, sequence_number: u16, timestamp: u32, ssrc: u32, marker: bool) -> Self {
         let version = 2;
         let padding = false;
-        let extension = false;
-        let csrc_count = 0;
         RtpPacket {
             version,
             padding,
-            extension,
-            csrc_count,
             marker,
             payload_type,
             sequence_number,
             timestamp,
             ssrc,
+            csrcs: Vec::new(),
+            extensions: Vec::new(),
             payload: Vec::new(),
         }
     }
 
+    /// 解析一个 rtp 包, 去掉填充; 非 RFC 8285 格式的头扩展会被忽略.
+    pub fn parse(data: &[u8]) -> Result<Self, RtpError> {
+        if data.len() < RTP_HEADER_SIZE {
+            return Err(RtpError::Truncated);
+        }
+        let version = data[0] >> 6;
+        if version != 2 {
+            return Err(RtpError::InvalidVersion(version));
+        }
+        let padding = data[0] & 0x20 != 0;
+        let extension = data[0] & 0x10 != 0;
+        let csrc_count = (data[0] & 0x0F) as usize;
+        let mut packet = RtpPacket::new(
+            data[1] & 0x7F,
+            u16::from_be_bytes([data[2], data[3]]),
+            u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            data[1] & 0x80 != 0,
+        );
+
+        let mut body = &data[RTP_HEADER_SIZE..];
+        if padding {
+            let pad = *body.last().ok_or(RtpError::InvalidPadding)? as usize;
+            if pad == 0 || pad > body.len() {
+                return Err(RtpError::InvalidPadding);
+            }
+            body = &body[..body.len() - pad];
+        }
+        let csrcs = body.get(..csrc_count * 4).ok_or(RtpError::Truncated)?;
+        packet.csrcs = csrcs.chunks_exact(4).map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]])).collect();
+        body = &body[csrc_count * 4..];
+
+        if extension {
+            let header = body.get(..4).ok_or(RtpError::Truncated)?;
+            let profile = u16::from_be_bytes([header[0], header[1]]);
+            let len = u16::from_be_bytes([header[2], header[3]]) as usize * 4;
+            let elements = body.get(4..4 + len).ok_or(RtpError::Truncated)?;
+            packet.extensions = Self::parse_extensions(profile, elements)?;
+            body = &body[4 + len..];
+        }
+        packet.payload = body.to_vec();
+        Ok(packet)
+    }
+
+    fn parse_extensions(profile: u16, mut data: &[u8]) -> Result<Vec<HeaderExtension>, RtpError> {
+        let one_byte = profile == ONE_BYTE_PROFILE;
+        if !one_byte && profile & 0xFFF0 != TWO_BYTE_PROFILE {
+            return Ok(Vec::new());
+        }
+        let mut extensions = Vec::new();
+        while let Some(&first) = data.first() {
+            // id 为 0 的是填充字节
+            if first == 0 {
+                data = &data[1..];
+                continue;
+            }
+            let (id, len, header) = if one_byte {
+                // id 15 保留, 遇到时停止解析
+                if first >> 4 == 15 {
+                    break;
+                }
+                (first >> 4, (first & 0x0F) as usize + 1, 1)
+            } else {
+                (first, *data.get(1).ok_or(RtpError::Truncated)? as usize, 2)
+            };
+            let value = data.get(header..header + len).ok_or(RtpError::Truncated)?;
+            extensions.push(HeaderExtension { id, data: value.to_vec() });
+            data = &data[header + len..];
+        }
+        Ok(extensions)
+    }
+
+    /// 添加一个头扩展元素, 单字节和双字节格式都不能表示 id 0 和超过 255 字节的数据.
+    pub fn add_extension(&mut self, id: u8, data: Vec<u8>) -> Result<(), RtpError> {
+        if id == 0 || data.len() > 255 {
+            return Err(RtpError::InvalidExtension(id));
+        }
+        self.extensions.push(HeaderExtension { id, data });
+        Ok(())
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
-        let first_byte = (self.version << 6) | ((self.padding as u8) << 5) | ((self.extension as u8) << 4) | self.csrc_count;
+        let extension = !self.extensions.is_empty();
+        let csrc_count = self.csrcs.len().min(15) as u8;
+        let first_byte = (self.version << 6) | ((self.padding as u8) << 5) | ((extension as u8) << 4) | csrc_count;
         let second_byte = ((self.marker as u8) << 7) | self.payload_type;
         bytes.push(first_byte);
 
@@ -46,12 +149,124 @@ impl RtpPacket {
         bytes.extend(&self.sequence_number.to_be_bytes());
         bytes.extend(&self.timestamp.to_be_bytes());
         bytes.extend(&self.ssrc.to_be_bytes());
+        for csrc in &self.csrcs[..csrc_count as usize] {
+            bytes.extend(csrc.to_be_bytes());
+        }
+        if extension {
+            self.write_extensions(&mut bytes);
+        }
         bytes.extend(&*self.payload);
         bytes
     }
+
+    /// 所有元素的 id 在 1~14 且长度在 1~16 时用单字节头, 否则用双字节头, 补零到 4 字节对齐.
+    fn write_extensions(&self, bytes: &mut Vec<u8>) {
+        let one_byte = self.extensions.iter().all(|ext| (1..=14).contains(&ext.id) && (1..=16).contains(&ext.data.len()));
+        let start = bytes.len();
+        let profile = if one_byte { ONE_BYTE_PROFILE } else { TWO_BYTE_PROFILE };
+        bytes.extend(profile.to_be_bytes());
+        bytes.extend([0, 0]); // 长度稍后回填
+        for ext in &self.extensions {
+            if one_byte {
+                bytes.push((ext.id << 4) | (ext.data.len() - 1) as u8);
+            } else {
+                bytes.extend([ext.id, ext.data.len() as u8]);
+            }
+            bytes.extend(&ext.data);
+        }
+        while !(bytes.len() - start).is_multiple_of(4) {
+            bytes.push(0);
+        }
+        let words = ((bytes.len() - start) / 4 - 1) as u16;
+        bytes[start + 2..start + 4].copy_from_slice(&words.to_be_bytes());
+    }
+}
+
+/// 第 `frames` 帧的 rtp 时间戳. 按帧数计算而不是逐帧累加, 29.97 等非整数帧率不会累积误差.
+pub fn frame_timestamp(frames: u64, clock_rate: u32, fps: f64) -> u32 {
+    (frames as f64 * clock_rate as f64 / fps).round() as u64 as u32
 }
 
 pub trait RtpSink: Send + Sync {
-    fn handle(&mut self, nalu: &[u8], write_stream: Box<dyn Write>);
+    fn handle(&mut self, nalu: &[u8], sender: &mut RtpSender) -> io::Result<()>;
+    /// 发送缓存的最后一个访问单元. 访问单元在下一个访问单元开始时才能确定结束.
+    fn end_of_stream(&mut self, sender: &mut RtpSender) -> io::Result<()>;
     fn get_nalu_iter(&self) -> NaluIterator;
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_timestamp() {
+        assert_eq!(frame_timestamp(1, 90000, 25.0), 3600);
+        // 30000/1001 和 24000/1001 帧率
+        assert_eq!(frame_timestamp(1, 90000, 30000.0 / 1001.0), 3003);
+        assert_eq!(frame_timestamp(4, 90000, 24000.0 / 1001.0), 15015);
+        assert_eq!(frame_timestamp(1_000_000, 90000, 30000.0 / 1001.0), 3_003_000_000);
+        assert_eq!(frame_timestamp(1_500_000, 90000, 30000.0 / 1001.0), (4_504_500_000u64 % (1 << 32)) as u32);
+    }
+
+    #[test]
+    fn test_csrc_and_one_byte_extension() {
+        let mut packet = RtpPacket::new(96, 1, 2, 3, true);
+        packet.csrcs = vec![0x11223344];
+        packet.add_extension(3, vec![0xaa, 0xbb, 0xcc]).unwrap();
+        packet.add_extension(5, vec![0x01]).unwrap();
+        packet.payload = vec![9];
+        let bytes = packet.to_bytes();
+        assert_eq!(bytes[0], 0x91);
+        assert_eq!(&bytes[12..16], &[0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(&bytes[16..20], &[0xbe, 0xde, 0, 2]);
+        assert_eq!(&bytes[20..28], &[0x32, 0xaa, 0xbb, 0xcc, 0x50, 0x01, 0, 0]);
+        assert_eq!(&bytes[28..], &[9]);
+
+        let parsed = RtpPacket::parse(&bytes).unwrap();
+        assert_eq!(parsed.csrcs, packet.csrcs);
+        assert_eq!(parsed.extensions, packet.extensions);
+        assert_eq!(parsed.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_parse_padding_and_errors() {
+        let mut bytes = RtpPacket::new(96, 7, 90000, 1234, true).to_bytes();
+        bytes[0] |= 0x20;
+        bytes.extend([1, 2, 3, 0, 0, 3]);
+        let packet = RtpPacket::parse(&bytes).unwrap();
+        assert!(packet.marker);
+        assert_eq!(packet.payload_type, 96);
+        assert_eq!(packet.sequence_number, 7);
+        assert_eq!(packet.timestamp, 90000);
+        assert_eq!(packet.ssrc, 1234);
+        assert_eq!(packet.payload, vec![1, 2, 3]);
+
+        *bytes.last_mut().unwrap() = 0;
+        assert_eq!(RtpPacket::parse(&bytes).unwrap_err(), RtpError::InvalidPadding);
+        assert_eq!(RtpPacket::parse(&bytes[..11]).unwrap_err(), RtpError::Truncated);
+        assert_eq!(RtpPacket::parse(&[0x40; 12]).unwrap_err(), RtpError::InvalidVersion(1));
+        // 声明了 CSRC 但长度不够
+        let mut csrc = RtpPacket::new(96, 7, 0, 1, false).to_bytes();
+        csrc[0] |= 2;
+        csrc.extend([0; 4]);
+        assert_eq!(RtpPacket::parse(&csrc).unwrap_err(), RtpError::Truncated);
+    }
+
+    #[test]
+    fn test_two_byte_extension() {
+        let mut packet = RtpPacket::new(96, 1, 2, 3, false);
+        packet.add_extension(20, vec![]).unwrap();
+        packet.add_extension(1, vec![7; 17]).unwrap();
+        let bytes = packet.to_bytes();
+        assert_eq!(bytes[0], 0x90);
+        assert_eq!(&bytes[12..16], &[0x10, 0x00, 0, 6]);
+        assert_eq!(&bytes[16..20], &[20, 0, 1, 17]);
+        assert_eq!(bytes.len(), 16 + 24);
+        assert_eq!(&bytes[37..], &[0, 0, 0]);
+        assert_eq!(RtpPacket::parse(&bytes).unwrap().extensions, packet.extensions);
+
+        // id 0 在双字节格式中是填充, 不能作为扩展
+        assert_eq!(packet.add_extension(0, vec![1]), Err(RtpError::InvalidExtension(0)));
+        assert_eq!(packet.add_extension(2, vec![0; 256]), Err(RtpError::InvalidExtension(2)));
+        assert_eq!(packet.extensions.len(), 2);
+    }
+}
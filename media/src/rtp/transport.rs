@@ -0,0 +1,362 @@
+use std::{
+    io::{self, IoSlice, Write},
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use super::{extension::HeaderExtender, fec::FecEncoder, pacer::Pacer, retransmit::{NackQueue, Retransmitter}, rtcp::RtcpSender, rtp_packet::RtpPacket};
+
+/// SETUP 协商出的传输方式.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    /// RTP/AVP/TCP, 与 rtsp 共用控制连接 (RFC 2326 10.12)
+    Interleaved { rtp_channel: u8, rtcp_channel: u8 },
+    /// RTP/AVP (UDP), 客户端的 rtp/rtcp 端口
+    Udp { client_rtp_port: u16, client_rtcp_port: u16 },
+}
+
+impl Transport {
+    /// 解析 Transport 请求头, 取第一个支持的单播方案.
+    /// 例如 "RTP/AVP/TCP;unicast;interleaved=0-1", "RTP/AVP;unicast;client_port=8000-8001".
+    pub fn parse(header: &str) -> Option<Self> {
+        header.split(',').find_map(|spec| {
+            let mut params = spec.split(';').map(str::trim);
+            let protocol = params.next()?.to_ascii_uppercase();
+            let pair = |value: &str| -> Option<(u16, u16)> {
+                match value.split_once('-') {
+                    Some((a, b)) => Some((a.parse().ok()?, b.parse().ok()?)),
+                    None => {
+                        let a: u16 = value.parse().ok()?;
+                        Some((a, a.checked_add(1)?))
+                    }
+                }
+            };
+            let param = |name: &str| {
+                spec.split(';')
+                    .filter_map(|p| p.trim().split_once('='))
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.trim())
+            };
+
+            match protocol.as_str() {
+                "RTP/AVP/TCP" => {
+                    let (rtp, rtcp) = param("interleaved").and_then(pair).unwrap_or((0, 1));
+                    Some(Transport::Interleaved {
+                        rtp_channel: u8::try_from(rtp).ok()?,
+                        rtcp_channel: u8::try_from(rtcp).ok()?,
+                    })
+                }
+                "RTP/AVP" | "RTP/AVP/UDP" => {
+                    if params.any(|p| p.eq_ignore_ascii_case("multicast")) {
+                        return None;
+                    }
+                    let (rtp, rtcp) = param("client_port").and_then(pair)?;
+                    Some(Transport::Udp { client_rtp_port: rtp, client_rtcp_port: rtcp })
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+/// 服务端的一对 udp 端口, rtp 为偶数端口, rtcp 为紧随其后的奇数端口.
+#[derive(Debug)]
+pub struct UdpPair {
+    pub rtp: UdpSocket,
+    pub rtcp: UdpSocket,
+}
+
+impl UdpPair {
+    pub fn bind(ip: IpAddr) -> io::Result<Self> {
+        const MAX_ATTEMPTS: usize = 16;
+        for _ in 0..MAX_ATTEMPTS {
+            let rtp = UdpSocket::bind((ip, 0))?;
+            let port = rtp.local_addr()?.port();
+            if port % 2 != 0 || port == u16::MAX {
+                continue;
+            }
+            if let Ok(rtcp) = UdpSocket::bind((ip, port + 1)) {
+                return Ok(Self { rtp, rtcp });
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::AddrInUse, "no free rtp/rtcp port pair"))
+    }
+
+    pub fn ports(&self) -> io::Result<(u16, u16)> {
+        Ok((self.rtp.local_addr()?.port(), self.rtcp.local_addr()?.port()))
+    }
+}
+
+enum Output {
+    Interleaved { stream: Box<dyn Write + Send>, rtp_channel: u8, rtcp_channel: u8 },
+    Udp { sockets: Arc<UdpPair>, rtp_addr: SocketAddr, rtcp_addr: SocketAddr },
+}
+
+/// 按协商的传输方式发送 rtp 包, 并定期插入 RTCP SR.
+pub struct RtpSender {
+    output: Output,
+    rtcp: RtcpSender,
+    retransmit: Option<(Retransmitter, NackQueue)>,
+    fec: Option<FecEncoder>,
+    extensions: Option<HeaderExtender>,
+    pacer: Option<Pacer>,
+}
+
+impl RtpSender {
+    pub fn interleaved(stream: Box<dyn Write + Send>, rtp_channel: u8, rtcp_channel: u8, rtcp: RtcpSender) -> Self {
+        Self {
+            output: Output::Interleaved { stream, rtp_channel, rtcp_channel },
+            rtcp,
+            retransmit: None,
+            fec: None,
+            extensions: None,
+            pacer: None,
+        }
+    }
+
+    pub fn udp(sockets: Arc<UdpPair>, peer: IpAddr, client_rtp_port: u16, client_rtcp_port: u16, rtcp: RtcpSender) -> Self {
+        Self {
+            output: Output::Udp {
+                sockets,
+                rtp_addr: SocketAddr::new(peer, client_rtp_port),
+                rtcp_addr: SocketAddr::new(peer, client_rtcp_port),
+            },
+            rtcp,
+            retransmit: None,
+            fec: None,
+            extensions: None,
+            pacer: None,
+        }
+    }
+
+    /// 按 `nacks` 中收到的序号重传最近发送过的包.
+    pub fn with_retransmission(mut self, retransmitter: Retransmitter, nacks: NackQueue) -> Self {
+        self.retransmit = Some((retransmitter, nacks));
+        self
+    }
+
+    /// 每组媒体包之后发送一个 ULPFEC 包.
+    pub fn with_fec(mut self, encoder: FecEncoder) -> Self {
+        self.fec = Some(encoder);
+        self
+    }
+
+    /// 发送前加上 a=extmap 协商的头扩展.
+    pub fn with_extensions(mut self, extender: HeaderExtender) -> Self {
+        self.extensions = Some(extender);
+        self
+    }
+
+    /// 按时间戳控制发送节奏, 每个访问单元在其显示时间发送.
+    pub fn with_pacer(mut self, pacer: Pacer) -> Self {
+        self.pacer = Some(pacer);
+        self
+    }
+
+    /// sink 发送一个访问单元之前调用, 头扩展的采集时间和关键帧标记按访问单元确定.
+    pub fn begin_access_unit(&mut self, timestamp: u32, keyframe: bool) {
+        if let Some(extender) = &mut self.extensions {
+            extender.begin_access_unit(timestamp, keyframe, SystemTime::now());
+        }
+    }
+
+    pub fn rtcp(&self) -> &RtcpSender {
+        &self.rtcp
+    }
+
+    pub fn send(&mut self, packet: &RtpPacket) -> io::Result<()> {
+        if let Some(pacer) = &mut self.pacer {
+            pacer.wait(packet.timestamp);
+        }
+        let extended;
+        let packet = match &mut self.extensions {
+            Some(extender) => {
+                let mut packet = packet.clone();
+                extender.apply(&mut packet, SystemTime::now());
+                extended = packet;
+                &extended
+            }
+            None => packet,
+        };
+        Self::write_rtp(&mut self.output, &packet.to_bytes())?;
+        if let Some(fec) = self.fec.as_mut().and_then(|encoder| encoder.on_sent(packet)) {
+            Self::write_rtp(&mut self.output, &fec.to_bytes())?;
+        }
+        if let Some((retransmitter, nacks)) = &mut self.retransmit {
+            retransmitter.on_sent(packet);
+            let lost = nacks.lock().unwrap().take();
+            for packet in retransmitter.retransmit(&lost, Instant::now()) {
+                Self::write_rtp(&mut self.output, &packet.to_bytes())?;
+            }
+        }
+
+        let now = Instant::now();
+        self.rtcp.on_rtp_sent(packet, now);
+        if self.rtcp.report_due(now) {
+            if let Some(report) = self.rtcp.report(now, SystemTime::now()) {
+                self.send_rtcp(&report)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_rtp(output: &mut Output, bytes: &[u8]) -> io::Result<()> {
+        match output {
+            Output::Interleaved { stream, rtp_channel, .. } => Self::write_interleaved(stream, *rtp_channel, bytes),
+            Output::Udp { sockets, rtp_addr, .. } => sockets.rtp.send_to(bytes, *rtp_addr).map(|_| ()),
+        }
+    }
+
+    pub fn send_rtcp(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &mut self.output {
+            Output::Interleaved { stream, rtcp_channel, .. } => Self::write_interleaved(stream, *rtcp_channel, bytes),
+            Output::Udp { sockets, rtcp_addr, .. } => sockets.rtcp.send_to(bytes, *rtcp_addr).map(|_| ()),
+        }
+    }
+
+    /// '$' + 通道号 + 2 字节长度, 后接 rtp/rtcp 包; 超过 65535 字节的包无法表示.
+    fn write_interleaved(stream: &mut Box<dyn Write + Send>, channel: u8, bytes: &[u8]) -> io::Result<()> {
+        let len = u16::try_from(bytes.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{} byte packet exceeds interleaved frame size", bytes.len()))
+        })?;
+        let [high, low] = len.to_be_bytes();
+        let interleaved: &[u8] = &[0x24, channel, high, low];
+        stream.write_all_vectored(&mut [IoSlice::new(interleaved), IoSlice::new(bytes)])
+    }
+}
+
+/// 测试用的 interleaved 发送端, 写入的字节保存在共享缓冲区中.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuf(pub std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl SharedBuf {
+    pub fn sender(&self) -> RtpSender {
+        RtpSender::interleaved(Box::new(self.clone()), 0, 1, RtcpSender::new("cname".to_string(), 90000))
+    }
+
+    /// 取出已发送的 rtp 包 (通道 0), 忽略 RTCP.
+    pub fn rtp_packets(&self) -> Vec<RtpPacket> {
+        let bytes = self.0.lock().unwrap().clone();
+        let mut data = &bytes[..];
+        let mut packets = Vec::new();
+        while data.len() > 4 {
+            let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+            if data[1] == 0 {
+                packets.push(RtpPacket::parse(&data[4..4 + len]).unwrap());
+            }
+            data = &data[4 + len..];
+        }
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtp::retransmit::Retransmission;
+
+    #[test]
+    fn test_parse_transport() {
+        assert_eq!(
+            Transport::parse("RTP/AVP/TCP;unicast;interleaved=2-3"),
+            Some(Transport::Interleaved { rtp_channel: 2, rtcp_channel: 3 })
+        );
+        assert_eq!(
+            Transport::parse("RTP/AVP;unicast;client_port=8000-8001"),
+            Some(Transport::Udp { client_rtp_port: 8000, client_rtcp_port: 8001 })
+        );
+        assert_eq!(
+            Transport::parse("RTP/AVP;multicast;client_port=8000-8001,RTP/AVP/TCP;unicast"),
+            Some(Transport::Interleaved { rtp_channel: 0, rtcp_channel: 1 })
+        );
+        assert_eq!(Transport::parse("RTP/AVP;unicast"), None);
+        assert_eq!(Transport::parse("RAW/RAW/UDP;unicast;client_port=8000"), None);
+    }
+
+    #[test]
+    fn test_interleaved_frame_too_large() {
+        let buf = SharedBuf::default();
+        let mut sender = RtpSender::interleaved(Box::new(buf.clone()), 0, 1, RtcpSender::new("cname".to_string(), 90000));
+        let mut packet = RtpPacket::new(96, 7, 0, 1234, true);
+        packet.payload = vec![0; u16::MAX as usize];
+        assert_eq!(sender.send(&packet).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(buf.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_interleaved_rtp_and_sender_report() {
+        let buf = SharedBuf::default();
+        let mut sender = RtpSender::interleaved(Box::new(buf.clone()), 0, 1, RtcpSender::new("cname".to_string(), 90000));
+        let mut packet = RtpPacket::new(96, 7, 0, 1234, true);
+        packet.payload = vec![1, 2, 3];
+        sender.send(&packet).unwrap();
+
+        let bytes = buf.0.lock().unwrap().clone();
+        assert_eq!(&bytes[..4], &[0x24, 0, 0, 15]);
+        assert_eq!(&bytes[4..19], packet.to_bytes().as_slice());
+        // 第一个 rtp 包之后紧跟 SR + SDES, 走 rtcp 通道
+        assert_eq!(&bytes[19..21], &[0x24, 1]);
+        assert_eq!(bytes[23], 0x80);
+        assert_eq!(bytes[24], 200);
+        assert_eq!(sender.rtcp().packet_count(), 1);
+        assert_eq!(sender.rtcp().octet_count(), 3);
+    }
+
+    #[test]
+    fn test_nack_retransmission() {
+        let buf = SharedBuf::default();
+        let nacks: NackQueue = Default::default();
+        let retransmitter = Retransmitter::new(Retransmission::InStream, 16);
+        let mut sender = RtpSender::interleaved(Box::new(buf.clone()), 0, 1, RtcpSender::new("cname".to_string(), 90000))
+            .with_retransmission(retransmitter, Arc::clone(&nacks));
+        let first = RtpPacket::new(96, 1, 0, 1234, false);
+        sender.send(&first).unwrap();
+        buf.0.lock().unwrap().clear();
+
+        nacks.lock().unwrap().extend([1]);
+        let second = RtpPacket::new(96, 2, 0, 1234, false);
+        sender.send(&second).unwrap();
+        let bytes = buf.0.lock().unwrap().clone();
+        // 先发新包, 再重传 NACK 的包
+        assert_eq!(&bytes[4..16], second.to_bytes().as_slice());
+        assert_eq!(&bytes[20..32], first.to_bytes().as_slice());
+        assert!(nacks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_udp_sender() {
+        let localhost: IpAddr = "127.0.0.1".parse().unwrap();
+        let sockets = Arc::new(UdpPair::bind(localhost).unwrap());
+        let (rtp_port, rtcp_port) = sockets.ports().unwrap();
+        assert_eq!(rtp_port % 2, 0);
+        assert_eq!(rtcp_port, rtp_port + 1);
+
+        let client = UdpPair::bind(localhost).unwrap();
+        let (client_rtp, client_rtcp) = client.ports().unwrap();
+        let mut sender = RtpSender::udp(sockets, localhost, client_rtp, client_rtcp, RtcpSender::new("cname".to_string(), 90000));
+        let packet = RtpPacket::new(96, 0, 0, 1234, true);
+        sender.send(&packet).unwrap();
+
+        let mut buf = [0u8; 1500];
+        let n = client.rtp.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], packet.to_bytes().as_slice());
+        let n = client.rtcp.recv(&mut buf).unwrap();
+        assert_eq!(buf[1], 200);
+        assert!(n > 28);
+    }
+}
@@ -60,11 +60,19 @@ pub struct H264Fmtp {
     pub profile_level_id: Vec<u8>,
     pub sps: Vec<u8>,
     pub pps: Vec<u8>,
+    // 以下只在 packetization-mode=2 时输出
+    pub sprop_interleaving_depth: Option<u16>,
+    pub sprop_deint_buf_req: Option<u32>,
+    pub sprop_max_don_diff: Option<u16>,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct H265Fmtp {
     pub payload_type: u16,
+    // 来自 SPS 的 profile_tier_level, 为空时不输出 (接收端按 Main profile, level 3.1 处理)
+    pub profile_id: Option<u8>,
+    pub tier_flag: Option<u8>,
+    pub level_id: Option<u8>,
     pub vps: Vec<u8>,
     pub sps: Vec<u8>,
     pub pps: Vec<u8>,
@@ -79,10 +87,19 @@ impl<'a> From<H264Fmtp> for String {
         let sps_str = general_purpose::STANDARD.encode(&fmtp.sps);
         let pps_str = general_purpose::STANDARD.encode(&fmtp.pps);
 
-        let h264_fmtp = format!(
+        let mut h264_fmtp = format!(
             "{} packetization-mode={}; profile-level-id={}; sprop-parameter-sets={},{}",
             fmtp.payload_type, fmtp.packetization_mode, profile_level_id_str, sps_str, pps_str
         );
+        if let Some(depth) = fmtp.sprop_interleaving_depth {
+            h264_fmtp = format!("{}; sprop-interleaving-depth={}", h264_fmtp, depth);
+        }
+        if let Some(buf_req) = fmtp.sprop_deint_buf_req {
+            h264_fmtp = format!("{}; sprop-deint-buf-req={}", h264_fmtp, buf_req);
+        }
+        if let Some(don_diff) = fmtp.sprop_max_don_diff {
+            h264_fmtp = format!("{}; sprop-max-don-diff={}", h264_fmtp, don_diff);
+        }
         
         format!("{}\r\n", h264_fmtp)
     }
@@ -94,10 +111,17 @@ impl<'a> From<H265Fmtp> for String {
         let pps_str = general_purpose::STANDARD.encode(&fmtp.pps);
         let vps_str = general_purpose::STANDARD.encode(&fmtp.vps);
 
-        let h265_fmtp = format!(
-            "{} sprop-vps={}; sprop-sps={}; sprop-pps={}",
-            fmtp.payload_type, vps_str, sps_str, pps_str
-        );
+        let mut h265_fmtp = format!("{} ", fmtp.payload_type);
+        if let Some(profile_id) = fmtp.profile_id {
+            h265_fmtp = format!("{}profile-id={}; ", h265_fmtp, profile_id);
+        }
+        if let Some(tier_flag) = fmtp.tier_flag {
+            h265_fmtp = format!("{}tier-flag={}; ", h265_fmtp, tier_flag);
+        }
+        if let Some(level_id) = fmtp.level_id {
+            h265_fmtp = format!("{}level-id={}; ", h265_fmtp, level_id);
+        }
+        h265_fmtp = format!("{}sprop-vps={}; sprop-sps={}; sprop-pps={}", h265_fmtp, vps_str, sps_str, pps_str);
 
         format!("{}\r\n", h265_fmtp)
     }
@@ -120,6 +144,7 @@ pub struct MediaInfo {
     pub rtpmap: RtpMap,
     pub fmtp: Option<Fmtp>,
     pub attribute: HashMap<String, String>,  // other attribute.
+    pub extra_attributes: Vec<(String, String)>, // 可重复的属性, 如附加的 rtpmap/fmtp, 按顺序输出在 fmtp 之后.
 }
 
 impl<'a> From<MediaInfo> for String {
@@ -155,6 +180,10 @@ impl<'a> From<MediaInfo> for String {
             res = format!("{}a=fmtp:{}", res, fmtp_string);
         }
 
+        for (k, v) in media_info.extra_attributes {
+            res = format!("{res}a={k}:{v}\r\n");
+        }
+
         for (k, v) in media_info.attribute {
             res = format!("{res}a={k}:{v}\r\n");
         }
@@ -219,11 +248,12 @@ impl SDP {
 #[cfg(test)]
 mod tests{
     use std::collections::HashMap;
-    use crate::session::H264Fmtp;
-    use crate::session::MediaInfo;
+    use super::H264Fmtp;
+    use super::MediaInfo;
     use super::SDP;
     use super::RtpMap;
     use super::Fmtp;
+    use super::H265Fmtp;
 
     #[test]
     fn test_generate_sdp() {
@@ -252,8 +282,21 @@ mod tests{
         let sdp = SDP::new("123455556".to_string(), "10.15.112.58".to_string(), vec![media_info], None);
         // println!("sdp: {}", String::from(sdp));
 
-        let target = "v=0\r\no=rust-rtsp-server 123455556 0 IN IP4 10.15.112.58\r\ns=seminar\r\nc=IN IP4 10.15.112.58\r\nt=0 0\r\nm=video 2007 RTP/AVP 96\r\na=rtpmap:96 H264/90000\r\na=fmtp:96 packetization-mode=1;profile-level-id=104230\r\n";
+        let target = "v=0\r\no=- 123455556 0 IN IP4 10.15.112.58\r\ns=seminar\r\nc=IN IP4 10.15.112.58\r\nt=0 0\r\nm=video 2007 RTP/AVP 96\r\na=rtpmap:96 H264/90000\r\na=fmtp:96 packetization-mode=1; profile-level-id=104230; sprop-parameter-sets=,\r\na=control:track0\r\n";
         assert_eq!(String::from(sdp), target);
     }
-}
 
+    #[test]
+    fn test_h265_fmtp() {
+        let mut fmtp = H265Fmtp { payload_type: 96, vps: vec![0x40, 0x01], ..Default::default() };
+        assert_eq!(String::from(fmtp.clone()), "96 sprop-vps=QAE=; sprop-sps=; sprop-pps=\r\n");
+
+        fmtp.profile_id = Some(1);
+        fmtp.tier_flag = Some(0);
+        fmtp.level_id = Some(120);
+        assert_eq!(
+            String::from(fmtp),
+            "96 profile-id=1; tier-flag=0; level-id=120; sprop-vps=QAE=; sprop-sps=; sprop-pps=\r\n"
+        );
+    }
+}
@@ -1,10 +1,14 @@
-use std::{collections::HashMap, fs::File, sync::{mpsc::Sender, Arc, Mutex}};
-use crate::{codec::parse::ParameterSet, rtp::{rtp_h264::RtpSinkH264, rtp_h265::RtpSinkH265, rtp_packet::RtpSink}, sdp::{Fmtp, H264Fmtp, H265Fmtp, MediaInfo, RtpMap, SDP}};
-use crate::codec::parse;
+use std::{collections::HashMap, io::{self, Write}, net::{IpAddr, SocketAddr}, sync::{mpsc::Sender, Arc, Mutex}, time::SystemTime};
+use crate::{codec::{h264_sps::{Sps, SpsRewrite}, parse::ParameterSet, probe::{self, ProbeError}}, rtp::{extension::{ExtensionKind, ExtensionMap, HeaderExtender}, fec::{FecEncoder, Ulpfec}, pacer::{LatePolicy, Pacer, SystemClock}, qos::QosStats, retransmit::{NackQueue, Retransmission, Retransmitter, HISTORY_SIZE}, rtcp::RtcpSender, rtp_h264::{PacketizationMode, RtpSinkH264}, rtp_h265::RtpSinkH265, rtp_packet::RtpSink, transport::{RtpSender, Transport, UdpPair}}, sdp::{Fmtp, H264Fmtp, H265Fmtp, MediaInfo, RtpMap, SDP}};
 
 const RTP_PAYLOAD_TYPE_H26X: u8 = 96; // 媒体类型-视频
 const RTP_PAYLOAD_TYPE_AAC: u8 = 97;  // 媒体类型-音频
 const RTP_PAYLOAD_TYPE_PCMA: u8 = 8;  // 媒体类型-音频
+const RTP_PAYLOAD_TYPE_RTX: u8 = 98;  // RFC 4588 重传流
+const RTP_PAYLOAD_TYPE_ULPFEC: u8 = 99; // RFC 5109 前向纠错流
+const VIDEO_CLOCK_RATE: u32 = 90000;
+const DEFAULT_FPS: f64 = 25.0; // 参数集中没有帧率时使用
+const SPROP_DEINT_BUF_REQ: u32 = 1 << 20; // mode 2 接收端的解交织缓冲区大小, 按解码顺序发送时实际不需要缓存
 
 pub enum Track{
     Audio,
@@ -32,10 +36,29 @@ impl From<Track> for String {
     }
 }
 
+/// 从 SPS 得到的视频参数.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VideoInfo {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: Option<f64>, // VUI 中没有 timing_info 时为空
+}
+
 pub struct Session<'a> {
     session_name: &'a str,  // used for identifying the session.
     pub session_id: String,     // used for setupting the session, not session id in sdp.
     pub rtp_sinks: HashMap<String, Arc<Mutex<Box<dyn RtpSink>>>>, // used for sending the rtp packets. key is the track id.
+    transports: HashMap<String, (Transport, Option<Arc<UdpPair>>)>, // negotiated in SETUP, udp 时带服务端端口.
+    ssrc: u32,
+    qos: Arc<Mutex<QosStats>>, // 客户端 RTCP 报告的接收质量.
+    nacks: NackQueue,
+    retransmission: Retransmission, // 只对 udp 客户端生效.
+    fec: Option<Ulpfec>,            // 只对 udp 客户端生效.
+    extensions: ExtensionMap,       // 通过 a=extmap 声明的 rtp 头扩展.
+    late_policy: LatePolicy,        // 发送落后于时间戳时的处理方式.
+    video_info: Option<VideoInfo>,  // 用于发送节奏和 SDP 的帧率, 分辨率.
+    sps_rewrite: SpsRewrite,        // 按挂载点修改 H.264 SPS.
+    probed_sps: Option<Vec<u8>>,    // 探测到的原始 H.264 SPS, 每次都从它改写.
     fmtps: HashMap<String, Fmtp>, // used for setting the fmtp.
     rtpmaps: HashMap<String, RtpMap>, // used for setting the rtpmap.
     video_file: Option<Arc<String>>, // used for storing the video file.
@@ -43,8 +66,27 @@ pub struct Session<'a> {
     tx_play: Sender<bool>, // TODO: use async channel
 }
 
+/// 客户端 RTCP 的处理入口: 更新 QoS 统计, 把 NACK 交给 rtp 发送线程.
+#[derive(Clone)]
+pub struct RtcpHandler {
+    qos: Arc<Mutex<QosStats>>,
+    nacks: NackQueue,
+    ssrc: u32,
+}
+
+impl RtcpHandler {
+    pub fn handle(&self, data: &[u8]) {
+        match self.qos.lock().unwrap().on_rtcp(data, self.ssrc, VIDEO_CLOCK_RATE, SystemTime::now()) {
+            Ok(lost) if !lost.is_empty() => self.nacks.lock().unwrap().extend(lost),
+            Ok(_) => {}
+            Err(e) => log::warn!("invalid rtcp packet: {:?}", e),
+        }
+    }
+}
+
 impl<'a> Session<'a> {
-    pub fn new(session_name: &'a str, video_file: Option<Arc<String>>, audio_file: Option<Arc<String>>, tx_play: Sender<bool>) -> Self {
+    /// 视频文件无法读取或编码格式不支持时返回错误.
+    pub fn new(session_name: &'a str, video_file: Option<Arc<String>>, audio_file: Option<Arc<String>>, tx_play: Sender<bool>) -> Result<Self, ProbeError> {
         const CHARSET: &[u8] = b"0123456789";
         const SESSION_LEN: usize = 10;
         use rand::Rng;
@@ -59,6 +101,17 @@ impl<'a> Session<'a> {
             session_name,
             session_id,
             rtp_sinks: HashMap::new(),
+            transports: HashMap::new(),
+            ssrc: rand::random(),
+            qos: Arc::new(Mutex::new(QosStats::default())),
+            nacks: NackQueue::default(),
+            retransmission: Retransmission::Off,
+            fec: None,
+            extensions: ExtensionMap::default(),
+            late_policy: LatePolicy::default(),
+            video_info: None,
+            sps_rewrite: SpsRewrite::default(),
+            probed_sps: None,
             fmtps: HashMap::new(),
             rtpmaps: HashMap::new(),
             video_file,
@@ -66,27 +119,82 @@ impl<'a> Session<'a> {
             tx_play,
         };
 
-        session.add_fmtp(Track::Video);
-        
+        session.add_fmtp(Track::Video)?;
+        session.add_video_sink();
+        Ok(session)
+    }
+
+    /// 按视频的 fmtp 创建 rtp 发送端, fmtp 改变后需要重新创建.
+    fn add_video_sink(&mut self) {
         // let file = "./test.h264";
-        let file = session.video_file.as_ref().unwrap().clone();
-        let clock_rate = 90000;
-        let fps = 25;
-        let ssrc = 12345678;
-        match session.fmtps.get(&String::from(Track::Video)).unwrap() {
+        let file = self.video_file.as_ref().unwrap().clone();
+        let clock_rate = VIDEO_CLOCK_RATE;
+        let fps = self.video_fps();
+        let ssrc = self.ssrc;
+        let rtp_sink: Box<dyn RtpSink> = match self.fmtps.get(&String::from(Track::Video)).unwrap() {
             Fmtp::H264(fmtp) => {
-                let rtp_sink: Box<dyn RtpSink> = Box::new(RtpSinkH264::new(file, fmtp.payload_type as u8, clock_rate, fps, ssrc, true));
-                let rtp_sink_arc = Arc::new(Mutex::new(rtp_sink));
-                session.add_rtp_sink(Track::Video, Arc::clone(&rtp_sink_arc));
+                let mode = PacketizationMode::try_from(fmtp.packetization_mode).unwrap_or_default();
+                Box::new(RtpSinkH264::new(file, fmtp.payload_type as u8, clock_rate, fps, ssrc, true).with_packetization_mode(mode).with_sps_rewrite(self.sps_rewrite))
             }
             Fmtp::H265(fmtp) => {
-                let rtp_sink: Box<dyn RtpSink> = Box::new(RtpSinkH265::new(file, fmtp.payload_type as u8, clock_rate, fps, ssrc, true));
-                let rtp_sink_arc = Arc::new(Mutex::new(rtp_sink));
-                session.add_rtp_sink(Track::Video, Arc::clone(&rtp_sink_arc));
+                Box::new(RtpSinkH265::new(file, fmtp.payload_type as u8, clock_rate, fps, ssrc, true))
             }
         };
+        self.add_rtp_sink(Track::Video, Arc::new(Mutex::new(rtp_sink)));
+    }
+
+    /// 设置 H.264 的 packetization-mode, 需要在 DESCRIBE 之前调用; H.265 时忽略.
+    pub fn set_packetization_mode(&mut self, mode: PacketizationMode) {
+        let Some(Fmtp::H264(fmtp)) = self.fmtps.get_mut(&String::from(Track::Video)) else {
+            if mode != PacketizationMode::default() {
+                log::warn!("packetization-mode only applies to h264, ignored");
+            }
+            return;
+        };
+        let interleaved = mode == PacketizationMode::Interleaved;
+        fmtp.packetization_mode = mode as u8;
+        fmtp.sprop_interleaving_depth = interleaved.then_some(0);
+        fmtp.sprop_deint_buf_req = interleaved.then_some(SPROP_DEINT_BUF_REQ);
+        fmtp.sprop_max_don_diff = interleaved.then_some(0);
+        self.add_video_sink();
+    }
 
-        session
+    /// 改写 H.264 的 SPS, 需要在 DESCRIBE 之前调用; sprop-parameter-sets 和码流中的 SPS 一起修改.
+    /// 总是从探测到的原始 SPS 改写, 多次调用时只有最后一次生效.
+    pub fn set_sps_rewrite(&mut self, rewrite: SpsRewrite) {
+        let (Some(Fmtp::H264(fmtp)), Some(probed_sps)) = (self.fmtps.get_mut(&String::from(Track::Video)), &self.probed_sps) else {
+            if !rewrite.is_empty() {
+                log::warn!("sps rewrite only applies to h264, ignored");
+            }
+            return;
+        };
+        let sps = match rewrite.rewrite(probed_sps) {
+            Ok(sps) => sps,
+            Err(e) => {
+                log::warn!("rewrite sps failed: {:?}, ignored", e);
+                return;
+            }
+        };
+        fmtp.profile_level_id = sps.get(1..4).unwrap_or_default().to_vec();
+        if let Ok(parsed) = Sps::parse(&sps) {
+            let (width, height) = parsed.parse_width_height();
+            self.video_info = Some(VideoInfo { width, height, frame_rate: parsed.frame_rate() });
+        }
+        fmtp.sps = sps;
+        self.sps_rewrite = rewrite;
+        self.add_video_sink();
+    }
+
+    pub fn video_info(&self) -> Option<VideoInfo> {
+        self.video_info
+    }
+
+    /// rtp 时间戳的帧率, 保留小数, 与 a=framerate 一致.
+    fn video_fps(&self) -> f64 {
+        match self.video_info.and_then(|info| info.frame_rate) {
+            Some(frame_rate) if frame_rate >= 1.0 => frame_rate,
+            _ => DEFAULT_FPS,
+        }
     }
 
     fn add_rtp_sink(&mut self, track: Track, rtp_sink: Arc<Mutex<Box<dyn RtpSink>>>) {
@@ -98,32 +206,156 @@ impl<'a> Session<'a> {
         rtpsink
     }
     
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    /// 记录 SETUP 协商的传输方式, udp 时在 `local_ip` 上分配服务端端口对并返回.
+    pub fn setup(&mut self, track: Track, transport: Transport, local_ip: IpAddr) -> io::Result<Option<(u16, u16)>> {
+        let (udp, server_ports) = match transport {
+            Transport::Udp { .. } => {
+                let pair = UdpPair::bind(local_ip)?;
+                let ports = pair.ports()?;
+                (Some(Arc::new(pair)), Some(ports))
+            }
+            Transport::Interleaved { .. } => (None, None),
+        };
+        self.transports.insert(String::from(track), (transport, udp));
+        Ok(server_ports)
+    }
+
+    /// 按 SETUP 的结果创建发送端; 没有 SETUP 过时按 interleaved=0-1 发送.
+    /// `stream` 为 rtsp 控制连接, `peer` 为客户端地址.
+    pub fn rtp_sender(&self, track: Track, stream: Box<dyn Write + Send>, peer: IpAddr) -> RtpSender {
+        let rtcp = RtcpSender::new(self.cname(), VIDEO_CLOCK_RATE);
+        let sender = match self.transports.get(&String::from(track)) {
+            Some((Transport::Udp { client_rtp_port, client_rtcp_port }, Some(udp))) => {
+                let mut sender = RtpSender::udp(Arc::clone(udp), peer, *client_rtp_port, *client_rtcp_port, rtcp);
+                if self.retransmission != Retransmission::Off {
+                    let retransmitter = Retransmitter::new(self.retransmission, HISTORY_SIZE);
+                    sender = sender.with_retransmission(retransmitter, Arc::clone(&self.nacks));
+                }
+                if let Some(fec) = self.fec {
+                    sender = sender.with_fec(FecEncoder::new(fec));
+                }
+                sender
+            }
+            Some((Transport::Interleaved { rtp_channel, rtcp_channel }, _)) => {
+                RtpSender::interleaved(stream, *rtp_channel, *rtcp_channel, rtcp)
+            }
+            _ => RtpSender::interleaved(stream, 0, 1, rtcp),
+        };
+        let sender = sender.with_pacer(Pacer::new(Box::new(SystemClock), VIDEO_CLOCK_RATE, self.late_policy));
+        if self.extensions.is_empty() {
+            return sender;
+        }
+        sender.with_extensions(HeaderExtender::new(self.extensions.clone(), VIDEO_CLOCK_RATE))
+    }
+
+    pub fn qos(&self) -> Arc<Mutex<QosStats>> {
+        Arc::clone(&self.qos)
+    }
+
+    /// 控制连接上收到的 interleaved 数据是否为 RTCP.
+    pub fn is_rtcp_channel(&self, channel: u8) -> bool {
+        self.transports.values().any(|(transport, _)| {
+            matches!(transport, Transport::Interleaved { rtcp_channel, .. } if *rtcp_channel == channel)
+        })
+    }
+
+    /// udp 传输时服务端的 rtp/rtcp 端口对, 用于接收客户端的 RTCP.
+    pub fn udp_sockets(&self, track: Track) -> Option<Arc<UdpPair>> {
+        self.transports.get(&String::from(track)).and_then(|(_, udp)| udp.clone())
+    }
+
+    /// udp 传输时 SETUP 协商的客户端 RTCP 地址, 其他来源的 RTCP 不予处理.
+    pub fn client_rtcp_addr(&self, track: Track, peer: IpAddr) -> Option<SocketAddr> {
+        match self.transports.get(&String::from(track)) {
+            Some((Transport::Udp { client_rtcp_port, .. }, _)) => Some(SocketAddr::new(peer, *client_rtcp_port)),
+            _ => None,
+        }
+    }
+
+    /// 处理客户端发来的 RTCP 复合包.
+    pub fn on_rtcp(&self, data: &[u8]) {
+        self.rtcp_handler().handle(data);
+    }
+
+    /// 不持有 session 锁处理 RTCP 时使用, 例如 udp 接收线程.
+    pub fn rtcp_handler(&self) -> RtcpHandler {
+        RtcpHandler {
+            qos: Arc::clone(&self.qos),
+            nacks: Arc::clone(&self.nacks),
+            ssrc: self.ssrc,
+        }
+    }
+
+    /// 开启 udp 客户端的 NACK 重传, `rtx` 为 true 时使用 RFC 4588 RTX 流.
+    pub fn enable_retransmission(&mut self, rtx: bool) {
+        self.retransmission = if rtx {
+            Retransmission::Rtx { payload_type: RTP_PAYLOAD_TYPE_RTX, ssrc: rand::random() }
+        } else {
+            Retransmission::InStream
+        };
+    }
+
+    /// 开启 udp 客户端的 ULPFEC, 每 `group_size` (1~16) 个包生成一个 FEC 包.
+    pub fn enable_fec(&mut self, group_size: u8) {
+        self.fec = Some(Ulpfec { payload_type: RTP_PAYLOAD_TYPE_ULPFEC, ssrc: rand::random(), group_size });
+    }
+
+    /// 开启 rtp 头扩展, id 按 `kinds` 的顺序从 1 开始分配.
+    pub fn enable_header_extensions(&mut self, kinds: &[ExtensionKind]) {
+        self.extensions = ExtensionMap::new(kinds);
+    }
+
+    /// 设置发送落后时是连续追赶还是跳过落后的时间.
+    pub fn set_late_policy(&mut self, policy: LatePolicy) {
+        self.late_policy = policy;
+    }
+
+    fn cname(&self) -> String {
+        format!("rtsp-server-{}", self.session_id)
+    }
+
     fn add_rtpmap(&mut self, track: Track) {
         todo!()
     }
 
-    fn add_fmtp(&mut self, track: Track) {
+    fn add_fmtp(&mut self, track: Track) -> Result<(), ProbeError> {
         match track {
             Track::Video => {
                 let video_file = self.video_file.clone().unwrap();
-                let mut file = File::open(video_file.as_ref()).unwrap();
-                if let ParameterSet::H264 { sps, pps } = parse::parse_h264(&mut file) {
-                    let mut h264_fmtp = H264Fmtp::default();
-                    h264_fmtp.payload_type = 96;
-                    h264_fmtp.packetization_mode = 1;
-                    h264_fmtp.profile_level_id = sps[1..4].to_vec();
-                    h264_fmtp.sps = sps;
-                    h264_fmtp.pps = pps;
-                    self.fmtps.insert(String::from(track), Fmtp::H264(h264_fmtp));
-                }else if let ParameterSet::H265 { vps, sps, pps } = parse::parse_h265(&mut file) {
-                    let mut h265_fmtp = H265Fmtp::default();
-                    h265_fmtp.payload_type = 96;
-                    h265_fmtp.vps = vps;
-                    h265_fmtp.sps = sps;
-                    h265_fmtp.pps = pps;
-                    self.fmtps.insert(String::from(track), Fmtp::H265(h265_fmtp));
-                    
+                let info = probe::probe_file(video_file.as_ref())?;
+                self.video_info = Some(VideoInfo { width: info.width, height: info.height, frame_rate: info.frame_rate });
+                match info.parameter_set {
+                    ParameterSet::H264 { sps, pps } => {
+                        self.probed_sps = Some(sps.clone());
+                        let h264_fmtp = H264Fmtp {
+                            payload_type: 96,
+                            packetization_mode: 1,
+                            profile_level_id: sps.get(1..4).unwrap_or_default().to_vec(),
+                            sps,
+                            pps,
+                            ..Default::default()
+                        };
+                        self.fmtps.insert(String::from(track), Fmtp::H264(h264_fmtp));
+                    }
+                    ParameterSet::H265 { vps, sps, pps } => {
+                        let h265_fmtp = H265Fmtp {
+                            payload_type: 96,
+                            profile_id: Some(info.profile_idc),
+                            tier_flag: Some(info.tier_flag),
+                            level_id: Some(info.level_idc),
+                            vps,
+                            sps,
+                            pps,
+                        };
+                        self.fmtps.insert(String::from(track), Fmtp::H265(h265_fmtp));
+                    }
+                    ParameterSet::Other => return Err(ProbeError::Unsupported),
                 }
+                Ok(())
             },
             Track::Audio => {
                 todo!()
@@ -140,7 +372,7 @@ impl<'a> Session<'a> {
         let mut rtpmap = RtpMap::default();
         rtpmap.payload_type = RTP_PAYLOAD_TYPE_H26X as u16;
         rtpmap.encoding_name = encoding_name.to_string(); 
-        rtpmap.clock_rate = 90000;
+        rtpmap.clock_rate = VIDEO_CLOCK_RATE;
         rtpmap.encoding_param = String::from("");
 
         let mut video_media_info = MediaInfo::default();
@@ -154,6 +386,41 @@ impl<'a> Session<'a> {
             video_media_info.fmtp = Some(self.fmtps.get("video").unwrap().clone());   
             video_media_info.attribute = HashMap::new();
             video_media_info.attribute.insert("control".to_string(), "track1".to_string());
+            if let Some(info) = self.video_info {
+                let extra = &mut video_media_info.extra_attributes;
+                if let Some(frame_rate) = info.frame_rate {
+                    extra.push(("framerate".to_string(), format!("{}", (frame_rate * 100.0).round() / 100.0)));
+                }
+                extra.push(("framesize".to_string(), format!("{} {}-{}", RTP_PAYLOAD_TYPE_H26X, info.width, info.height)));
+                extra.push(("x-dimensions".to_string(), format!("{},{}", info.width, info.height)));
+            }
+
+            if self.retransmission != Retransmission::Off {
+                video_media_info.extra_attributes.push(("rtcp-fb".to_string(), format!("{} nack", RTP_PAYLOAD_TYPE_H26X)));
+            }
+            if let Retransmission::Rtx { payload_type, ssrc } = self.retransmission {
+                video_media_info.fmts.push(payload_type);
+                let extra = &mut video_media_info.extra_attributes;
+                extra.push(("rtpmap".to_string(), format!("{} rtx/{}", payload_type, VIDEO_CLOCK_RATE)));
+                extra.push(("fmtp".to_string(), format!("{} apt={}", payload_type, RTP_PAYLOAD_TYPE_H26X)));
+                extra.push(("ssrc-group".to_string(), format!("FID {} {}", self.ssrc, ssrc)));
+                extra.push(("ssrc".to_string(), format!("{} cname:{}", ssrc, self.cname())));
+            }
+            // 纠错流不封装在 RED 中, 只声明 ulpfec 负载类型, 不能用 FlexFEC 的 FEC-FR 分组
+            if let Some(Ulpfec { payload_type, ssrc, .. }) = self.fec {
+                video_media_info.fmts.push(payload_type);
+                let extra = &mut video_media_info.extra_attributes;
+                extra.push(("rtpmap".to_string(), format!("{} ulpfec/{}", payload_type, VIDEO_CLOCK_RATE)));
+                extra.push(("ssrc".to_string(), format!("{} cname:{}", ssrc, self.cname())));
+            }
+            for extmap in self.extensions.extmap() {
+                video_media_info.extra_attributes.push(("extmap".to_string(), extmap));
+            }
+            // 附加的 rtx/fec 流与媒体流同属一个 cname
+            if matches!(self.retransmission, Retransmission::Rtx { .. }) || self.fec.is_some() {
+                let cname = ("ssrc".to_string(), format!("{} cname:{}", self.ssrc, self.cname()));
+                video_media_info.extra_attributes.push(cname);
+            }
         }
         
         use std::time::{SystemTime, UNIX_EPOCH};
@@ -174,7 +441,50 @@ impl<'a> Session<'a> {
     }
 
     pub fn teardown(&self) {
-        self.tx_play.send(false).unwrap();
+        // rtp 线程可能已经退出
+        let _ = self.tx_play.send(false);
+    }
+
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::test_data::{H264_PPS, H264_SPS};
+
+    #[test]
+    fn test_unsupported_video_file() {
+        let path = std::env::temp_dir().join(format!("unsupported-{}.h264", std::process::id()));
+        std::fs::write(&path, b"not a video").unwrap();
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let result = Session::new("session", Some(Arc::new(path.to_string_lossy().into_owned())), None, tx);
+        assert!(matches!(result, Err(ProbeError::NotAnnexB)));
+        std::fs::remove_file(&path).unwrap();
+
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let result = Session::new("session", Some(Arc::new(path.to_string_lossy().into_owned())), None, tx);
+        assert!(matches!(result, Err(ProbeError::Io(_))));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_sps_rewrite_from_probed_sps() {
+        let (sps, pps) = (H264_SPS, H264_PPS);
+        let path = std::env::temp_dir().join(format!("sps-rewrite-{}.h264", std::process::id()));
+        std::fs::write(&path, [&[0, 0, 0, 1][..], &sps, &[0, 0, 0, 1], &pps].concat()).unwrap();
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let mut session = Session::new("session", Some(Arc::new(path.to_string_lossy().into_owned())), None, tx).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // 第二次 DESCRIBE 的改写不叠加第一次的结果
+        session.set_sps_rewrite(SpsRewrite { level_idc: Some(31), ..Default::default() });
+        let rewrite = SpsRewrite { frame_rate: Some(30), ..Default::default() };
+        session.set_sps_rewrite(rewrite);
+        let Some(Fmtp::H264(fmtp)) = session.fmtps.get("video") else { panic!("no h264 fmtp") };
+        assert_eq!(fmtp.sps, rewrite.rewrite(&sps).unwrap());
+        assert_eq!(fmtp.profile_level_id, vec![0x64, 0x00, 0x28]);
+        assert_eq!(session.video_info().unwrap().frame_rate, Some(30.0));
+
+        session.set_sps_rewrite(SpsRewrite::default());
+        let Some(Fmtp::H264(fmtp)) = session.fmtps.get("video") else { panic!("no h264 fmtp") };
+        assert_eq!(fmtp.sps, sps.to_vec());
+    }
+}
@@ -0,0 +1,129 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+use serde::Deserialize;
+
+/// CIDR 网段, 例如 "10.0.0.0/8", "fd00::/64"; 不带前缀长度时表示单个地址.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // 双栈监听时 ipv4 客户端的地址形如 ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            IpAddr::V4(_) => ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|e| format!("invalid address in {:?}: {}", s, e))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().ok().filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid prefix length in {:?}", s))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// 基于来源地址的访问控制: 命中 `deny` 拒绝, `allow` 为空时放行, 否则必须命中 `allow`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct IpFilter {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+impl IpFilter {
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|cidr| cidr.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let lan: Cidr = "192.168.10.0/24".parse().unwrap();
+        assert!(lan.contains(ip("192.168.10.200")));
+        assert!(!lan.contains(ip("192.168.11.1")));
+        assert!(lan.contains(ip("::ffff:192.168.10.7")));
+        assert!(!lan.contains(ip("fd00::1")));
+
+        let ula: Cidr = "fd00:1::/32".parse().unwrap();
+        assert!(ula.contains(ip("fd00:1:ffff::1")));
+        assert!(!ula.contains(ip("fd00:2::1")));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("8.8.8.8")));
+        let host: Cidr = "10.0.0.1".parse().unwrap();
+        assert_eq!(host.to_string(), "10.0.0.1/32");
+        assert!(!host.contains(ip("10.0.0.2")));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_ip_filter() {
+        let filter = IpFilter {
+            allow: vec!["10.0.0.0/8".parse().unwrap(), "::1".parse().unwrap()],
+            deny: vec!["10.0.66.0/24".parse().unwrap()],
+        };
+        assert!(filter.permits(ip("10.1.2.3")));
+        assert!(filter.permits(ip("::1")));
+        assert!(!filter.permits(ip("10.0.66.9")));
+        assert!(!filter.permits(ip("172.16.0.1")));
+
+        let deny_only = IpFilter { allow: vec![], deny: vec!["172.16.0.0/12".parse().unwrap()] };
+        assert!(deny_only.permits(ip("10.1.2.3")));
+        assert!(!deny_only.permits(ip("172.20.0.1")));
+        assert!(IpFilter::default().permits(ip("1.2.3.4")));
+    }
+}
@@ -1,12 +1,28 @@
 use base64::{engine::general_purpose, Engine};
+use hmac::{Hmac, Mac};
 use md5;
-use std::collections::HashMap;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, io, path::Path, sync::Mutex};
+
+use crate::{config::{AuthConfig, ProviderKind}, request::url_path};
 
 pub enum AuthType {
     Basic(Option<(String, String)>),
     Digest(Option<HashMap<String, String>>), // Digest 认证参数
 }
 
+impl AuthType {
+    /// 客户端声称的用户名, 认证是否通过都可以取, 用于失败计数和审计.
+    pub fn username(&self) -> Option<&str> {
+        match self {
+            AuthType::Basic(Some((username, _))) => Some(username),
+            AuthType::Digest(Some(params)) => params.get("username").map(String::as_str),
+            _ => None,
+        }
+    }
+}
+
 fn parse_basic_auth(auth_header: &str) -> Option<(String, String)> {
     let encoded = &auth_header[6..];
     let decoded = general_purpose::STANDARD.decode(encoded).ok()?;
@@ -21,64 +37,195 @@ fn parse_basic_auth(auth_header: &str) -> Option<(String, String)> {
 /// 使用示例
 /// let auth_header = "Digest username=\"admin\", nonce=\"abc123\", uri=\"rtsp://example.com/stream\", response=\"d41d8cd98f00b204e9800998ecf8427e\"";
 /// let auth_params = parse_digest_header(auth_header);
+///
+/// 引号内的值可以包含逗号, 例如 uri 中的查询参数.
 fn parse_digest_header(auth_header: &str) -> Option<HashMap<String, String> >{
     let mut params = HashMap::new();
-    let stripped = auth_header.trim_start_matches("Digest ");
-    for pair in stripped.split(',') {
-        let mut kv = pair.trim().splitn(2, '=');
-        if let (Some(key), Some(value)) = (kv.next(), kv.next()) {
-            let value = value.trim_matches('\"').to_string();
-            params.insert(key.trim().to_string(), value);
-        }
+    let mut rest = auth_header.trim().strip_prefix("Digest")?.trim_start();
+    while !rest.is_empty() {
+        let (key, after_key) = rest.split_once('=')?;
+        let after_key = after_key.trim_start();
+        let (value, after_value) = if let Some(quoted) = after_key.strip_prefix('"') {
+            let end = quoted.find('"')?;
+            (&quoted[..end], &quoted[end + 1..])
+        } else {
+            let end = after_key.find(',').unwrap_or(after_key.len());
+            (after_key[..end].trim_end(), &after_key[end..])
+        };
+        params.insert(key.trim().to_string(), value.to_string());
+        rest = after_value.trim_start().trim_start_matches(',').trim_start();
     }
     Some(params)
 }
 
-/// 使用示例
-/// let method = "DESCRIBE";
-/// let password = "secret"; // 服务器存储的密码
-/// let is_valid = validate_digest_response(&auth_params, method, realm, password);
-pub fn validate_digest_response(
-    auth_params: &HashMap<String, String>,
-    method: &str,
-    realm: &str,
-    password: &str,
-) -> bool {
-    // 提取必要参数
-    let username = auth_params.get("username").unwrap();
-    let nonce = auth_params.get("nonce").unwrap();
-    let uri = auth_params.get("uri").unwrap();
-    let client_response = auth_params.get("response").unwrap();
+/// Digest 支持的摘要算法 (RFC 7616).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum DigestAlgorithm {
+    #[default]
+    #[serde(rename = "MD5")]
+    Md5,
+    #[serde(rename = "SHA-256")]
+    Sha256,
+}
 
-    // 计算 HA1
-    let ha1_input = format!("{}:{}:{}", username, realm, password);
-    let ha1 = format!("{:x}", md5::compute(ha1_input.as_bytes()));
+impl DigestAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "MD5",
+            DigestAlgorithm::Sha256 => "SHA-256",
+        }
+    }
 
-    // 计算 HA2
-    let ha2_input = format!("{}:{}", method, uri);
-    let ha2 = format!("{:x}", md5::compute(ha2_input.as_bytes()));
+    /// 十六进制摘要
+    pub fn hash(&self, data: &str) -> String {
+        match self {
+            DigestAlgorithm::Md5 => format!("{:x}", md5::compute(data.as_bytes())),
+            DigestAlgorithm::Sha256 => Sha256::digest(data.as_bytes())
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        }
+    }
+}
 
-    // 计算服务器端 response
-    let server_response_input = format!("{}:{}:{}", ha1, nonce, ha2);
-    let server_response = &format!("{:x}", md5::compute(server_response_input.as_bytes()));
+impl std::str::FromStr for DigestAlgorithm {
+    type Err = ();
 
-    // 比对客户端和服务器端的 response
-    server_response == client_response
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "MD5" => Ok(DigestAlgorithm::Md5),
+            "SHA-256" => Ok(DigestAlgorithm::Sha256),
+            _ => Err(()),
+        }
+    }
 }
 
-
-pub fn validate_digest_auth(
-    username: &str,
-    password: &str,
+/// 计算 Digest response.
+/// 带 qop 时 response = H(HA1:nonce:nc:cnonce:qop:HA2), 否则按 RFC 2069 计算 H(HA1:nonce:HA2).
+pub fn digest_response(
+    algorithm: DigestAlgorithm,
+    ha1: &str,
     nonce: &str,
     method: &str,
     uri: &str,
-    response: &str,
+    qop: Option<(&str, &str, &str)>, // (nc, cnonce, qop)
+) -> String {
+    let ha2 = algorithm.hash(&format!("{}:{}", method, uri));
+    match qop {
+        Some((nc, cnonce, qop)) => {
+            algorithm.hash(&format!("{}:{}:{}:{}:{}:{}", ha1, nonce, nc, cnonce, qop, ha2))
+        }
+        None => algorithm.hash(&format!("{}:{}:{}", ha1, nonce, ha2)),
+    }
+}
+
+/// Basic 认证同样用 HA1 校验, 凭据存储里不需要保存明文密码.
+pub fn validate_basic_response(
+    username: &str,
+    password: &str,
+    realm: &str,
+    provider: &dyn AuthProvider,
 ) -> bool {
-    let ha1 = format!("{:x}", md5::compute(format!("{}:{}:{}", username, "realm", password)));
-    let ha2 = format!("{:x}", md5::compute(format!("{}:{}", method, uri)));
-    let expected_response = format!("{:x}", md5::compute(format!("{}:{}:{}", ha1, nonce, ha2)));
-    response == expected_response
+    [DigestAlgorithm::Md5, DigestAlgorithm::Sha256].iter().any(|&algorithm| {
+        match provider.ha1(username, realm, algorithm) {
+            Some(ha1) => {
+                let expected = compute_ha1(algorithm, username, realm, password);
+                constant_time_eq(ha1.to_ascii_lowercase().as_bytes(), expected.as_bytes())
+            }
+            None => false,
+        }
+    })
+}
+
+/// HA1 = H(username:realm:password)
+pub fn compute_ha1(algorithm: DigestAlgorithm, username: &str, realm: &str, password: &str) -> String {
+    algorithm.hash(&format!("{}:{}:{}", username, realm, password))
+}
+
+/// 凭据存储, Basic 和 Digest 校验都只依赖用户在某个 realm 下的 HA1.
+pub trait AuthProvider: Send + Sync {
+    fn ha1(&self, username: &str, realm: &str, algorithm: DigestAlgorithm) -> Option<String>;
+}
+
+/// 内存中的 用户名 -> 密码 映射, 适用于测试或少量用户.
+#[derive(Debug, Default)]
+pub struct MemoryAuthProvider {
+    users: HashMap<String, String>,
+}
+
+impl MemoryAuthProvider {
+    pub fn new(users: HashMap<String, String>) -> Self {
+        Self { users }
+    }
+}
+
+impl AuthProvider for MemoryAuthProvider {
+    fn ha1(&self, username: &str, realm: &str, algorithm: DigestAlgorithm) -> Option<String> {
+        let password = self.users.get(username)?;
+        Some(compute_ha1(algorithm, username, realm, password))
+    }
+}
+
+/// htdigest 格式的凭据文件, 每行 `username:realm:HA1`, 与 apache htdigest 工具生成的文件兼容.
+/// HA1 为 32 位十六进制时是 MD5, 64 位时是 SHA-256, 同一用户可以各有一行.
+#[derive(Debug, Default)]
+pub struct HtdigestAuthProvider {
+    entries: HashMap<(String, String, DigestAlgorithm), String>, // (username, realm, algorithm) -> HA1
+}
+
+impl HtdigestAuthProvider {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(content: &str) -> io::Result<Self> {
+        let mut entries = HashMap::new();
+        for (lineno, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.splitn(3, ':').collect();
+            let algorithm = match parts.get(2).map(|ha1| ha1.len()) {
+                Some(32) => Some(DigestAlgorithm::Md5),
+                Some(64) => Some(DigestAlgorithm::Sha256),
+                _ => None,
+            };
+            let valid = parts.len() == 3 && parts[2].chars().all(|c| c.is_ascii_hexdigit());
+            let Some(algorithm) = algorithm.filter(|_| valid) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid htdigest entry at line {}", lineno + 1),
+                ));
+            };
+            entries.insert(
+                (parts[0].to_string(), parts[1].to_string(), algorithm),
+                parts[2].to_ascii_lowercase(),
+            );
+        }
+        Ok(Self { entries })
+    }
+}
+
+impl AuthProvider for HtdigestAuthProvider {
+    fn ha1(&self, username: &str, realm: &str, algorithm: DigestAlgorithm) -> Option<String> {
+        self.entries
+            .get(&(username.to_string(), realm.to_string(), algorithm))
+            .cloned()
+    }
+}
+
+/// 根据配置创建凭据存储.
+pub fn provider_from_config(config: &AuthConfig) -> io::Result<Box<dyn AuthProvider>> {
+    match config.provider {
+        ProviderKind::Memory => Ok(Box::new(MemoryAuthProvider::new(config.users.clone()))),
+        ProviderKind::Htdigest => {
+            let file = config.file.as_ref().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "htdigest provider requires `file`")
+            })?;
+            Ok(Box::new(HtdigestAuthProvider::load(file)?))
+        }
+    }
 }
 
 pub fn get_auth_type(auth_header: &str) -> Option<AuthType> {
@@ -120,43 +267,219 @@ impl AuthHeaderGenerator for BasicAuthenticator {
     }
 }
 
+/// 认证失败的原因.
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    Malformed,          // 无法解析的 Authorization 头
+    InvalidCredentials, // Basic 用户名或密码错误
+    MissingParam(&'static str),
+    RealmMismatch,
+    UnsupportedAlgorithm,
+    UnsupportedQop,
+    OpaqueMismatch,
+    UriMismatch,
+    UnknownUser,
+    InvalidNonce,     // 不是本服务器签发的 nonce
+    InvalidResponse,  // 密码错误
+    StaleNonce,       // 摘要正确但 nonce 已过期, 应带 stale=true 重新质询
+    NonceCountReplay, // nc 没有递增
+}
+
+pub(crate) fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 比较耗时只取决于长度, 不泄露第一个不同字节的位置.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+pub(crate) fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// 服务器端 Digest 认证 (RFC 7616).
+///
+/// nonce = 签发时间(16 位十六进制) + HMAC-SHA256(secret, 签发时间), 服务器无需保存即可校验来源和有效期;
+/// 只有带 qop 的请求才记录 nonce 的 nc, 用于拒绝重放. `require_qop` 为 false 时接受 RFC 2069 客户端,
+/// 这些请求没有 nc, 截获的响应在 nonce 有效期内可以重放.
 pub struct DigestAuthenticator {
     realm: String,
-    nonce: String,
+    secret: [u8; 32],
     opaque: String,
-    pub stale: bool,   // nonce 是否过期
-    algorithm: String, // 目前只支持 MD5
+    algorithm: DigestAlgorithm,
+    nonce_lifetime: u64,    // 秒
+    require_qop: bool,      // 是否拒绝不带 qop 的 RFC 2069 客户端, 这些客户端没有重放保护
+    nonce_counts: Mutex<HashMap<String, (u64, u32)>>, // nonce -> (签发时间, 最近的 nc)
 }
 
+const NONCE_TIMESTAMP_LEN: usize = 16;
+
 impl DigestAuthenticator {
-    pub fn new(realm: &str, nonce: Option<String>) -> Self {
+    pub fn new(realm: &str, algorithm: DigestAlgorithm, nonce_lifetime: u64, require_qop: bool) -> Self {
         Self {
             realm: realm.to_string(),
-            nonce: nonce.unwrap_or(generate_nonce()), // 生成随机 nonce, 也可以从配置中读取
-            opaque: "".to_string(),
-            stale: false, // 默认不过期, 修改 nonce 时设置为 true.
-            algorithm: "MD5".to_string(),
+            secret: rand::random(),
+            opaque: generate_nonce(),
+            algorithm,
+            nonce_lifetime,
+            require_qop,
+            nonce_counts: Mutex::new(HashMap::new()),
         }
     }
-}
 
-impl AuthHeaderGenerator for DigestAuthenticator {
-    fn generate(&mut self) -> String {
-        if self.stale {
-            self.nonce = generate_nonce();
+    pub fn from_config(config: &AuthConfig) -> Self {
+        Self::new(&config.realm, config.algorithm, config.nonce_lifetime, config.require_qop)
+    }
+
+    pub fn realm(&self) -> &str {
+        &self.realm
+    }
+
+    fn sign(&self, timestamp: u64) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("hmac accepts any key size");
+        mac.update(format!("{:016x}", timestamp).as_bytes());
+        hex_encode(&mac.finalize().into_bytes())
+    }
+
+    fn issue_nonce(&self, now: u64) -> String {
+        format!("{:016x}{}", now, self.sign(now))
+    }
+
+    /// 校验 nonce 签名, 返回签发时间.
+    fn verify_nonce(&self, nonce: &str) -> Option<u64> {
+        if nonce.len() <= NONCE_TIMESTAMP_LEN || !nonce.is_ascii() {
+            return None;
         }
-        
+        let (timestamp, signature) = nonce.split_at(NONCE_TIMESTAMP_LEN);
+        let timestamp = u64::from_str_radix(timestamp, 16).ok()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("hmac accepts any key size");
+        mac.update(format!("{:016x}", timestamp).as_bytes());
+        mac.verify_slice(&hex_decode(signature)?).ok()?;
+        Some(timestamp)
+    }
+
+    /// 生成 WWW-Authenticate 质询, nonce 过期时带上 stale=true.
+    pub fn challenge(&self, stale: bool) -> String {
+        self.challenge_at(now_secs(), stale)
+    }
+
+    fn challenge_at(&self, now: u64, stale: bool) -> String {
         let mut header = format!(
-            r#"Digest realm="{}", nonce="{}", algorithm="{}"#,
-            self.realm, self.nonce, self.algorithm
+            r#"Digest realm="{}", qop="auth", algorithm={}, nonce="{}", opaque="{}""#,
+            self.realm,
+            self.algorithm.name(),
+            self.issue_nonce(now),
+            self.opaque
         );
 
-        if self.stale {
+        if stale {
             header.push_str(", stale=true");
         }
 
         header
     }
+
+    /// 校验 Authorization: Digest ... 参数, 成功时返回用户名.
+    /// `url` 为请求行中的 url, 必须与摘要中的 uri 一致.
+    pub fn validate(
+        &self,
+        params: &HashMap<String, String>,
+        method: &str,
+        url: &str,
+        provider: &dyn AuthProvider,
+    ) -> Result<String, AuthError> {
+        self.validate_at(params, method, url, provider, now_secs())
+    }
+
+    fn validate_at(
+        &self,
+        params: &HashMap<String, String>,
+        method: &str,
+        url: &str,
+        provider: &dyn AuthProvider,
+        now: u64,
+    ) -> Result<String, AuthError> {
+        let param = |name: &'static str| {
+            params.get(name).map(String::as_str).ok_or(AuthError::MissingParam(name))
+        };
+        let username = param("username")?;
+        let nonce = param("nonce")?;
+        let uri = param("uri")?;
+        let client_response = param("response")?;
+
+        if param("realm")? != self.realm {
+            return Err(AuthError::RealmMismatch);
+        }
+        let algorithm = match params.get("algorithm") {
+            Some(algorithm) => algorithm.parse().map_err(|_| AuthError::UnsupportedAlgorithm)?,
+            None => DigestAlgorithm::Md5,
+        };
+        if algorithm != self.algorithm {
+            return Err(AuthError::UnsupportedAlgorithm);
+        }
+        if param("opaque")? != self.opaque {
+            return Err(AuthError::OpaqueMismatch);
+        }
+        if !uri_matches(uri, url) {
+            return Err(AuthError::UriMismatch);
+        }
+
+        let qop = match params.get("qop").map(String::as_str) {
+            Some("auth") => {
+                let nc = param("nc")?;
+                let cnonce = param("cnonce")?;
+                Some((nc, cnonce, "auth"))
+            }
+            Some(_) => return Err(AuthError::UnsupportedQop),
+            None if self.require_qop => return Err(AuthError::MissingParam("qop")),
+            None => None,
+        };
+
+        let issued = self.verify_nonce(nonce).ok_or(AuthError::InvalidNonce)?;
+        let ha1 = provider.ha1(username, &self.realm, algorithm).ok_or(AuthError::UnknownUser)?;
+        let expected = digest_response(algorithm, &ha1, nonce, method, uri, qop);
+        let client_response = client_response.to_ascii_lowercase();
+        if !constant_time_eq(expected.to_ascii_lowercase().as_bytes(), client_response.as_bytes()) {
+            return Err(AuthError::InvalidResponse);
+        }
+
+        if now.saturating_sub(issued) > self.nonce_lifetime || issued > now {
+            return Err(AuthError::StaleNonce);
+        }
+
+        if let Some((nc, _, _)) = qop {
+            let nc = u32::from_str_radix(nc, 16).map_err(|_| AuthError::MissingParam("nc"))?;
+            let mut counts = self.nonce_counts.lock().unwrap();
+            counts.retain(|_, (issued, _)| now.saturating_sub(*issued) <= self.nonce_lifetime);
+            let last = counts.entry(nonce.to_string()).or_insert((issued, 0));
+            if nc <= last.1 {
+                return Err(AuthError::NonceCountReplay);
+            }
+            last.1 = nc;
+        }
+
+        Ok(username.to_string())
+    }
+}
+
+/// 摘要中的 uri 可以是完整 url, 也可以只有路径部分.
+fn uri_matches(uri: &str, url: &str) -> bool {
+    uri == url || url_path(uri) == url_path(url)
+}
+
+pub(crate) fn now_secs() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
 }
 
 pub fn generate<T: AuthHeaderGenerator>(auth: &mut T) -> String {
@@ -175,8 +498,148 @@ mod tests {
         let auth_params = parse_digest_header(auth_header).unwrap();
         println!("auth_params: {:#?}", auth_params);
         assert_eq!(auth_params.get("username").unwrap(), "admin");
-        assert_eq!(auth_params.get("nonce").unwrap(), "abc123");
-        assert_eq!(auth_params.get("uri").unwrap(), "rtsp://example.com/stream");
-        assert_eq!(auth_params.get("response").unwrap(), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(auth_params.get("realm").unwrap(), "rust rtsp-server");
+        assert_eq!(auth_params.get("nonce").unwrap(), "c4119e8b076a09c3");
+        assert_eq!(auth_params.get("uri").unwrap(), "rtsp://192.168.3.27:5544/track1");
+        assert_eq!(auth_params.get("response").unwrap(), "73dbd1c2c166a45b27f04345770cc3f7");
+        assert_eq!(auth_params.get("algorithm").unwrap(), "MD5");
+
+        // 引号内的逗号和不带引号的参数
+        let auth_header = "Digest username=\"admin\", uri=\"rtsp://example.com/stream?a=1,b=2\", qop=auth, nc=00000001";
+        let auth_params = parse_digest_header(auth_header).unwrap();
+        assert_eq!(auth_params.get("uri").unwrap(), "rtsp://example.com/stream?a=1,b=2");
+        assert_eq!(auth_params.get("qop").unwrap(), "auth");
+        assert_eq!(auth_params.get("nc").unwrap(), "00000001");
+    }
+
+    #[test]
+    fn test_htdigest_provider() {
+        // htdigest -c users.htdigest "rust rtsp-server" admin, 密码 123456
+        let content = "# comment\nadmin:rust rtsp-server:6918a7dc3bf06722f63cd4213a964c86\n\n";
+        let provider = HtdigestAuthProvider::parse(content).unwrap();
+        assert_eq!(
+            provider.ha1("admin", "rust rtsp-server", DigestAlgorithm::Md5).as_deref(),
+            Some("6918a7dc3bf06722f63cd4213a964c86")
+        );
+        assert_eq!(provider.ha1("admin", "rust rtsp-server", DigestAlgorithm::Sha256), None);
+        assert_eq!(provider.ha1("admin", "other realm", DigestAlgorithm::Md5), None);
+        assert!(HtdigestAuthProvider::parse("admin:123456").is_err());
+    }
+
+    #[test]
+    fn test_validate_basic_with_provider() {
+        let realm = "rust rtsp-server";
+        let mut users = HashMap::new();
+        users.insert("admin".to_string(), "123456".to_string());
+        let memory = MemoryAuthProvider::new(users);
+        let htdigest = HtdigestAuthProvider::parse(&format!(
+            "admin:{}:{}",
+            realm,
+            compute_ha1(DigestAlgorithm::Sha256, "admin", realm, "123456")
+        )).unwrap();
+
+        for provider in [&memory as &dyn AuthProvider, &htdigest] {
+            assert!(validate_basic_response("admin", "123456", realm, provider));
+            assert!(!validate_basic_response("admin", "654321", realm, provider));
+            assert!(!validate_basic_response("guest", "123456", realm, provider));
+        }
+    }
+
+    fn client_params(
+        auth: &DigestAuthenticator,
+        challenge: &str,
+        method: &str,
+        uri: &str,
+        nc: Option<&str>,
+    ) -> HashMap<String, String> {
+        let challenge = parse_digest_header(challenge).unwrap();
+        let nonce = challenge.get("nonce").unwrap();
+        let ha1 = compute_ha1(auth.algorithm, "admin", auth.realm(), "123456");
+        let qop = nc.map(|nc| (nc, "0a4f113b", "auth"));
+        let response = digest_response(auth.algorithm, &ha1, nonce, method, uri, qop);
+
+        let mut params = HashMap::new();
+        params.insert("username".to_string(), "admin".to_string());
+        params.insert("realm".to_string(), auth.realm().to_string());
+        params.insert("nonce".to_string(), nonce.to_string());
+        params.insert("uri".to_string(), uri.to_string());
+        params.insert("algorithm".to_string(), auth.algorithm.name().to_string());
+        params.insert("opaque".to_string(), challenge.get("opaque").unwrap().to_string());
+        params.insert("response".to_string(), response);
+        if let Some((nc, cnonce, qop)) = qop {
+            params.insert("nc".to_string(), nc.to_string());
+            params.insert("cnonce".to_string(), cnonce.to_string());
+            params.insert("qop".to_string(), qop.to_string());
+        }
+        params
     }
-}
\ No newline at end of file
+
+    fn provider() -> MemoryAuthProvider {
+        let mut users = HashMap::new();
+        users.insert("admin".to_string(), "123456".to_string());
+        MemoryAuthProvider::new(users)
+    }
+
+    #[test]
+    fn test_digest_qop_and_replay() {
+        let provider = provider();
+        let url = "rtsp://127.0.0.1:5544/live";
+        for algorithm in [DigestAlgorithm::Md5, DigestAlgorithm::Sha256] {
+            let auth = DigestAuthenticator::new("rust rtsp-server", algorithm, 60, true);
+            let challenge = auth.challenge_at(1000, false);
+            assert!(challenge.contains(&format!("algorithm={}", algorithm.name())));
+            assert!(!challenge.contains("stale"));
+
+            let params = client_params(&auth, &challenge, "DESCRIBE", url, Some("00000001"));
+            assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1010), Ok("admin".to_string()));
+            // 相同 nc 重放
+            assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1011), Err(AuthError::NonceCountReplay));
+            let params = client_params(&auth, &challenge, "SETUP", "/live/track1", Some("00000002"));
+            assert_eq!(auth.validate_at(&params, "SETUP", "rtsp://127.0.0.1:5544/live/track1", &provider, 1012), Ok("admin".to_string()));
+            // 十六进制不区分大小写
+            let mut params = client_params(&auth, &challenge, "DESCRIBE", url, Some("00000004"));
+            let upper = params["response"].to_ascii_uppercase();
+            params.insert("response".to_string(), upper);
+            assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1012), Ok("admin".to_string()));
+
+            // 方法或 uri 不匹配
+            let params = client_params(&auth, &challenge, "DESCRIBE", url, Some("00000003"));
+            assert_eq!(auth.validate_at(&params, "PLAY", url, &provider, 1013), Err(AuthError::InvalidResponse));
+            assert_eq!(auth.validate_at(&params, "DESCRIBE", "rtsp://127.0.0.1:5544/other", &provider, 1013), Err(AuthError::UriMismatch));
+
+            // 要求 qop 时拒绝 RFC 2069 风格的响应
+            let params = client_params(&auth, &challenge, "DESCRIBE", url, None);
+            assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1013), Err(AuthError::MissingParam("qop")));
+        }
+    }
+
+    #[test]
+    fn test_digest_nonce_expiry_and_forgery() {
+        let provider = provider();
+        let url = "rtsp://127.0.0.1:5544/live";
+        let auth = DigestAuthenticator::new("rust rtsp-server", DigestAlgorithm::Md5, 60, false);
+        let challenge = auth.challenge_at(1000, false);
+
+        // 兼容不带 qop 的客户端
+        let params = client_params(&auth, &challenge, "DESCRIBE", url, None);
+        assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1030), Ok("admin".to_string()));
+        // 过期 nonce 需要 stale=true 重新质询
+        assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1061), Err(AuthError::StaleNonce));
+        assert!(auth.challenge_at(1061, true).ends_with(", stale=true"));
+
+        // 其他服务器签发的 nonce
+        let other = DigestAuthenticator::new("rust rtsp-server", DigestAlgorithm::Md5, 60, false);
+        let mut params = client_params(&auth, &other.challenge_at(1000, false), "DESCRIBE", url, None);
+        params.insert("opaque".to_string(), auth.opaque.clone());
+        assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1030), Err(AuthError::InvalidNonce));
+
+        let mut params = client_params(&auth, &challenge, "DESCRIBE", url, None);
+        params.insert("opaque".to_string(), "forged".to_string());
+        assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1030), Err(AuthError::OpaqueMismatch));
+        // 缺少 opaque 同样拒绝
+        params.remove("opaque");
+        assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1030), Err(AuthError::MissingParam("opaque")));
+        params.remove("response");
+        assert_eq!(auth.validate_at(&params, "DESCRIBE", url, &provider, 1030), Err(AuthError::MissingParam("response")));
+    }
+}
@@ -0,0 +1,345 @@
+use std::{collections::HashMap, io, path::{Path, PathBuf}};
+use serde::Deserialize;
+
+use crate::{acl::{Cidr, IpFilter}, auth::DigestAlgorithm};
+
+/// 服务器配置, 从 toml 文件加载.
+///
+/// ```toml
+/// [server]
+/// listen = "0.0.0.0:5544"
+/// video_file = "test.h265"
+/// allow = ["10.0.0.0/8", "fd00::/8"]
+/// deny = ["10.0.66.0/24"]
+/// retransmit = "rtx"
+/// fec = 8
+/// extensions = ["abs-send-time", "frame-marking"]
+/// late_policy = "skip"
+/// # auth = "none"  # 不配置 [auth] 时必须显式关闭认证
+///
+/// [tls]
+/// listen = "0.0.0.0:322"
+/// cert = "cert.pem"
+/// key = "key.pem"
+///
+/// [auth]
+/// realm = "rust rtsp-server"
+/// provider = "htdigest"
+/// file = "users.htdigest"
+/// algorithm = "SHA-256"
+///
+/// [auth.token]
+/// secret = "change me"
+///
+/// [auth.groups]
+/// viewers = ["alice", "bob"]
+///
+/// [[mounts]]
+/// path = "/live"
+/// read = ["@viewers"]
+/// publish = ["camera"]
+/// publish_allow = ["10.0.20.0/24"]
+/// packetization_mode = 0
+///
+/// [mounts.sps]
+/// level_idc = 41
+/// frame_rate = 30
+/// max_num_reorder_frames = 0
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub tls: Option<TlsConfig>,
+    pub auth: Option<AuthConfig>, // 为空时不做认证
+    pub mounts: Vec<MountConfig>, // 为空时不限制路径
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub listen: Option<String>,     // 为空时使用本机 ip:5544
+    pub video_file: Option<String>,
+    #[serde(flatten)]
+    pub ip: IpFilter,               // 建立连接时检查来源地址
+    pub retransmit: Retransmit,     // udp 客户端的 NACK 重传方式
+    pub fec: Option<u8>,            // udp 客户端的 ULPFEC, 每组 rtp 包数 (1~16)
+    pub extensions: Vec<RtpExtension>, // rtp 头扩展, 按顺序分配 extmap id
+    pub late_policy: LatePolicy,    // 发送落后于视频时间戳时的处理方式
+    pub auth: Option<AuthMode>,     // 没有 [auth] 时必须显式设为 "none", 否则拒绝启动
+}
+
+/// 不配置 [auth] 时的认证方式, 只能显式关闭认证.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    None,
+}
+
+/// 发送落后时: 连续发送追上原计划, 或者跳过落后的时间从当前时间重新计划.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LatePolicy {
+    #[default]
+    CatchUp,
+    Skip,
+}
+
+/// 可选的 rtp 头扩展 (RFC 8285).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RtpExtension {
+    AbsSendTime,
+    AbsCaptureTime,
+    FrameMarking,
+}
+
+/// 收到 RTCP NACK 后的重传方式.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Retransmit {
+    #[default]
+    Off,
+    InStream, // 原 ssrc 原序号重发
+    Rtx,      // RFC 4588, 单独的负载类型和 ssrc, 在 SDP 中声明
+}
+
+/// rtsps:// 监听配置, 证书和私钥均为 PEM 格式.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+    pub listen: Option<String>, // 为空时使用本机 ip:322
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    #[serde(flatten)]
+    pub ip: IpFilter,
+}
+
+/// 认证配置, 凭据来自内存中的用户表或 htdigest 文件.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default = "default_realm")]
+    pub realm: String,
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub file: Option<PathBuf>,  // htdigest 文件路径
+    #[serde(default)]
+    pub users: HashMap<String, String>, // memory: 用户名 -> 密码
+    #[serde(default)]
+    pub algorithm: DigestAlgorithm, // "MD5" 或 "SHA-256"
+    #[serde(default = "default_nonce_lifetime")]
+    pub nonce_lifetime: u64,        // 秒, 过期后带 stale=true 重新质询
+    #[serde(default)]
+    pub require_qop: bool,          // 拒绝不带 qop=auth 的旧客户端; 为 false 时旧客户端在 nonce 有效期内没有重放保护
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>, // 组名 -> 用户列表, 在挂载点规则中以 @组名 引用
+    #[serde(default)]
+    pub lockout: LockoutConfig,
+    pub token: Option<TokenConfig>, // 签名 url, 为空时不接受 ?token=
+}
+
+/// 临时观看链接的签名密钥, 见 [`crate::token::UrlSigner`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenConfig {
+    pub secret: String,
+}
+
+/// 认证失败锁定策略, 时间单位为秒.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LockoutConfig {
+    pub max_failures: u32, // 连续失败多少次后开始锁定
+    pub lockout: u64,      // 首次锁定时长, 之后每次失败翻倍
+    pub max_lockout: u64,
+    pub reset_after: u64,  // 多久没有失败后清零计数
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            lockout: 30,
+            max_lockout: 3600,
+            reset_after: 900,
+        }
+    }
+}
+
+/// 挂载点及其访问规则.
+/// `read` 为空时所有认证用户都可以观看, `publish` 为空时不允许推流 (ANNOUNCE/RECORD).
+#[derive(Debug, Clone, Deserialize)]
+pub struct MountConfig {
+    pub path: String,
+    #[serde(default)]
+    pub anonymous: bool, // 允许不认证观看
+    pub read: Option<Vec<String>>,
+    pub publish: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub ip: IpFilter,            // 观看和推流都要满足
+    #[serde(default)]
+    pub publish_allow: Vec<Cidr>, // 推流额外限制来源网段, 为空时不限制
+    pub packetization_mode: Option<u8>, // H.264 的 packetization-mode (0/1/2), 为空时为 1
+    #[serde(default)]
+    pub sps: Option<SpsConfig>,  // 改写 H.264 SPS, 为空时原样发送
+}
+
+/// 挂载点的 SPS 改写项, 未设置的保持原值.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SpsConfig {
+    pub level_idc: Option<u8>,
+    pub frame_rate: Option<u32>,             // 写入 VUI timing_info
+    pub max_num_reorder_frames: Option<u32>, // 0 表示没有 B 帧重排序, 播放器可以不缓存
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    Memory,
+    Htdigest,
+}
+
+pub const DEFAULT_REALM: &str = "rust rtsp-server";
+
+fn default_realm() -> String {
+    DEFAULT_REALM.to_string()
+}
+
+fn default_nonce_lifetime() -> u64 {
+    300
+}
+
+pub const DEFAULT_RTSP_PORT: u16 = 5544;
+pub const DEFAULT_RTSPS_PORT: u16 = 322;
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        content.parse()
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = r#"
+            [server]
+            listen = "127.0.0.1:5544"
+            allow = ["10.0.0.0/8", "fd00::/8"]
+            deny = ["10.0.66.1"]
+            retransmit = "in-stream"
+            fec = 4
+            extensions = ["abs-capture-time", "frame-marking"]
+            late_policy = "skip"
+
+            [tls]
+            cert = "cert.pem"
+            key = "key.pem"
+        "#.parse().unwrap();
+        assert_eq!(config.server.listen.as_deref(), Some("127.0.0.1:5544"));
+        assert_eq!(config.server.video_file, None);
+        assert_eq!(config.server.retransmit, Retransmit::InStream);
+        assert_eq!(config.server.fec, Some(4));
+        assert_eq!(config.server.extensions, vec![RtpExtension::AbsCaptureTime, RtpExtension::FrameMarking]);
+        assert_eq!(config.server.late_policy, LatePolicy::Skip);
+        assert!(config.server.ip.permits("10.1.2.3".parse().unwrap()));
+        assert!(!config.server.ip.permits("10.0.66.1".parse().unwrap()));
+        assert!(!config.server.ip.permits("192.168.1.1".parse().unwrap()));
+        let tls = config.tls.unwrap();
+        assert_eq!(tls.listen, None);
+        assert_eq!(tls.cert, PathBuf::from("cert.pem"));
+
+        assert!(tls.ip.allow.is_empty());
+        assert!(r#"
+            [server]
+            allow = ["10.0.0.0/40"]
+        "#.parse::<Config>().is_err());
+
+        let config: Config = "".parse().unwrap();
+        assert_eq!(config.server.late_policy, LatePolicy::CatchUp);
+        assert_eq!(config.server.auth, None);
+        assert!(config.tls.is_none());
+        assert!(config.auth.is_none());
+        assert!(config.mounts.is_empty());
+    }
+
+    #[test]
+    fn test_parse_auth_config() {
+        let config: Config = r#"
+            [auth]
+            [auth.users]
+            admin = "123456"
+        "#.parse().unwrap();
+        let auth = config.auth.unwrap();
+        assert_eq!(auth.realm, DEFAULT_REALM);
+        assert_eq!(auth.provider, ProviderKind::Memory);
+        assert_eq!(auth.users.get("admin").map(String::as_str), Some("123456"));
+        assert_eq!(auth.algorithm, DigestAlgorithm::Md5);
+        assert_eq!(auth.nonce_lifetime, 300);
+        assert!(!auth.require_qop);
+        assert_eq!(auth.lockout.max_failures, 5);
+        assert!(auth.token.is_none());
+
+        let config: Config = r#"
+            [auth]
+            realm = "cameras"
+            provider = "htdigest"
+            file = "users.htdigest"
+            algorithm = "SHA-256"
+
+            [auth.lockout]
+            max_failures = 3
+
+            [auth.token]
+            secret = "s3cret"
+        "#.parse().unwrap();
+        let auth = config.auth.unwrap();
+        assert_eq!(auth.token.unwrap().secret, "s3cret");
+        assert_eq!(auth.provider, ProviderKind::Htdigest);
+        assert_eq!(auth.algorithm, DigestAlgorithm::Sha256);
+        assert_eq!(auth.file, Some(PathBuf::from("users.htdigest")));
+    }
+
+    #[test]
+    fn test_parse_mounts() {
+        let config: Config = r#"
+            [[mounts]]
+            path = "/cam1"
+            read = ["@viewers"]
+            publish = ["camera"]
+            allow = ["10.0.0.0/8"]
+            publish_allow = ["10.0.20.0/24"]
+            packetization_mode = 2
+
+            [mounts.sps]
+            level_idc = 41
+            max_num_reorder_frames = 0
+
+            [[mounts]]
+            path = "/public"
+            anonymous = true
+        "#.parse().unwrap();
+        let cam = &config.mounts[0];
+        assert_eq!(cam.read.as_deref(), Some(&["@viewers".to_string()][..]));
+        assert!(cam.ip.permits("10.0.30.1".parse().unwrap()));
+        assert_eq!(cam.publish_allow, vec!["10.0.20.0/24".parse().unwrap()]);
+        assert_eq!(cam.packetization_mode, Some(2));
+        assert_eq!(cam.sps, Some(SpsConfig { level_idc: Some(41), frame_rate: None, max_num_reorder_frames: Some(0) }));
+        let public = &config.mounts[1];
+        assert!(public.anonymous);
+        assert!(public.ip.allow.is_empty() && public.publish_allow.is_empty());
+        assert_eq!(public.packetization_mode, None);
+        assert_eq!(public.sps, None);
+    }
+}
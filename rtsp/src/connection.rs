@@ -1,24 +1,103 @@
-use std::{net::TcpStream, sync::{Arc, Mutex}};
+use std::{io::{self, BufRead, Read, Write}, net::{SocketAddr, TcpStream}, sync::{Arc, Mutex}};
 
 use media::session::Session;
 
+/// 控制连接的底层传输, 明文 TCP 或 TLS.
+/// `try_clone` 得到的句柄与原连接共享同一条链路, 用于 rtp 线程发送 interleaved 数据.
+pub trait Stream: Read + Write + Send {
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>>;
+}
+
+impl Stream for TcpStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+}
+
+/// 控制连接上收到的一条消息.
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Request(String),
+    /// '$' 开头的 interleaved 数据 (RFC 2326 10.12), 一般是客户端的 RTCP
+    Interleaved { channel: u8, data: Vec<u8> },
+}
+
+/// 从控制连接读取下一条消息, 对端关闭连接时返回 `Ok(None)`.
+/// `reader` 要在整个连接上复用, 否则缓冲区中已读入的数据会丢失.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Message>> {
+    loop {
+        let buf = reader.fill_buf()?;
+        match buf.first() {
+            None => return Ok(None),
+            Some(b'$') => {
+                let mut header = [0u8; 4];
+                reader.read_exact(&mut header)?;
+                let mut data = vec![0u8; u16::from_be_bytes([header[2], header[3]]) as usize];
+                reader.read_exact(&mut data)?;
+                return Ok(Some(Message::Interleaved { channel: header[1], data }));
+            }
+            // 请求之间多余的空行
+            Some(b'\r' | b'\n') => reader.consume(1),
+            Some(_) => break,
+        }
+    }
+
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line.to_string());
+    }
+
+    let content_length = lines
+        .iter()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > 0 {
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body)?;
+        lines.push(String::from_utf8_lossy(&body).into_owned());
+    }
+    Ok(Some(Message::Request(lines.join("\r\n"))))
+}
+
 pub struct Connection<'a> {
-    pub stream: TcpStream, // TODO: use async tcp stream
+    pub stream: Box<dyn Stream>, // TODO: use async tcp stream
     pub session: Arc<Mutex<Session<'a>>>,
+    pub token_path: Option<String>, // 签名 url 校验通过的路径, 之后同一连接上不带 token 的请求沿用
 }
 
 impl<'a> Connection<'a> {
-    pub fn new(stream: TcpStream, session: Arc<Mutex<Session<'a>>>) -> Self {
+    pub fn new(stream: Box<dyn Stream>, session: Arc<Mutex<Session<'a>>>) -> Self {
         Connection {
             stream,
             session,
+            token_path: None,
         }
     }
 
-    pub fn get_stream(&self) -> &TcpStream {
-        &self.stream
+    pub fn get_stream(&mut self) -> &mut dyn Stream {
+        self.stream.as_mut()
     }
-    
+
     pub fn get_local_ip(&self) -> String {
         self.stream.local_addr().unwrap().ip().to_string()
     }
@@ -38,4 +117,31 @@ impl<'a> Connection<'a> {
     pub fn get_session(&self) -> Arc<Mutex<Session<'a>>> {
         Arc::clone(&self.session)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_message() {
+        let mut data = b"OPTIONS rtsp://127.0.0.1/live RTSP/1.0\r\nCSeq: 1\r\n\r\n".to_vec();
+        data.extend([0x24, 1, 0, 4, 0x81, 201, 0, 0]);
+        data.extend(b"\r\nANNOUNCE rtsp://127.0.0.1/live RTSP/1.0\r\nCSeq: 2\r\nContent-Length: 5\r\n\r\nv=0\r\n");
+        let mut reader = io::BufReader::new(data.as_slice());
+
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(Message::Request("OPTIONS rtsp://127.0.0.1/live RTSP/1.0\r\nCSeq: 1".to_string()))
+        );
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(Message::Interleaved { channel: 1, data: vec![0x81, 201, 0, 0] })
+        );
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(Message::Request("ANNOUNCE rtsp://127.0.0.1/live RTSP/1.0\r\nCSeq: 2\r\nContent-Length: 5\r\nv=0\r\n".to_string()))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+}
@@ -1,8 +1,8 @@
-use std::sync::{Arc, Mutex};
+use std::{net::IpAddr, sync::{Arc, Mutex}};
 
 use crate::{request::RtspRequest, response::RtspResponse};
 use linked_hash_map::LinkedHashMap;
-use media::{sdp::SDP, session::Session};
+use media::{rtp::transport::Transport, sdp::SDP, session::{Session, Track}};
 
 const USER_AGENT: &str = "rust rtsp-server";
 
@@ -23,11 +23,12 @@ impl DescribeHandler {
 
 pub struct SetupHandler<'a> {
     session: Arc<Mutex<Session<'a>>>,
+    local_ip: IpAddr, // udp 传输时在该地址上分配服务端端口
 }
 
 impl<'a> SetupHandler<'a> {
-    pub fn new(session: Arc<Mutex<Session<'a>>>) -> Self {
-        Self { session }
+    pub fn new(session: Arc<Mutex<Session<'a>>>, local_ip: IpAddr) -> Self {
+        Self { session, local_ip }
     }
 }
 pub struct PlayHandler<'a> {
@@ -80,7 +81,7 @@ impl Handler for DescribeHandler {
 
 impl<'a> Handler for SetupHandler<'a> {
     fn handle(&mut self, req: &RtspRequest) -> RtspResponse {
-        let session = self.session.lock().unwrap();
+        let mut session = self.session.lock().unwrap();
         let mut session_str = session.session_id.as_str().to_string();
         session_str += ";timeout=60";
 
@@ -95,9 +96,26 @@ impl<'a> Handler for SetupHandler<'a> {
             headers.insert("Session", session_str.to_string());
         }
 
-        let mut transport = req.headers.get("Transport").unwrap().to_string();
-        transport += ";ssrc=12345678";
-        headers.insert("Transport", transport);
+        let Some(transport) = req.headers.get("Transport").and_then(|t| Transport::parse(t)) else {
+            log::warn!("unsupported transport: {:?}", req.headers.get("Transport"));
+            return RtspResponse::new("461", Some(headers), None);
+        };
+        let transport_str = match session.setup(Track::Video, transport, self.local_ip) {
+            Ok(Some((server_rtp_port, server_rtcp_port))) => {
+                let Transport::Udp { client_rtp_port, client_rtcp_port } = transport else { unreachable!() };
+                format!("RTP/AVP;unicast;client_port={}-{};server_port={}-{}",
+                    client_rtp_port, client_rtcp_port, server_rtp_port, server_rtcp_port)
+            }
+            Ok(None) => {
+                let Transport::Interleaved { rtp_channel, rtcp_channel } = transport else { unreachable!() };
+                format!("RTP/AVP/TCP;unicast;interleaved={}-{}", rtp_channel, rtcp_channel)
+            }
+            Err(e) => {
+                log::error!("setup transport failed: {}", e);
+                return RtspResponse::new("500", Some(headers), None);
+            }
+        };
+        headers.insert("Transport", format!("{};ssrc={:08X}", transport_str, session.ssrc()));
 
         //DATA 
         RtspResponse::new("200", Some(headers), None)
@@ -3,4 +3,11 @@ pub mod response;
 pub mod handler;
 pub mod router;
 pub mod connection;
-pub mod auth;
\ No newline at end of file
+pub mod auth;
+pub mod tls;
+pub mod config;
+pub mod mount;
+pub mod lockout;
+pub mod token;
+pub mod acl;
+pub mod stats;
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::{collections::HashMap, hash::Hash, net::IpAddr, sync::Mutex, time::{Duration, Instant}};
+
+use crate::config::LockoutConfig;
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// 按来源 ip 和用户名分别统计连续认证失败次数.
+/// 超过 `max_failures` 后锁定, 每多失败一次锁定时间翻倍, 最长 `max_lockout` 秒;
+/// 认证成功或 `reset_after` 秒内没有再失败时清零.
+pub struct LoginGuard {
+    config: LockoutConfig,
+    by_ip: Mutex<HashMap<IpAddr, Failures>>,
+    by_user: Mutex<HashMap<String, Failures>>,
+}
+
+impl LoginGuard {
+    pub fn new(config: LockoutConfig) -> Self {
+        Self {
+            config,
+            by_ip: Mutex::new(HashMap::new()),
+            by_user: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 处于锁定期时返回剩余时间.
+    pub fn locked(&self, ip: IpAddr, username: Option<&str>) -> Option<Duration> {
+        self.locked_at(ip, username, Instant::now())
+    }
+
+    pub fn record_failure(&self, ip: IpAddr, username: Option<&str>) {
+        self.record_failure_at(ip, username, Instant::now())
+    }
+
+    pub fn record_success(&self, ip: IpAddr, username: &str) {
+        self.by_ip.lock().unwrap().remove(&ip);
+        self.by_user.lock().unwrap().remove(username);
+    }
+
+    fn locked_at(&self, ip: IpAddr, username: Option<&str>, now: Instant) -> Option<Duration> {
+        let remaining = |until: Option<Instant>| until.and_then(|until| until.checked_duration_since(now));
+        let by_ip = remaining(self.by_ip.lock().unwrap().get(&ip).and_then(|f| f.locked_until));
+        let by_user = username.and_then(|username| {
+            remaining(self.by_user.lock().unwrap().get(username).and_then(|f| f.locked_until))
+        });
+        by_ip.max(by_user).filter(|d| !d.is_zero())
+    }
+
+    fn record_failure_at(&self, ip: IpAddr, username: Option<&str>, now: Instant) {
+        Self::bump(&self.config, &mut self.by_ip.lock().unwrap(), ip, now);
+        if let Some(username) = username {
+            Self::bump(&self.config, &mut self.by_user.lock().unwrap(), username.to_string(), now);
+        }
+    }
+
+    fn bump<K: Hash + Eq>(config: &LockoutConfig, table: &mut HashMap<K, Failures>, key: K, now: Instant) {
+        let reset_after = Duration::from_secs(config.reset_after);
+        table.retain(|_, f| {
+            now.duration_since(f.last_failure) < reset_after || f.locked_until.is_some_and(|until| until > now)
+        });
+
+        let failures = table.entry(key).or_insert(Failures {
+            count: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        failures.count += 1;
+        failures.last_failure = now;
+        if failures.count >= config.max_failures {
+            let exponent = (failures.count - config.max_failures).min(31);
+            let secs = config.lockout.saturating_mul(1 << exponent).min(config.max_lockout);
+            failures.locked_until = Some(now + Duration::from_secs(secs));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential_lockout() {
+        let guard = LoginGuard::new(LockoutConfig {
+            max_failures: 3,
+            lockout: 10,
+            max_lockout: 30,
+            reset_after: 600,
+        });
+        let ip: IpAddr = "192.168.1.10".parse().unwrap();
+        let other_ip: IpAddr = "192.168.1.11".parse().unwrap();
+        let t0 = Instant::now();
+
+        guard.record_failure_at(ip, Some("admin"), t0);
+        guard.record_failure_at(ip, Some("admin"), t0);
+        assert_eq!(guard.locked_at(ip, Some("admin"), t0), None);
+        guard.record_failure_at(ip, Some("admin"), t0);
+        assert_eq!(guard.locked_at(ip, None, t0), Some(Duration::from_secs(10)));
+        // 换 ip 也会因为用户名被锁
+        assert_eq!(guard.locked_at(other_ip, Some("admin"), t0), Some(Duration::from_secs(10)));
+        assert_eq!(guard.locked_at(other_ip, Some("guest"), t0), None);
+
+        let t1 = t0 + Duration::from_secs(11);
+        assert_eq!(guard.locked_at(ip, Some("admin"), t1), None);
+        guard.record_failure_at(ip, Some("admin"), t1);
+        assert_eq!(guard.locked_at(ip, Some("admin"), t1), Some(Duration::from_secs(20)));
+        guard.record_failure_at(ip, Some("admin"), t1);
+        assert_eq!(guard.locked_at(ip, Some("admin"), t1), Some(Duration::from_secs(30)));
+
+        guard.record_success(ip, "admin");
+        assert_eq!(guard.locked_at(ip, Some("admin"), t1), None);
+    }
+
+    #[test]
+    fn test_failures_expire() {
+        let guard = LoginGuard::new(LockoutConfig {
+            max_failures: 2,
+            lockout: 10,
+            max_lockout: 30,
+            reset_after: 60,
+        });
+        let ip: IpAddr = "::1".parse().unwrap();
+        let t0 = Instant::now();
+        guard.record_failure_at(ip, None, t0);
+        guard.record_failure_at(ip, None, t0 + Duration::from_secs(61));
+        assert_eq!(guard.locked_at(ip, None, t0 + Duration::from_secs(61)), None);
+    }
+}
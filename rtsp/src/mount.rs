@@ -0,0 +1,181 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use crate::{config::MountConfig, request::{Method, RtspRequest}};
+
+/// 请求需要的权限.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Publish,
+}
+
+impl Access {
+    /// ANNOUNCE/RECORD 以及 mode=record 的 SETUP 需要推流权限, 其余为观看.
+    pub fn of(req: &RtspRequest) -> Self {
+        match req.method {
+            Method::Announce | Method::Record => Access::Publish,
+            Method::Setup => {
+                let record = req.headers.get("Transport").is_some_and(|transport| {
+                    transport
+                        .split(';')
+                        .any(|param| param.trim().replace('"', "").eq_ignore_ascii_case("mode=record"))
+                });
+                if record { Access::Publish } else { Access::Read }
+            }
+            _ => Access::Read,
+        }
+    }
+}
+
+/// 挂载点表, 按路径最长前缀匹配.
+#[derive(Debug, Default)]
+pub struct Mounts {
+    mounts: Vec<MountConfig>,
+    groups: HashMap<String, Vec<String>>,
+}
+
+impl Mounts {
+    pub fn new(mounts: Vec<MountConfig>, groups: HashMap<String, Vec<String>>) -> Self {
+        Self { mounts, groups }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mounts.is_empty()
+    }
+
+    /// "/live" 匹配 "/live" 和 "/live/track1", 不匹配 "/livestream".
+    pub fn find(&self, path: &str) -> Option<&MountConfig> {
+        self.mounts
+            .iter()
+            .filter(|mount| is_under(path, &mount.path))
+            .max_by_key(|mount| mount.path.trim_end_matches('/').len())
+    }
+
+    /// 来源地址是否允许以 `access` 访问该挂载点.
+    pub fn reachable_from(&self, mount: &MountConfig, ip: IpAddr, access: Access) -> bool {
+        if !mount.ip.permits(ip) {
+            return false;
+        }
+        access == Access::Read || mount.publish_allow.is_empty() || mount.publish_allow.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    pub fn allows_anonymous(&self, mount: &MountConfig, access: Access) -> bool {
+        mount.anonymous && access == Access::Read
+    }
+
+    /// 认证通过的用户是否有该挂载点的权限, 规则中 `@组名` 表示整个组.
+    pub fn authorize(&self, mount: &MountConfig, username: &str, access: Access) -> bool {
+        let rule = match access {
+            Access::Read => match &mount.read {
+                Some(rule) => rule,
+                None => return true,
+            },
+            Access::Publish => match &mount.publish {
+                Some(rule) => rule,
+                None => return false,
+            },
+        };
+        rule.iter().any(|entry| match entry.strip_prefix('@') {
+            Some(group) => self
+                .groups
+                .get(group)
+                .is_some_and(|members| members.iter().any(|member| member == username)),
+            None => entry == username,
+        })
+    }
+}
+
+/// `path` 是否为 `prefix` 本身或其下的子路径.
+pub fn is_under(path: &str, prefix: &str) -> bool {
+    match path.strip_prefix(prefix.trim_end_matches('/')) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::IpFilter;
+
+    fn mounts() -> Mounts {
+        let mount = |path: &str, anonymous, read: Option<&[&str]>, publish: Option<&[&str]>| MountConfig {
+            path: path.to_string(),
+            anonymous,
+            read: read.map(|r| r.iter().map(|s| s.to_string()).collect()),
+            publish: publish.map(|p| p.iter().map(|s| s.to_string()).collect()),
+            ip: IpFilter::default(),
+            publish_allow: vec![],
+            packetization_mode: None,
+            sps: None,
+        };
+        let mut groups = HashMap::new();
+        groups.insert("viewers".to_string(), vec!["alice".to_string(), "bob".to_string()]);
+        Mounts::new(
+            vec![
+                mount("/", false, None, None),
+                mount("/live", false, Some(&["@viewers", "carol"]), Some(&["camera"])),
+                mount("/public/", true, None, None),
+            ],
+            groups,
+        )
+    }
+
+    #[test]
+    fn test_find_mount() {
+        let mounts = mounts();
+        assert_eq!(mounts.find("/live").unwrap().path, "/live");
+        assert_eq!(mounts.find("/live/track1").unwrap().path, "/live");
+        assert_eq!(mounts.find("/livestream").unwrap().path, "/");
+        assert_eq!(mounts.find("/public").unwrap().path, "/public/");
+        assert!(Mounts::default().find("/live").is_none());
+    }
+
+    #[test]
+    fn test_authorize() {
+        let mounts = mounts();
+        let live = mounts.find("/live").unwrap();
+        assert!(mounts.authorize(live, "alice", Access::Read));
+        assert!(mounts.authorize(live, "carol", Access::Read));
+        assert!(!mounts.authorize(live, "dave", Access::Read));
+        assert!(!mounts.authorize(live, "alice", Access::Publish));
+        assert!(mounts.authorize(live, "camera", Access::Publish));
+        assert!(!mounts.allows_anonymous(live, Access::Read));
+
+        let root = mounts.find("/other").unwrap();
+        assert!(mounts.authorize(root, "dave", Access::Read));
+        assert!(!mounts.authorize(root, "dave", Access::Publish));
+
+        let public = mounts.find("/public").unwrap();
+        assert!(mounts.allows_anonymous(public, Access::Read));
+        assert!(!mounts.allows_anonymous(public, Access::Publish));
+    }
+
+    #[test]
+    fn test_access_of_request() {
+        let req: RtspRequest = String::from("SETUP rtsp://127.0.0.1/live/track1 RTSP/1.0\r\nCSeq: 3\r\nTransport: RTP/AVP/TCP;unicast;interleaved=0-1;mode=\"RECORD\"\r\n").into();
+        assert_eq!(Access::of(&req), Access::Publish);
+        let req: RtspRequest = String::from("SETUP rtsp://127.0.0.1/live/track1 RTSP/1.0\r\nCSeq: 3\r\nTransport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n").into();
+        assert_eq!(Access::of(&req), Access::Read);
+        let req: RtspRequest = String::from("RECORD rtsp://127.0.0.1/live RTSP/1.0\r\nCSeq: 5\r\n").into();
+        assert_eq!(Access::of(&req), Access::Publish);
+        let req: RtspRequest = String::from("PLAY rtsp://127.0.0.1/live RTSP/1.0\r\nCSeq: 5\r\n").into();
+        assert_eq!(Access::of(&req), Access::Read);
+    }
+
+    #[test]
+    fn test_reachable_from() {
+        let mut mounts = mounts();
+        mounts.mounts[1].ip.allow = vec!["10.0.0.0/8".parse().unwrap()];
+        mounts.mounts[1].publish_allow = vec!["10.0.20.0/24".parse().unwrap()];
+        let live = mounts.find("/live").unwrap();
+        let camera: IpAddr = "10.0.20.5".parse().unwrap();
+        let office: IpAddr = "10.0.30.5".parse().unwrap();
+        let outside: IpAddr = "192.168.1.5".parse().unwrap();
+        assert!(mounts.reachable_from(live, camera, Access::Publish));
+        assert!(mounts.reachable_from(live, office, Access::Read));
+        assert!(!mounts.reachable_from(live, office, Access::Publish));
+        assert!(!mounts.reachable_from(live, outside, Access::Read));
+        assert!(mounts.reachable_from(mounts.find("/public").unwrap(), outside, Access::Publish));
+    }
+}
@@ -8,6 +8,7 @@ pub enum Method {
     Setup,
     Play,
     Pause,
+    Record,
     Teardown,
     Uninitialized,
 }
@@ -21,6 +22,7 @@ impl From<&Method> for String {
             Method::Setup => "SETUP".to_string(),
             Method::Play => "PLAY".to_string(),
             Method::Pause => "PAUSE".to_string(),
+            Method::Record => "RECORD".to_string(),
             Method::Teardown => "TEARDOWN".to_string(),
             Method::Uninitialized => "Uninitialized".to_string(),
         }
@@ -36,6 +38,7 @@ impl From<&str> for Method {
             "SETUP" => Method::Setup,
             "PLAY" => Method::Play,
             "PAUSE" => Method::Pause,
+            "RECORD" => Method::Record,
             "TEARDOWN" => Method::Teardown,
             _ => Method::Uninitialized,
         }
@@ -104,6 +107,29 @@ impl From<String> for RtspRequest {
     }
 }
 
+impl RtspRequest {
+    /// 请求 url 的路径部分, 例如 rtsp://host:5544/live/track1 -> /live/track1
+    pub fn path(&self) -> &str {
+        let Url::Path(url) = &self.url;
+        url_path(url)
+    }
+
+    /// url 中 '?' 之后的查询串.
+    pub fn query(&self) -> Option<&str> {
+        let Url::Path(url) = &self.url;
+        url.split_once('?').map(|(_, query)| query)
+    }
+}
+
+/// 去掉 url 中的 scheme, host 和查询串, 只保留路径; 本身就是路径时原样返回.
+pub fn url_path(url: &str) -> &str {
+    let url = url.split_once('?').map_or(url, |(url, _)| url);
+    match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or("/"),
+        None => url,
+    }
+}
+
 fn process_req_line(s: &str) -> (Method, Url, Version) {
     let mut words = s.split_whitespace();
     let methods = words.next().unwrap();
@@ -158,5 +184,19 @@ mod tests {
         assert_eq!(Version::V1_0, req.version);
         assert_eq!(Url::Path("rtsp://10.15.112.58:5544".to_string()), req.url);
         assert_eq!(expected_header, req.headers);
+        assert_eq!(req.path(), "/");
+    }
+
+    #[test]
+    fn test_url_path() {
+        assert_eq!(url_path("rtsp://10.15.112.58:5544/live/track1"), "/live/track1");
+        assert_eq!(url_path("rtsps://example.com/live"), "/live");
+        assert_eq!(url_path("/live"), "/live");
+        assert_eq!(url_path("rtsp://example.com/live?token=ab&expires=10"), "/live");
+        assert_eq!(url_path("rtsp://example.com?token=ab"), "/");
+
+        let req: RtspRequest = String::from("DESCRIBE rtsp://127.0.0.1/live?token=ab&expires=10 RTSP/1.0\r\nCSeq: 2\r\n").into();
+        assert_eq!(req.path(), "/live");
+        assert_eq!(req.query(), Some("token=ab&expires=10"));
     }
 }
\ No newline at end of file
@@ -71,8 +71,13 @@ impl<'a> RtspResponse<'a> {
             "200" => "OK".into(),
             "400" => "Bad Request".into(),
             "401" => "Unauthorized".into(),
+            "403" => "Forbidden".into(),
             "404" => "Not Found".into(),
+            "415" => "Unsupported Media Type".into(),
+            "461" => "Unsupported Transport".into(),
             "500" => "Internal Server Error".into(),
+            "501" => "Not Implemented".into(),
+            "503" => "Service Unavailable".into(),
             _ => "Not Found".into(),
         };
         response.body = body;
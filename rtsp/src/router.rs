@@ -1,83 +1,319 @@
 use linked_hash_map::LinkedHashMap;
-use crate::{auth::{self, AuthHeaderGenerator, AuthType}, connection::Connection, handler::TeardownHandler, request::{self, RtspRequest}, response::RtspResponse};
+use std::{io, net::{IpAddr, Ipv4Addr}};
+use crate::{auth::{self, AuthError, AuthProvider, DigestAlgorithm, DigestAuthenticator}, config::{AuthMode, Config, DEFAULT_REALM}, connection::Connection, handler::TeardownHandler, lockout::LoginGuard, mount::{self, Access, Mounts}, request::{self, RtspRequest, Url}, response::RtspResponse, stats::StatsRegistry, token::{self, UrlSigner}};
+use media::{codec::h264_sps::SpsRewrite, rtp::rtp_h264::PacketizationMode};
 use crate::handler::{DescribeHandler, Handler, OptionsHandler, PlayHandler, SetupHandler};
-pub struct Router;
+/// 挂载点改写 SPS 时允许的最大帧率.
+const MAX_SPS_FRAME_RATE: u32 = 1000;
+
+/// 请求被拒绝的原因.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Denied {
+    NotFound,
+    Forbidden,
+    Unauthorized { stale: bool }, // nonce 过期时带 stale=true 重新质询
+    Locked { retry_after: u64 },  // 秒
+}
+
+pub struct Router {
+    auth_provider: Option<Box<dyn AuthProvider>>, // 为空时不做认证
+    digest: DigestAuthenticator,
+    mounts: Mounts,
+    login_guard: LoginGuard,
+    url_signer: Option<UrlSigner>,
+    stats: StatsRegistry,
+}
 
 impl Router {
-    fn get_handler<'a>(method: &request::Method, connect: &'a Connection) -> Box<dyn Handler + 'a> {
-        match method {
+    pub fn new(config: &Config) -> io::Result<Self> {
+        // 没有 [auth] 时拒绝启动, 除非显式关闭认证, 避免缺少配置的服务器对所有人开放
+        match (&config.auth, config.server.auth) {
+            (None, None) => {
+                let msg = "no [auth] configured, set auth = \"none\" in [server] to serve without authentication";
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+            (Some(_), Some(AuthMode::None)) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "[auth] conflicts with auth = \"none\""));
+            }
+            _ => {}
+        }
+        let (auth_provider, digest) = match &config.auth {
+            Some(auth) => (Some(auth::provider_from_config(auth)?), DigestAuthenticator::from_config(auth)),
+            None => (None, DigestAuthenticator::new(DEFAULT_REALM, DigestAlgorithm::Md5, 0, false)),
+        };
+        let groups = config.auth.as_ref().map(|auth| auth.groups.clone()).unwrap_or_default();
+        for mount in &config.mounts {
+            if let Some(Err(mode)) = mount.packetization_mode.map(PacketizationMode::try_from) {
+                let msg = format!("mount {}: invalid packetization_mode {}", mount.path, mode);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+            if let Some(frame_rate) = mount.sps.and_then(|sps| sps.frame_rate).filter(|fps| !(1..=MAX_SPS_FRAME_RATE).contains(fps)) {
+                let msg = format!("mount {}: sps frame_rate {} out of range 1~{}", mount.path, frame_rate, MAX_SPS_FRAME_RATE);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+        }
+        Ok(Router {
+            auth_provider,
+            digest,
+            mounts: Mounts::new(config.mounts.clone(), groups),
+            login_guard: LoginGuard::new(config.auth.as_ref().map(|auth| auth.lockout.clone()).unwrap_or_default()),
+            stats: StatsRegistry::default(),
+            url_signer: config.auth.as_ref().and_then(|auth| auth.token.as_ref()).map(|token| UrlSigner::new(&token.secret)),
+        })
+    }
+
+    /// 在线客户端的 QoS 统计.
+    pub fn stats(&self) -> &StatsRegistry {
+        &self.stats
+    }
+
+    /// 不支持的方法 (推流, 暂停, GET_PARAMETER 等) 返回 `None`.
+    fn get_handler<'a>(method: &request::Method, connect: &Connection<'a>) -> Option<Box<dyn Handler + 'a>> {
+        let session = connect.get_session();
+        let handler: Box<dyn Handler + 'a> = match method {
             request::Method::Options => Box::new(OptionsHandler {}),
             request::Method::Describe => {
-                let session = connect.get_session();
                 let sdp = session.lock().unwrap().generare_sdp();
                 Box::new(DescribeHandler::new(sdp))
             },
             request::Method::Setup => {
-                Box::new(SetupHandler::new(connect.get_session()))
+                let local_ip = connect.stream.local_addr().map(|addr| addr.ip()).unwrap_or(Ipv4Addr::UNSPECIFIED.into());
+                Box::new(SetupHandler::new(session, local_ip))
             },
             request::Method::Play => {
-                let session = connect.get_session(); 
                 Box::new(PlayHandler::new(session))
             },
             request::Method::Teardown => { 
-                let session = connect.get_session(); 
                 Box::new(TeardownHandler::new(session))
             },
-            request::Method::Announce => todo!(),
-            request::Method::Pause => todo!(),
-            request::Method::Uninitialized => todo!(),
-        }
+            request::Method::Announce
+            | request::Method::Pause
+            | request::Method::Record
+            | request::Method::Uninitialized => return None,
+        };
+        Some(handler)
     }
 
-    fn unauthorized(req: &RtspRequest, mut auth: impl AuthHeaderGenerator) -> RtspResponse {
+    fn unauthorized(req: &RtspRequest, www_authenticate: String) -> RtspResponse<'static> {
         let mut headers: LinkedHashMap<&str, String> = LinkedHashMap::new();
-        let seq = req.headers.get("CSeq").unwrap();
-        headers.insert("CSeq", seq.to_string());
-        headers.insert("WWW-Authenticate", auth.generate());
+        headers.insert("CSeq", Router::cseq(req));
+        headers.insert("WWW-Authenticate", www_authenticate);
         RtspResponse::new("401", Some(headers), None)
     }
 
-    pub fn route(req: RtspRequest, connect: &Connection ) -> () {
-        let mut handler = Router::get_handler(&req.method, connect);
-        let username = "admin";
-        let password = "123456";
-
-        if let Some(auth_info) = req.headers.get("Authorization") {
-            log::debug!("auth_info: {:#?}", auth_info);
-            match auth::get_auth_type(auth_info) {
-                Some(auth::AuthType::Basic(client_auth)) => {
-                    let auth = &client_auth.unwrap();
-                    let res = username == auth.0 && password == auth.1;
-                    if !res {
-                        log::error!("auth failed");
-                        return ;
-                    }
-                },
-                Some(auth::AuthType::Digest(client_auth)) => {
-                    let auth = &client_auth.unwrap();
-                    log::debug!("auth: {:#?}", auth);
-                    let realm = "rust rtsp-server";
-                    let method = String::from(&req.method);
-                    let res = auth::validate_digest_response(auth, &method, realm, password);
-                    if !res {
-                        log::error!("auth failed");
-                        return ;
-                    }
-                }
-                None => todo!(),
+    /// 应答中的 CSeq, 请求没有 CSeq 时为 0. 认证之前的应答都要经过这里, 不能 panic.
+    fn cseq(req: &RtspRequest) -> String {
+        req.headers.get("CSeq").map_or("0", String::as_str).to_string()
+    }
+
+    pub fn error_response(req: &RtspRequest, status_code: &'static str) -> RtspResponse<'static> {
+        let mut headers: LinkedHashMap<&str, String> = LinkedHashMap::new();
+        headers.insert("CSeq", Router::cseq(req));
+        RtspResponse::new(status_code, Some(headers), None)
+    }
+
+    pub fn route(&self, req: RtspRequest, connect: &mut Connection) {
+        // 各 handler 都依赖 CSeq
+        if !req.headers.contains_key("CSeq") {
+            let _ = Router::error_response(&req, "400").send_response(&mut connect.get_stream());
+            return;
+        }
+        if req.method != request::Method::Options {
+            let peer = connect.stream.peer_addr().map(|addr| addr.ip()).unwrap_or(Ipv4Addr::UNSPECIFIED.into());
+            if let Err(denied) = self.check_access(&req, peer, &mut connect.token_path) {
+                let _ = self.denied_response(&req, denied).send_response(&mut connect.get_stream());
+                return ;
             }
-        } else if req.method != request::Method::Options {
-            // let auth = auth::BasicAuthenticator::new("rust rtsp-server");
-            let auth = auth::DigestAuthenticator::new("rust rtsp-server", None);
-            let resp = Router::unauthorized(&req, auth);
-            let mut stream = connect.get_stream();
-            let _ = resp.send_response(&mut stream);
-            return ;
         }
 
-        let mut stream = connect.get_stream(); 
+        if req.method == request::Method::Describe {
+            self.apply_mount_options(&req, connect);
+        }
+        let Some(mut handler) = Router::get_handler(&req.method, connect) else {
+            let _ = Router::error_response(&req, "501").send_response(&mut connect.get_stream());
+            return;
+        };
         let resp: RtspResponse = handler.handle(&req);
         log::debug!("resp: {:#?}", resp);
-        let _ = resp.send_response(&mut stream);
+        let _ = resp.send_response(&mut connect.get_stream());
+    }
+
+    /// 按挂载点配置调整会话, 在生成 SDP 之前调用.
+    fn apply_mount_options(&self, req: &RtspRequest, connect: &Connection) {
+        // 挂载点没有设置的项恢复默认值, 同一连接上一次 DESCRIBE 的设置不能沿用
+        let mount = self.mounts.find(req.path());
+        let mode = mount
+            .and_then(|mount| mount.packetization_mode)
+            .and_then(|mode| PacketizationMode::try_from(mode).ok())
+            .unwrap_or_default();
+        let rewrite = mount
+            .and_then(|mount| mount.sps)
+            .map(|sps| SpsRewrite { level_idc: sps.level_idc, frame_rate: sps.frame_rate, max_num_reorder_frames: sps.max_num_reorder_frames })
+            .unwrap_or_default();
+        let session = connect.get_session();
+        let mut session = session.lock().unwrap();
+        session.set_packetization_mode(mode);
+        session.set_sps_rewrite(rewrite);
+    }
+
+    fn denied_response(&self, req: &RtspRequest, denied: Denied) -> RtspResponse<'static> {
+        match denied {
+            Denied::NotFound => Router::error_response(req, "404"),
+            Denied::Forbidden => Router::error_response(req, "403"),
+            Denied::Unauthorized { stale } => Router::unauthorized(req, self.digest.challenge(stale)),
+            Denied::Locked { retry_after } => {
+                let mut resp_headers: LinkedHashMap<&str, String> = LinkedHashMap::new();
+                resp_headers.insert("CSeq", Router::cseq(req));
+                resp_headers.insert("Retry-After", retry_after.to_string());
+                RtspResponse::new("503", Some(resp_headers), None)
+            }
+        }
+    }
+
+    /// 查找挂载点, 认证并检查该用户对挂载点的权限. 拒绝时返回原因, 由 [`Router::route`] 生成应答.
+    fn check_access(&self, req: &RtspRequest, peer: IpAddr, token_path: &mut Option<String>) -> Result<(), Denied> {
+        let mount = if self.mounts.is_empty() {
+            None
+        } else {
+            match self.mounts.find(req.path()) {
+                Some(mount) => Some(mount),
+                None => {
+                    log::warn!("no mount for {}", req.path());
+                    return Err(Denied::NotFound);
+                }
+            }
+        };
+
+        let access = Access::of(req);
+        if let Some(mount) = mount {
+            if !self.mounts.reachable_from(mount, peer, access) {
+                log::warn!(target: "audit", "address denied: peer={} {:?} {}", peer, access, mount.path);
+                return Err(Denied::Forbidden);
+            }
+        }
+
+        let Some(provider) = &self.auth_provider else {
+            return Ok(());
+        };
+        if mount.is_some_and(|mount| self.mounts.allows_anonymous(mount, access)) {
+            return Ok(());
+        }
+
+        if access == Access::Read {
+            // 签名 url 只授予观看权限, 签名绑定挂载点路径 (未配置挂载点时为请求路径)
+            let signed_path = mount.map_or(req.path(), |mount| mount.path.as_str());
+            if let (Some(signer), Some(query)) = (&self.url_signer, req.query()) {
+                if token::parse_query(query).is_some() {
+                    return match signer.verify(signed_path, query, peer) {
+                        Ok(()) => {
+                            log::info!(target: "audit", "token accepted: path={} peer={}", signed_path, peer);
+                            *token_path = Some(signed_path.to_string());
+                            Ok(())
+                        }
+                        Err(e) => {
+                            log::warn!(target: "audit", "token rejected ({:?}): path={} peer={}", e, signed_path, peer);
+                            Err(Denied::Forbidden)
+                        }
+                    };
+                }
+            }
+            if token_path.as_deref().is_some_and(|path| mount::is_under(req.path(), path)) {
+                return Ok(());
+            }
+        }
+
+        let Some(auth_info) = req.headers.get("Authorization") else {
+            // let auth = auth::BasicAuthenticator::new(self.digest.realm()).generate();
+            return Err(Denied::Unauthorized { stale: false });
+        };
+        log::debug!("auth_info: {:#?}", auth_info);
+        let auth_type = auth::get_auth_type(auth_info);
+        let claimed = auth_type.as_ref().and_then(|auth| auth.username()).map(str::to_string);
+        let claimed = claimed.as_deref();
+        let user_agent = req.headers.get("User-Agent").map(String::as_str).unwrap_or("-");
+
+        if let Some(remaining) = self.login_guard.locked(peer, claimed) {
+            log::warn!(target: "audit", "auth rejected, locked for {}s: user={} peer={} user_agent={:?}",
+                remaining.as_secs(), claimed.unwrap_or("-"), peer, user_agent);
+            return Err(Denied::Locked { retry_after: remaining.as_secs() + 1 });
+        }
+
+        let username = match self.authenticate(auth_type, req, provider.as_ref()) {
+            Ok(username) => username,
+            Err(AuthError::StaleNonce) => {
+                return Err(Denied::Unauthorized { stale: true });
+            }
+            Err(e) => {
+                log::warn!(target: "audit", "auth failed ({:?}): user={} peer={} user_agent={:?}",
+                    e, claimed.unwrap_or("-"), peer, user_agent);
+                self.login_guard.record_failure(peer, claimed);
+                return Err(Denied::Unauthorized { stale: false });
+            }
+        };
+        log::info!(target: "audit", "auth succeeded: user={} peer={} user_agent={:?}", username, peer, user_agent);
+        self.login_guard.record_success(peer, &username);
+
+        if let Some(mount) = mount {
+            if !self.mounts.authorize(mount, &username, access) {
+                log::warn!(target: "audit", "access denied: user={} peer={} {:?} {}", username, peer, access, mount.path);
+                return Err(Denied::Forbidden);
+            }
+        }
+        Ok(())
+    }
+
+    /// 校验 Authorization 头, 成功时返回用户名.
+    fn authenticate(&self, auth_type: Option<auth::AuthType>, req: &RtspRequest, provider: &dyn AuthProvider) -> Result<String, AuthError> {
+        match auth_type {
+            Some(auth::AuthType::Basic(Some((username, password)))) => {
+                if auth::validate_basic_response(&username, &password, self.digest.realm(), provider) {
+                    Ok(username)
+                } else {
+                    Err(AuthError::InvalidCredentials)
+                }
+            },
+            Some(auth::AuthType::Digest(Some(auth))) => {
+                log::debug!("auth: {:#?}", auth);
+                let method = String::from(&req.method);
+                let Url::Path(url) = &req.url;
+                self.digest.validate(&auth, &method, url, provider)
+            }
+            _ => Err(AuthError::Malformed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requires_explicit_anonymous() {
+        assert_eq!(Router::new(&Config::default()).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+        let config: Config = "[server]\nauth = \"none\"".parse().unwrap();
+        assert!(Router::new(&config).is_ok());
+        let config: Config = "[server]\nauth = \"none\"\n[auth]\n[auth.users]\nadmin = \"123456\"".parse().unwrap();
+        assert!(Router::new(&config).is_err());
+        let config: Config = "[auth]\n[auth.users]\nadmin = \"123456\"".parse().unwrap();
+        assert!(Router::new(&config).is_ok());
+    }
+
+    #[test]
+    fn test_sps_frame_rate_range() {
+        let mount = |fps: u32| format!("[server]\nauth = \"none\"\n[[mounts]]\npath = \"/live\"\n[mounts.sps]\nframe_rate = {}", fps);
+        assert!(Router::new(&mount(30).parse().unwrap()).is_ok());
+        for fps in [0, MAX_SPS_FRAME_RATE + 1, u32::MAX] {
+            assert_eq!(Router::new(&mount(fps).parse().unwrap()).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+        }
+    }
+
+    #[test]
+    fn test_error_response_without_cseq() {
+        let req: RtspRequest = "DESCRIBE rtsp://localhost/live RTSP/1.0\r\n\r\n".to_string().into();
+        let mut bytes = Vec::new();
+        Router::error_response(&req, "400").send_response(&mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.starts_with("RTSP/1.0 400 Bad Request\r\n"));
+        assert!(text.contains("CSeq: 0\r\n"));
     }
-}
\ No newline at end of file
+}
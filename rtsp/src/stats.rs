@@ -0,0 +1,76 @@
+use std::{collections::HashMap, net::SocketAddr, sync::{Arc, Mutex}};
+
+use media::rtp::qos::QosStats;
+
+/// 一个客户端会话的接收质量快照.
+#[derive(Debug, Clone)]
+pub struct ClientStats {
+    pub session_id: String,
+    pub peer: SocketAddr,
+    pub qos: QosStats,
+}
+
+/// 会话 id -> (客户端地址, 会话共享的 QoS 统计).
+type ClientMap = HashMap<String, (SocketAddr, Arc<Mutex<QosStats>>)>;
+
+/// 当前在线客户端的 QoS 统计, 连接建立时登记, 断开时移除.
+#[derive(Default)]
+pub struct StatsRegistry {
+    clients: Mutex<ClientMap>,
+}
+
+impl StatsRegistry {
+    pub fn register(&self, session_id: &str, peer: SocketAddr, qos: Arc<Mutex<QosStats>>) {
+        self.clients.lock().unwrap().insert(session_id.to_string(), (peer, qos));
+    }
+
+    pub fn unregister(&self, session_id: &str) {
+        self.clients.lock().unwrap().remove(session_id);
+    }
+
+    /// 查询单个会话的统计, 服务器在会话结束时用它输出质量摘要.
+    pub fn get(&self, session_id: &str) -> Option<ClientStats> {
+        let clients = self.clients.lock().unwrap();
+        let (peer, qos) = clients.get(session_id)?;
+        let qos = qos.lock().unwrap().clone();
+        Some(ClientStats {
+            session_id: session_id.to_string(),
+            peer: *peer,
+            qos,
+        })
+    }
+
+    /// 所有在线会话的统计, 供嵌入本库的程序做监控导出.
+    pub fn snapshot(&self) -> Vec<ClientStats> {
+        let clients = self.clients.lock().unwrap();
+        clients
+            .iter()
+            .map(|(session_id, (peer, qos))| ClientStats {
+                session_id: session_id.clone(),
+                peer: *peer,
+                qos: qos.lock().unwrap().clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let registry = StatsRegistry::default();
+        let qos = Arc::new(Mutex::new(QosStats::default()));
+        registry.register("1234", "127.0.0.1:5000".parse().unwrap(), Arc::clone(&qos));
+        qos.lock().unwrap().cumulative_lost = 7;
+
+        let stats = registry.get("1234").unwrap();
+        assert_eq!(stats.qos.cumulative_lost, 7);
+        assert_eq!(registry.snapshot().len(), 1);
+
+        registry.unregister("1234");
+        assert!(registry.get("1234").is_none());
+        assert!(registry.snapshot().is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+use std::{fs::File, io::{self, BufReader, Read, Write}, net::{SocketAddr, TcpStream}, path::Path, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use rustls::{ServerConfig, ServerConnection};
+
+use crate::connection::Stream;
+
+/// 握手的总时长上限, 不完成握手的客户端不能一直占用监听线程.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// rtsps:// 监听使用的 TLS 握手器, 持有证书和私钥.
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    config: Arc<ServerConfig>,
+    handshake_timeout: Duration,
+}
+
+impl TlsAcceptor {
+    /// 从 PEM 格式的证书链和私钥文件创建.
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Self> {
+        let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(key)?))?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no private key found"))?;
+
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self { config: Arc::new(config), handshake_timeout: HANDSHAKE_TIMEOUT })
+    }
+
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// 在已建立的 tcp 连接上完成 TLS 握手, 超过 `handshake_timeout` 未完成时返回 TimedOut.
+    pub fn accept(&self, sock: TcpStream) -> io::Result<TlsStream> {
+        let mut conn = ServerConnection::new(Arc::clone(&self.config))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        // 每次读写的超时取剩余时间, 逐字节发送的客户端也会在截止时间断开
+        let deadline = Instant::now() + self.handshake_timeout;
+        while conn.is_handshaking() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "tls handshake timed out"));
+            }
+            sock.set_read_timeout(Some(remaining))?;
+            sock.set_write_timeout(Some(remaining))?;
+            match conn.complete_io(&mut &sock) {
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "tls handshake timed out"));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        sock.set_read_timeout(None)?;
+        sock.set_write_timeout(None)?;
+        Ok(TlsStream {
+            sock,
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+}
+
+/// TLS 之上的 rtsp 连接.
+///
+/// rtsp 控制线程阻塞读的同时, rtp 线程要在同一条连接上发送 interleaved 数据,
+/// 所以 TLS 状态放在锁里共享, 读端只在 socket 上有数据后才加锁解密.
+pub struct TlsStream {
+    sock: TcpStream,
+    conn: Arc<Mutex<ServerConnection>>,
+}
+
+impl TlsStream {
+    fn flush_tls(conn: &mut ServerConnection, mut sock: &TcpStream) -> io::Result<()> {
+        while conn.wants_write() {
+            conn.write_tls(&mut sock)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            {
+                let mut conn = self.conn.lock().unwrap();
+                match conn.reader().read(buf) {
+                    Ok(n) => return Ok(n),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e),
+                }
+            }
+
+            // 不持锁等待密文到达, 避免阻塞 rtp 发送.
+            if self.sock.peek(&mut [0u8; 1])? == 0 {
+                return Ok(0);
+            }
+
+            let mut conn = self.conn.lock().unwrap();
+            if conn.read_tls(&mut &self.sock)? == 0 {
+                return Ok(0);
+            }
+            if let Err(e) = conn.process_new_packets() {
+                // 尽量把 alert 发给对端
+                let _ = Self::flush_tls(&mut conn, &self.sock);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+            Self::flush_tls(&mut conn, &self.sock)?;
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let n = conn.writer().write(buf)?;
+        Self::flush_tls(&mut conn, &self.sock)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        conn.writer().flush()?;
+        Self::flush_tls(&mut conn, &self.sock)
+    }
+}
+
+impl Stream for TlsStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.peer_addr()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.local_addr()
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(TlsStream {
+            sock: self.sock.try_clone()?,
+            conn: Arc::clone(&self.conn),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::{BufRead, BufReader}, net::TcpListener, path::PathBuf, thread};
+    use rustls::{pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+    /// 为 localhost 生成自签名证书, 返回 (证书路径, 私钥路径, DER 证书).
+    fn self_signed_cert(name: &str) -> (PathBuf, PathBuf, Vec<u8>) {
+        let rcgen::CertifiedKey { cert, key_pair } =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir().join(format!("rtsps-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key_pair.serialize_pem()).unwrap();
+        (cert_path, key_path, cert.der().to_vec())
+    }
+
+    #[test]
+    fn test_tls_round_trip() {
+        let (cert_path, key_path, cert_der) = self_signed_cert("round-trip");
+        let acceptor = TlsAcceptor::from_pem_files(&cert_path, &key_path).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let mut stream: Box<dyn Stream> = Box::new(acceptor.accept(sock).unwrap());
+            let mut writer = stream.try_clone().unwrap();
+
+            let mut line = String::new();
+            BufReader::new(&mut stream).read_line(&mut line).unwrap();
+            assert_eq!(line, "OPTIONS rtsps://localhost/live RTSP/1.0\r\n");
+
+            // 控制应答和 interleaved 数据走同一条 TLS 连接
+            writer.write_all(b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\n").unwrap();
+            writer.write_all(&[0x24, 0, 0, 2, 0xab, 0xcd]).unwrap();
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert_der.into()).unwrap();
+        let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+        let conn = ClientConnection::new(Arc::new(config), ServerName::try_from("localhost").unwrap()).unwrap();
+        let mut client = StreamOwned::new(conn, TcpStream::connect(addr).unwrap());
+        client.write_all(b"OPTIONS rtsps://localhost/live RTSP/1.0\r\n").unwrap();
+
+        let mut reader = BufReader::new(client);
+        let mut status = String::new();
+        reader.read_line(&mut status).unwrap();
+        assert_eq!(status, "RTSP/1.0 200 OK\r\n");
+        let mut rest = [0u8; 17];
+        reader.read_exact(&mut rest).unwrap();
+        assert_eq!(&rest[11..], &[0x24, 0, 0, 2, 0xab, 0xcd]);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_handshake_timeout() {
+        let (cert_path, key_path, _) = self_signed_cert("handshake-timeout");
+        let acceptor = TlsAcceptor::from_pem_files(&cert_path, &key_path)
+            .unwrap()
+            .with_handshake_timeout(Duration::from_millis(200));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        // 连上后不发送 ClientHello
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (sock, _) = listener.accept().unwrap();
+
+        let start = Instant::now();
+        let err = acceptor.accept(sock).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,111 @@
+use std::net::IpAddr;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::auth::{hex_decode, hex_encode, now_secs};
+
+/// 签名 url 校验失败的原因.
+#[derive(Debug, PartialEq)]
+pub enum TokenError {
+    Malformed,        // 缺少 token/expires 或格式不对
+    Expired,
+    InvalidSignature, // 路径或客户端 ip 不匹配, 或者不是本服务器签发的
+}
+
+/// 临时观看链接: `rtsp://host/live?token=...&expires=...`.
+///
+/// token = HMAC-SHA256(secret, "路径\n过期时间\n客户端 ip"), 路径为挂载点路径,
+/// 不绑定 ip 时 ip 部分为空.
+pub struct UrlSigner {
+    secret: Vec<u8>,
+}
+
+impl UrlSigner {
+    pub fn new(secret: &str) -> Self {
+        Self { secret: secret.as_bytes().to_vec() }
+    }
+
+    fn mac(&self, path: &str, expires: u64, ip: Option<IpAddr>) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("hmac accepts any key size");
+        let ip = ip.map(|ip| ip.to_string()).unwrap_or_default();
+        mac.update(format!("{}\n{}\n{}", path, expires, ip).as_bytes());
+        mac
+    }
+
+    /// 生成 `path` 在 `expires` (unix 时间, 秒) 之前有效的查询串, 不带开头的 '?'.
+    pub fn sign(&self, path: &str, expires: u64, ip: Option<IpAddr>) -> String {
+        let token = hex_encode(&self.mac(path, expires, ip).finalize().into_bytes());
+        format!("token={}&expires={}", token, expires)
+    }
+
+    /// 校验请求的查询串是否为 `path` 签发且未过期; 绑定了 ip 的 token 只对该客户端有效.
+    pub fn verify(&self, path: &str, query: &str, peer: IpAddr) -> Result<(), TokenError> {
+        self.verify_at(path, query, peer, now_secs())
+    }
+
+    fn verify_at(&self, path: &str, query: &str, peer: IpAddr, now: u64) -> Result<(), TokenError> {
+        let (token, expires) = parse_query(query).ok_or(TokenError::Malformed)?;
+        let token = hex_decode(token).ok_or(TokenError::Malformed)?;
+        let expires: u64 = expires.parse().map_err(|_| TokenError::Malformed)?;
+
+        let signed = [Some(peer), None]
+            .into_iter()
+            .any(|ip| self.mac(path, expires, ip).verify_slice(&token).is_ok());
+        if !signed {
+            return Err(TokenError::InvalidSignature);
+        }
+        if now >= expires {
+            return Err(TokenError::Expired);
+        }
+        Ok(())
+    }
+}
+
+/// 从查询串中取出 (token, expires).
+pub fn parse_query(query: &str) -> Option<(&str, &str)> {
+    let param = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    };
+    Some((param("token")?, param("expires")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = UrlSigner::new("s3cret");
+        let client: IpAddr = "192.168.1.20".parse().unwrap();
+        let other: IpAddr = "192.168.1.21".parse().unwrap();
+
+        let query = signer.sign("/live", 1_000, None);
+        assert_eq!(signer.verify_at("/live", &query, client, 999), Ok(()));
+        assert_eq!(signer.verify_at("/live", &query, other, 999), Ok(()));
+        assert_eq!(signer.verify_at("/live", &query, client, 1_000), Err(TokenError::Expired));
+        assert_eq!(signer.verify_at("/public", &query, client, 999), Err(TokenError::InvalidSignature));
+        assert_eq!(UrlSigner::new("other").verify_at("/live", &query, client, 999), Err(TokenError::InvalidSignature));
+
+        // 改过期时间会使签名失效
+        let forged = query.replace("expires=1000", "expires=2000");
+        assert_eq!(signer.verify_at("/live", &forged, client, 1_500), Err(TokenError::InvalidSignature));
+
+        let query = signer.sign("/live", 1_000, Some(client));
+        assert_eq!(signer.verify_at("/live", &query, client, 999), Ok(()));
+        assert_eq!(signer.verify_at("/live", &query, other, 999), Err(TokenError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_malformed_query() {
+        let signer = UrlSigner::new("s3cret");
+        let peer: IpAddr = "::1".parse().unwrap();
+        assert_eq!(signer.verify_at("/live", "", peer, 0), Err(TokenError::Malformed));
+        assert_eq!(signer.verify_at("/live", "token=zz&expires=10", peer, 0), Err(TokenError::Malformed));
+        assert_eq!(signer.verify_at("/live", "token=00&expires=soon", peer, 0), Err(TokenError::Malformed));
+        assert_eq!(parse_query("a=1&expires=10&token=ab"), Some(("ab", "10")));
+    }
+}
@@ -1,97 +1,290 @@
 #![feature(write_all_vectored)]
 use std::{
     io::{
-        BufRead, BufReader, Write
+        BufReader, Write
     }, net::{
-        IpAddr, TcpListener, TcpStream, UdpSocket
-    }, sync::{Arc, Mutex}, thread, time::Duration};
-use rtsp::{connection::Connection, request::RtspRequest};
-use media::session::{Session, Track};
+        IpAddr, Ipv4Addr, TcpListener, TcpStream, UdpSocket
+    }, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread, time::{Duration, SystemTime, UNIX_EPOCH}};
+use rtsp::{acl::IpFilter, config::{Config, LatePolicy, Retransmit, RtpExtension, DEFAULT_RTSPS_PORT, DEFAULT_RTSP_PORT}, connection::{read_message, Connection, Message, Stream}, request::RtspRequest, tls::TlsAcceptor, token::UrlSigner};
+use media::{codec::probe::{self, ProbeError}, rtp::{extension::ExtensionKind, pacer}, session::{Session, Track}};
 use rtsp::router::Router;
+
+/// 每个会话的 rtp 发送选项, 来自 [server] 配置.
+struct MediaOptions {
+    retransmit: Retransmit,
+    fec: Option<u8>,
+    extensions: Vec<ExtensionKind>,
+    late_policy: pacer::LatePolicy,
+}
+
+impl MediaOptions {
+    fn from_config(config: &Config) -> Self {
+        let extensions = config.server.extensions.iter().map(|extension| match extension {
+            RtpExtension::AbsSendTime => ExtensionKind::AbsSendTime,
+            RtpExtension::AbsCaptureTime => ExtensionKind::AbsCaptureTime,
+            RtpExtension::FrameMarking => ExtensionKind::FrameMarking,
+        }).collect();
+        Self {
+            retransmit: config.server.retransmit,
+            fec: config.server.fec,
+            extensions,
+            late_policy: match config.server.late_policy {
+                LatePolicy::CatchUp => pacer::LatePolicy::CatchUp,
+                LatePolicy::Skip => pacer::LatePolicy::Skip,
+            },
+        }
+    }
+
+    fn apply(&self, session: &mut Session) {
+        match self.retransmit {
+            Retransmit::Off => {}
+            Retransmit::InStream => session.enable_retransmission(false),
+            Retransmit::Rtx => session.enable_retransmission(true),
+        }
+        if let Some(group_size) = self.fec {
+            session.enable_fec(group_size);
+        }
+        session.enable_header_extensions(&self.extensions);
+        session.set_late_policy(self.late_policy);
+    }
+}
+
 struct Server<'a> {
     socket_addr: &'a str,
     vedio_file: Arc<String>,
+    ip_filter: IpFilter,
+    media: MediaOptions,
+    tls: Option<(&'a str, TlsAcceptor, IpFilter)>, // rtsps 监听地址, 证书和来源地址限制
+    router: Arc<Router>,
 }
 impl<'a> Server<'a> {
-    fn new(socket_addr: &'a str, stream_file: Arc<String>) -> Self{
+    fn new(socket_addr: &'a str, stream_file: Arc<String>, ip_filter: IpFilter, media: MediaOptions, tls: Option<(&'a str, TlsAcceptor, IpFilter)>, router: Router) -> Self{
         Server{
             socket_addr,
             vedio_file: stream_file,
+            ip_filter,
+            media,
+            tls,
+            router: Arc::new(router),
         }
     }
-    fn handle_client(mut connection: Connection) {
-        // 读取客户端请求
+    fn handle_client(mut connection: Connection, router: &Router) {
+        let mut reader = BufReader::new(connection.stream.try_clone().unwrap());
+        let session_id = connection.session.lock().unwrap().session_id.clone();
+        if let Ok(peer) = connection.stream.peer_addr() {
+            router.stats().register(&session_id, peer, connection.session.lock().unwrap().qos());
+        }
+
+        // 读取客户端请求, 以及 interleaved 方式发来的 rtcp
         loop {
-            let buf_reader: BufReader<&mut TcpStream> = BufReader::new(&mut connection.stream);
-            let rtsp_request: Vec<_> = buf_reader
-                .lines()
-                .map(|result| result.unwrap())
-                .take_while(|line| !line.is_empty())
-                .collect();
-            if rtsp_request.is_empty() {
-                // log::warn!("receive nothing, close the client {} right now.", stream.peer_addr().unwrap());
-                // break;
-                continue;
+            match read_message(&mut reader) {
+                Ok(Some(Message::Request(rtsp_request))) => {
+                    log::info!("req: {:#?}", rtsp_request);
+                    let req: RtspRequest = rtsp_request.into();
+                    router.route(req, &mut connection);
+                }
+                Ok(Some(Message::Interleaved { channel, data })) => {
+                    let session = connection.session.lock().unwrap();
+                    if session.is_rtcp_channel(channel) {
+                        session.on_rtcp(&data);
+                    }
+                }
+                Ok(None) => {
+                    log::info!("client {:?} closed the connection", connection.stream.peer_addr());
+                    break;
+                }
+                Err(e) => {
+                    log::warn!("read from client {:?} failed: {}", connection.stream.peer_addr(), e);
+                    break;
+                }
+            }
+        }
+
+        if let Some(stats) = router.stats().get(&session_id) {
+            let qos = stats.qos;
+            log::info!(
+                "session {} ({}) qos: lost {} ({:.1}%), jitter {:?}, rtt {:?}, nacks {}, keyframe requests {}",
+                stats.session_id, stats.peer, qos.cumulative_lost, qos.fraction_lost * 100.0,
+                qos.jitter, qos.rtt, qos.nacks, qos.keyframe_requests
+            );
+        }
+        router.stats().unregister(&session_id);
+        connection.session.lock().unwrap().teardown();
+    }
+
+    /// 明文和 TLS 连接走同一套处理流程.
+    fn serve(&self, stream: Box<dyn Stream>) {
+        // let audio_file = Some("media/audio.aac");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let video_file = Arc::clone(&self.vedio_file);
+        let mut session = match Session::new("session", Some(video_file), None, tx) {
+            Ok(session) => session,
+            Err(e) => {
+                log::error!("open video file {} failed: {}", self.vedio_file, e);
+                let status = if matches!(e, ProbeError::Io(_)) { "500" } else { "415" };
+                Server::reject_client(stream, status);
+                return;
+            }
+        };
+        self.media.apply(&mut session);
+        let session = Arc::new(Mutex::new(session));
+        // 为每个连接创建一个新的线程
+        let session_clone = Arc::clone(&session);
+        let stream_clone = stream.try_clone().unwrap();
+        let router = Arc::clone(&self.router);
+        let handle_connect = thread::spawn( move || {
+            let connection = Connection::new(stream, session_clone);
+            Server::handle_client(connection, &router);
+        });
+        
+        let session_clone = Arc::clone(&session);
+        let handle_rtp = thread::spawn( move || {
+            let is_play: Option<bool> = rx.recv().ok();
+            if let Some(is_play) = is_play {
+                if !is_play {
+                    log::info!("stop play");
+                    return;
+                }
+                log::info!("start play");
+            }
+            
+            let (rtp_sink, mut rtp_sender, rtcp_receiver) = {
+                let mut session = session_clone.lock().unwrap();
+                let peer = stream_clone.peer_addr().map(|addr| addr.ip()).unwrap_or(Ipv4Addr::UNSPECIFIED.into());
+                let rtp_sink = Arc::clone(session.get_rtp_sink(Track::Video));
+                let rtcp_receiver = session.udp_sockets(Track::Video)
+                    .zip(session.client_rtcp_addr(Track::Video, peer))
+                    .map(|(sockets, client)| (sockets, client, session.rtcp_handler()));
+                (rtp_sink, session.rtp_sender(Track::Video, stream_clone, peer), rtcp_receiver)
+            };
+
+            // udp 传输时客户端的 rtcp 发到服务端的 rtcp 端口
+            let stopped = Arc::new(AtomicBool::new(false));
+            if let Some((sockets, client, rtcp_handler)) = rtcp_receiver {
+                let stopped = Arc::clone(&stopped);
+                thread::spawn(move || {
+                    let mut buf = [0u8; 1500];
+                    let _ = sockets.rtcp.set_read_timeout(Some(Duration::from_secs(1)));
+                    while !stopped.load(Ordering::Relaxed) {
+                        match sockets.rtcp.recv_from(&mut buf) {
+                            Ok((n, from)) if from == client => rtcp_handler.handle(&buf[..n]),
+                            // 未连接的 socket 收得到任意来源的包, 伪造的 NACK 会引发重传
+                            Ok((_, from)) => log::debug!("drop rtcp from {}, expected {}", from, client),
+                            Err(_) => {}
+                        }
+                    }
+                });
+            }
+            
+            let nalu_iter = rtp_sink.lock().unwrap().get_nalu_iter();
+            // 发送节奏由 rtp_sender 按时间戳控制, 这里只检查是否停止
+            for nalu in nalu_iter {
+                if let Ok(false) = rx.try_recv() {
+                    break;
+                }
+
+                if let Err(e) = rtp_sink.lock().unwrap().handle(&nalu, &mut rtp_sender) {
+                    log::warn!("send rtp failed: {}", e);
+                    break;
+                }
+            }
+            // 最后一个访问单元在读完文件后才能确定结束
+            if let Err(e) = rtp_sink.lock().unwrap().end_of_stream(&mut rtp_sender) {
+                log::debug!("send last access unit failed: {}", e);
             }
-            let req: RtspRequest = rtsp_request.join("\r\n").into();
-            log::info!("req: {:#?}", rtsp_request);
-            Router::route(req, &mut connection);
+            stopped.store(true, Ordering::Relaxed);
+        });
+
+        // 单个连接的线程 panic 不能让监听线程退出
+        if handle_connect.join().is_err() {
+            log::error!("rtsp connection thread panicked");
+        }
+        if handle_rtp.join().is_err() {
+            log::error!("rtp thread panicked");
         }
     }
     
+    /// 会话无法创建时, 对客户端的每个请求都回复 `status`, 直到连接关闭.
+    fn reject_client(mut stream: Box<dyn Stream>, status: &'static str) {
+        let Ok(read_stream) = stream.try_clone() else {
+            return;
+        };
+        let mut reader = BufReader::new(read_stream);
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if let Message::Request(rtsp_request) = message {
+                let req: RtspRequest = rtsp_request.into();
+                if Router::error_response(&req, status).send_response(&mut stream).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// 在握手和读取请求之前按来源地址过滤连接.
+    fn permits(stream: &TcpStream, ip_filter: &IpFilter) -> bool {
+        match stream.peer_addr() {
+            Ok(addr) if ip_filter.permits(addr.ip()) => true,
+            Ok(addr) => {
+                log::warn!(target: "audit", "connection from {} refused by ip filter", addr);
+                false
+            }
+            Err(_) => false,
+        }
+    }
+
     fn run(&self) {
-        let listener = TcpListener::bind(self.socket_addr).unwrap();
-        for stream in listener.incoming(){
-            match stream {
-                Ok(stream) => {
-                    // let audio_file = Some("media/audio.aac");
-                    let (tx, rx) = std::sync::mpsc::channel();
-                    let video_file = Arc::clone(&self.vedio_file);
-                    let session = Arc::new(Mutex::new(Session::new("session", Some(video_file), None, tx)));
-                    // 为每个连接创建一个新的线程
-                    let session_clone = Arc::clone(&session);
-                    let stream_clone = stream.try_clone().unwrap();
-                    let handle_connect = thread::spawn( move || {
-                        let connection = Connection::new(stream, session_clone);
-                        Server::handle_client(connection);
-                    });
-                    
-                    let session_clone = Arc::clone(&session);
-                    let handle_rtp = thread::spawn( move || {
-                        let is_play: Option<bool> = rx.recv().ok();
-                        if let Some(is_play) = is_play {
-                            if !is_play {
-                                log::info!("stop play");
-                                return;
-                            }
-                            log::info!("start play");
+        thread::scope(|s| {
+            if let Some((tls_addr, acceptor, ip_filter)) = &self.tls {
+                s.spawn(move || {
+                    let listener = TcpListener::bind(tls_addr).unwrap();
+                    for stream in listener.incoming() {
+                        if stream.as_ref().is_ok_and(|stream| !Server::permits(stream, ip_filter)) {
+                            continue;
                         }
-                        
-                        let mut session = session_clone.lock().unwrap();
-                        let rtp_sink = Arc::clone(session.get_rtp_sink(Track::Video));
-                        
-                        let nalu_iter = rtp_sink.lock().unwrap().get_nalu_iter();
-                        for nalu in nalu_iter {
-                            if let Some(play) = rx.recv_timeout(Duration::from_millis(40)).ok() {
-                                if !play {
-                                    break;
-                                }
+                        match stream.and_then(|stream| acceptor.accept(stream)) {
+                            Ok(stream) => self.serve(Box::new(stream)),
+                            Err(e) => {
+                                log::error!("rtsps connection failed: {}", e);
                             }
-                            
-                            rtp_sink.lock().unwrap().handle(&nalu, Box::new(stream_clone.try_clone().unwrap()));
                         }
-                    });
+                    }
+                });
+            }
 
-                    handle_connect.join().unwrap();
-                    handle_rtp.join().unwrap();
-                }
-                Err(e) => {
-                    eprintln!("Connection failed: {}", e);
+            let listener = TcpListener::bind(self.socket_addr).unwrap();
+            for stream in listener.incoming(){
+                match stream {
+                    Ok(stream) if !Server::permits(&stream, &self.ip_filter) => {}
+                    Ok(stream) => self.serve(Box::new(stream)),
+                    Err(e) => {
+                        eprintln!("Connection failed: {}", e);
+                    }
                 }
             }
-        }
+        });
     }
 }
+fn sign_url(config: &Config, args: &[String]) {
+    let Some(token) = config.auth.as_ref().and_then(|auth| auth.token.as_ref()) else {
+        log::error!("no [auth.token] configured");
+        return;
+    };
+    let (Some(path), Some(Ok(ttl))) = (args.first(), args.get(1).map(|ttl| ttl.parse::<u64>())) else {
+        log::error!("usage: sign <path> <ttl-secs> [client-ip]");
+        return;
+    };
+    let ip = match args.get(2).map(|ip| ip.parse::<IpAddr>()) {
+        Some(Ok(ip)) => Some(ip),
+        Some(Err(e)) => {
+            log::error!("invalid client ip: {}", e);
+            return;
+        }
+        None => None,
+    };
+    let expires = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + ttl;
+    println!("{}?{}", path, UrlSigner::new(&token.secret).sign(path, expires, ip));
+}
+
 fn main() {
     let env = env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info");
     let mut builder = env_logger::Builder::from_env(env);
@@ -120,14 +313,68 @@ fn main() {
         let local_addr = socket.local_addr().ok()?;
         Some(local_addr.ip())
     };
+    let config = match std::env::args().nth(1) {
+        Some(path) => match Config::load(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("load config {} failed: {}", path, e);
+                return;
+            }
+        },
+        None => Config::default(),
+    };
+    // miniRtspServer <配置文件> sign <路径> <有效期(秒)> [客户端 ip]: 生成临时观看链接的查询串
+    if std::env::args().nth(2).as_deref() == Some("sign") {
+        sign_url(&config, &std::env::args().skip(3).collect::<Vec<_>>());
+        return;
+    }
     match get_local_ip() {
         Some(ip) => {
-            let ip_with_port = format!("{}:5544", ip);
-            let home_dir = std::env::var("HOME").expect("HOME not set");
-            let relative_path = "coder/rust/miniRtspServer/test.h265";
-            let stream_file = format!("{}/{}", home_dir, relative_path);
+            let ip_with_port = config.server.listen.clone()
+                .unwrap_or_else(|| format!("{}:{}", ip, DEFAULT_RTSP_PORT));
+            let stream_file = config.server.video_file.clone().unwrap_or_else(|| {
+                let home_dir = std::env::var("HOME").expect("HOME not set");
+                let relative_path = "coder/rust/miniRtspServer/test.h265";
+                format!("{}/{}", home_dir, relative_path)
+            });
+            match probe::probe_file(&stream_file) {
+                Ok(info) => log::info!("{}: {} profile {} level {}, {}x{}, {:?} fps", stream_file, info.codec, info.profile_idc, info.level_idc, info.width, info.height, info.frame_rate),
+                Err(e) => {
+                    log::error!("unsupported video file {}: {}", stream_file, e);
+                    return;
+                }
+            }
             let video_file = Arc::new(stream_file);
-            let server = Server::new(&ip_with_port, video_file);
+
+            let tls_addr = config.tls.as_ref().map(|tls| {
+                tls.listen.clone().unwrap_or_else(|| format!("{}:{}", ip, DEFAULT_RTSPS_PORT))
+            });
+            let tls = match (&config.tls, &tls_addr) {
+                (Some(tls), Some(addr)) => match TlsAcceptor::from_pem_files(&tls.cert, &tls.key) {
+                    Ok(acceptor) => {
+                        log::info!("Listening on rtsps://{}", addr);
+                        Some((addr.as_str(), acceptor, tls.ip.clone()))
+                    }
+                    Err(e) => {
+                        log::error!("load tls cert {:?} / key {:?} failed: {}", tls.cert, tls.key, e);
+                        return;
+                    }
+                },
+                _ => None,
+            };
+
+            let router = match Router::new(&config) {
+                Ok(router) => router,
+                Err(e) => {
+                    log::error!("invalid auth config: {}", e);
+                    return;
+                }
+            };
+            if config.auth.is_none() {
+                log::warn!("auth = \"none\", authentication disabled");
+            }
+
+            let server = Server::new(&ip_with_port, video_file, config.server.ip.clone(), MediaOptions::from_config(&config), tls, router);
             log::info!("Listening on {}", ip_with_port);
             server.run();
         }